@@ -0,0 +1,83 @@
+use std::io;
+
+
+/// A lookup table for a canonical Huffman code, read most significant bit first.
+pub struct Huffman {
+    table: Vec<u32>,    // symbol << 8 | length, 0 for unused codes
+    bits: u32,
+}
+
+impl Huffman {
+
+    /// Build the table from the code length of each symbol, 0 for unused symbols.
+    ///
+    /// Codes are assigned in order of length, then symbol.  An incomplete code is accepted, but
+    /// decoding an unassigned code is an error.
+    pub fn new(lengths: &[u8], bits: u32) -> io::Result<Self> {
+        let mut table = vec![0; 1 << bits];
+        let mut code: u32 = 0;
+        for length in 1..=bits {
+            for (symbol, _) in lengths.iter().enumerate().filter(|(_, &l)| l as u32 == length) {
+                let count = 1 << (bits - length);
+                let start = (code as usize) << (bits - length);
+                if start + count > table.len() {
+                    eprintln!("ERROR: Huffman code is over-subscribed");
+                    return Err(io::Error::from(io::ErrorKind::InvalidData));
+                }
+                for entry in &mut table[start..start + count] {
+                    *entry = (symbol as u32) << 8 | length;
+                }
+                code += 1;
+            }
+            code <<= 1;
+        }
+        if lengths.iter().any(|&l| l as u32 > bits) {
+            eprintln!("ERROR: Huffman code is longer than {} bits", bits);
+            return Err(io::Error::from(io::ErrorKind::InvalidData));
+        }
+        Ok(Self { table: table, bits: bits })
+    }
+
+    /// Decode the symbol at the start of the next `bits` bits of input, returning it and its length.
+    pub fn decode(&self, peek: u32) -> io::Result<(u16, u32)> {
+        match self.table[(peek & ((1 << self.bits) - 1)) as usize] {
+            0 => {
+                eprintln!("ERROR: Invalid Huffman code");
+                Err(io::Error::from(io::ErrorKind::InvalidData))
+            }
+            entry => Ok(((entry >> 8) as u16, entry & 0xFF)),
+        }
+    }
+
+    /// Get the length of the longest code.
+    pub fn bits(&self) -> u32 { self.bits }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_codes() {
+        // symbol 1 is 0, symbol 0 is 10, symbols 2 and 3 are 110 and 111
+        let code = Huffman::new(&[2, 1, 3, 3, 0], 4).unwrap();
+        assert_eq!(code.bits(), 4);
+        assert_eq!(code.decode(0b0000).unwrap(), (1, 1));
+        assert_eq!(code.decode(0b0111).unwrap(), (1, 1));
+        assert_eq!(code.decode(0b1011).unwrap(), (0, 2));
+        assert_eq!(code.decode(0b1100).unwrap(), (2, 3));
+        assert_eq!(code.decode(0b1111).unwrap(), (3, 3));
+    }
+
+    #[test]
+    fn invalid_codes() {
+        assert!(Huffman::new(&[1, 1, 1], 4).is_err());
+        assert!(Huffman::new(&[1, 5], 4).is_err());
+
+        // an incomplete code is accepted, but its unassigned codes are not
+        let code = Huffman::new(&[1, 0, 2], 4).unwrap();
+        assert_eq!(code.decode(0b1000).unwrap(), (2, 2));
+        assert!(code.decode(0b1100).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use std::io;
+
+
+const CHUNK_SIZE: usize = 4096;
+
+const CHUNK_SIZE_MASK: u16 = 0x0FFF;       // compressed size - 3, including the header
+const CHUNK_SIGNATURE_MASK: u16 = 0x7000;
+const CHUNK_SIGNATURE: u16 = 0x3000;
+const CHUNK_COMPRESSED: u16 = 0x8000;
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Decompress an LZNT1 chunk into `output`, returning the number of bytes written.
+fn decompress_chunk(data: &[u8], output: &mut [u8]) -> io::Result<usize> {
+    let mut pos = 0;
+    let mut out = 0;
+    while pos < data.len() {
+        let flags = data[pos];
+        pos += 1;
+        for bit in 0..8 {
+            if pos >= data.len() {
+                break;
+            }
+            if flags & (1 << bit) == 0 {
+                if out >= output.len() {
+                    return invalid("LZNT1 chunk is too large");
+                }
+                output[out] = data[pos];
+                out += 1;
+                pos += 1;
+                continue;
+            }
+
+            if pos + 2 > data.len() {
+                return invalid("LZNT1 back reference is truncated");
+            }
+            let token = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+            pos += 2;
+
+            // the further into the chunk, the more bits are used for the displacement
+            let mut length_bits = 12;
+            let mut i = out.wrapping_sub(1);
+            while out > 0 && i >= 0x10 {
+                length_bits -= 1;
+                i >>= 1;
+            }
+            let distance = (token >> length_bits) + 1;
+            let length = (token & ((1 << length_bits) - 1)) + 3;
+            if out == 0 || distance > out || out + length > output.len() {
+                return invalid(&format!("Invalid LZNT1 back reference: {} x {} at {}", distance, length, out));
+            }
+            // the source may overlap what is being written
+            for _ in 0..length {
+                output[out] = output[out - distance];
+                out += 1;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Decompress LZNT1 data, as used by NTFS compression units.
+///
+/// Each chunk expands to 4096 bytes; a short chunk is padded with zeros.  Decompression stops
+/// at an empty chunk header, the end of `data` or the end of `output`.  Returns the number of
+/// bytes written.
+pub fn lznt1_decompress(data: &[u8], output: &mut [u8]) -> io::Result<usize> {
+    let mut pos = 0;
+    let mut out = 0;
+    while pos + 2 <= data.len() && out < output.len() {
+        let header = u16::from_le_bytes([data[pos], data[pos + 1]]);
+        if header == 0 {
+            break;
+        }
+        if header & CHUNK_SIGNATURE_MASK != CHUNK_SIGNATURE {
+            return invalid(&format!("Invalid LZNT1 chunk header: {:#06x}", header));
+        }
+        let end = pos + (header & CHUNK_SIZE_MASK) as usize + 3;
+        if end > data.len() {
+            return invalid(&format!("LZNT1 chunk at {} is truncated", pos));
+        }
+        let chunk = &data[pos + 2..end];
+        let limit = if output.len() - out < CHUNK_SIZE { output.len() } else { out + CHUNK_SIZE };
+
+        let count = if header & CHUNK_COMPRESSED != 0 {
+            decompress_chunk(chunk, &mut output[out..limit])?
+        } else {
+            if chunk.len() > limit - out {
+                return invalid("LZNT1 chunk is too large");
+            }
+            output[out..out + chunk.len()].copy_from_slice(chunk);
+            chunk.len()
+        };
+        for b in &mut output[out + count..limit] {
+            *b = 0;
+        }
+        out = limit;
+        pos = end;
+    }
+    Ok(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::utils::hex_decode;
+
+    const TUNE: &[u8] = b"F# F# G A A G F# E D D E F# F# E E F# F# G A A G F# E D D E F# E D D E E F# D E F# G F# D E F# G F# E D E A F# F# G A A G F# E D D E F# E D D";
+
+    /// The text of the MS-XCA example compressed as a single chunk, whose back references use
+    /// fewer length bits further into the chunk.
+    fn compressed() -> Vec<u8> {
+        hex_decode("
+            2fb08846232000204720410010a24701a04520440008450150ff026805241388052802180426024609160d01
+            484500781e90
+        ")
+    }
+
+    #[test]
+    fn compressed_chunk() {
+        let mut output = vec![0xFF; CHUNK_SIZE];
+        assert_eq!(lznt1_decompress(&compressed(), &mut output).unwrap(), CHUNK_SIZE);
+        assert_eq!(&output[..TUNE.len()], TUNE);
+        assert!(output[TUNE.len()..].iter().all(|&b| b == 0));
+
+        // the chunk does not fit in the output
+        let mut output = [0; 20];
+        assert!(lznt1_decompress(&compressed(), &mut output).is_err());
+    }
+
+    #[test]
+    fn uncompressed_chunk() {
+        // a chunk stored as is after a compressed one, then the terminating header
+        let mut data = compressed();
+        data.extend_from_slice(&(CHUNK_SIGNATURE | 4).to_le_bytes());
+        data.extend_from_slice(b"hello");
+        data.extend_from_slice(&[0, 0]);
+        let mut output = vec![0; CHUNK_SIZE * 3];
+        assert_eq!(lznt1_decompress(&data, &mut output).unwrap(), CHUNK_SIZE * 2);
+        assert_eq!(&output[..TUNE.len()], TUNE);
+        assert_eq!(&output[CHUNK_SIZE..CHUNK_SIZE + 6], b"hello\0");
+    }
+
+    #[test]
+    fn truncated_chunk() {
+        let data = compressed();
+        let mut output = vec![0; CHUNK_SIZE];
+        assert!(lznt1_decompress(&data[..data.len() - 1], &mut output).is_err());
+
+        // a back reference cut short by the end of its chunk
+        let data = hex_decode("02b0 0241 00");
+        assert!(lznt1_decompress(&data, &mut output).is_err());
+    }
+}
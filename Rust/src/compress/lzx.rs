@@ -0,0 +1,321 @@
+use std::io;
+
+use super::huffman::Huffman;
+
+
+const BLOCK_VERBATIM: u32 = 1;
+const BLOCK_ALIGNED: u32 = 2;
+const BLOCK_UNCOMPRESSED: u32 = 3;
+const DEFAULT_BLOCK_SIZE: usize = 32768;
+
+const WINDOW_SIZE: usize = 32768;
+const OFFSET_SLOTS: usize = 30;         // enough for the window
+const MAIN_SYMBOLS: usize = 256 + OFFSET_SLOTS * 8;
+const LENGTH_SYMBOLS: usize = 249;
+const PRECODE_SYMBOLS: usize = 20;
+const ALIGNED_SYMBOLS: usize = 8;
+const MAXIMUM_CODE_BITS: u32 = 16;
+const ALIGNED_CODE_BITS: u32 = 7;
+
+const PRIMARY_LENGTHS: usize = 7;       // match lengths held in the main symbol
+const MINIMUM_MATCH: usize = 2;
+const OFFSET_ADJUSTMENT: usize = 2;     // for the three repeated offsets
+const E8_FILE_SIZE: i32 = 12000000;     // for the translation of x86 call targets
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Number of extra bits following an offset slot.
+fn extra_bits(slot: usize) -> u32 {
+    if slot < 4 { 0 } else { core::cmp::min((slot as u32 - 2) / 2, 17) }
+}
+
+/// First offset of an offset slot.
+fn slot_base(slot: usize) -> usize {
+    (0..slot).fold(0, |base, s| base + (1 << extra_bits(s)))
+}
+
+/// A bit stream of 16 bit little endian words, read most significant bit first.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+
+    fn new(data: &'a [u8]) -> Self {
+        Self { data: data, pos: 0, buffer: 0, count: 0 }
+    }
+
+    /// Make sure `count` bits are buffered, past the end of the input reads as zeros.
+    fn ensure(&mut self, count: u32) {
+        while self.count < count {
+            let word = match self.data.get(self.pos..self.pos + 2) {
+                Some(b) => u16::from_le_bytes([b[0], b[1]]),
+                None => 0,
+            };
+            self.pos += 2;
+            self.buffer |= (word as u64) << (48 - self.count);
+            self.count += 16;
+        }
+    }
+
+    fn peek(&mut self, count: u32) -> u32 {
+        self.ensure(count);
+        (self.buffer >> (64 - count)) as u32
+    }
+
+    fn consume(&mut self, count: u32) {
+        self.buffer <<= count;
+        self.count -= count;
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = self.peek(count);
+        self.consume(count);
+        value
+    }
+
+    fn symbol(&mut self, code: &Huffman) -> io::Result<usize> {
+        let (symbol, length) = code.decode(self.peek(code.bits()))?;
+        self.consume(length);
+        Ok(symbol as usize)
+    }
+
+    /// Skip to the next word, or over a whole word if already there.
+    fn align(&mut self) {
+        self.ensure(1);
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    fn bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        match self.data.get(self.pos..self.pos + count) {
+            Some(bytes) => {
+                self.pos += count;
+                Ok(bytes)
+            }
+            None => invalid("LZX stream is truncated"),
+        }
+    }
+}
+
+/// Read code lengths as deltas from the previous lengths, themselves coded by a pretree.
+fn read_lengths(bits: &mut Bits, lengths: &mut [u8]) -> io::Result<()> {
+    let mut precode = [0; PRECODE_SYMBOLS];
+    for length in precode.iter_mut() {
+        *length = bits.read(4) as u8;
+    }
+    let precode = Huffman::new(&precode, MAXIMUM_CODE_BITS)?;
+
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = bits.symbol(&precode)?;
+        let (run, value) = match symbol {
+            0..=16 => (1, (lengths[i] + 17 - symbol as u8) % 17),
+            17 => (4 + bits.read(4) as usize, 0),
+            18 => (20 + bits.read(5) as usize, 0),
+            _ => {
+                let run = 4 + bits.read(1) as usize;
+                let symbol = bits.symbol(&precode)?;
+                if symbol > 16 {
+                    return invalid(&format!("Invalid LZX pretree symbol: {}", symbol));
+                }
+                (run, (lengths[i] + 17 - symbol as u8) % 17)
+            }
+        };
+        let end = core::cmp::min(i + run, lengths.len());
+        for length in &mut lengths[i..end] {
+            *length = value;
+        }
+        i = end;
+    }
+    Ok(())
+}
+
+/// Undo the translation of the targets of x86 `call` instructions.
+fn undo_e8(output: &mut [u8]) {
+    if output.len() <= 10 {
+        return;
+    }
+    let mut i = 0;
+    while i < output.len() - 10 {
+        if output[i] != 0xE8 {
+            i += 1;
+            continue;
+        }
+        let mut target = [0; 4];
+        target.copy_from_slice(&output[i + 1..i + 5]);
+        let absolute = i32::from_le_bytes(target);
+        let position = i as i32;
+        let relative = if (0..E8_FILE_SIZE).contains(&absolute) {
+            Some(absolute - position)
+        } else if absolute < 0 && absolute >= -position {
+            Some(absolute + E8_FILE_SIZE)
+        } else {
+            None
+        };
+        if let Some(relative) = relative {
+            output[i + 1..i + 5].copy_from_slice(&relative.to_le_bytes());
+        }
+        i += 5;
+    }
+}
+
+/// Decompress LZX data as used by WIM and WOF, with a 32 KiB window.
+///
+/// The stream has no header and x86 call translation is always applied.  Returns the number of
+/// bytes written to `output`.
+pub fn lzx_decompress(data: &[u8], output: &mut [u8]) -> io::Result<usize> {
+    if output.len() > WINDOW_SIZE {
+        return invalid(&format!("LZX output is larger than the window: {}", output.len()));
+    }
+    let mut bits = Bits::new(data);
+    let mut main_lengths = [0; MAIN_SYMBOLS];
+    let mut length_lengths = [0; LENGTH_SYMBOLS];
+    let mut recent = [1; 3];
+    let mut out = 0;
+
+    while out < output.len() {
+        let kind = bits.read(3);
+        let size = if bits.read(1) == 1 { DEFAULT_BLOCK_SIZE } else { bits.read(16) as usize };
+        if size == 0 || size > output.len() - out {
+            return invalid(&format!("Invalid LZX block size: {}", size));
+        }
+        let end = out + size;
+
+        if kind == BLOCK_UNCOMPRESSED {
+            bits.align();
+            for offset in recent.iter_mut() {
+                let mut value = [0; 4];
+                value.copy_from_slice(bits.bytes(4)?);
+                *offset = u32::from_le_bytes(value) as usize;
+                if *offset == 0 {
+                    return invalid("Invalid LZX repeated offset: 0");
+                }
+            }
+            output[out..end].copy_from_slice(bits.bytes(size)?);
+            if size % 2 == 1 {
+                bits.bytes(1)?;
+            }
+            out = end;
+            continue;
+        }
+        if kind != BLOCK_VERBATIM && kind != BLOCK_ALIGNED {
+            return invalid(&format!("Invalid LZX block type: {}", kind));
+        }
+
+        let aligned = if kind == BLOCK_ALIGNED {
+            let mut lengths = [0; ALIGNED_SYMBOLS];
+            for length in lengths.iter_mut() {
+                *length = bits.read(3) as u8;
+            }
+            Some(Huffman::new(&lengths, ALIGNED_CODE_BITS)?)
+        } else {
+            None
+        };
+        read_lengths(&mut bits, &mut main_lengths[..256])?;
+        read_lengths(&mut bits, &mut main_lengths[256..])?;
+        read_lengths(&mut bits, &mut length_lengths)?;
+        let main = Huffman::new(&main_lengths, MAXIMUM_CODE_BITS)?;
+        let lengths = Huffman::new(&length_lengths, MAXIMUM_CODE_BITS)?;
+
+        while out < end {
+            let symbol = bits.symbol(&main)?;
+            if symbol < 256 {
+                output[out] = symbol as u8;
+                out += 1;
+                continue;
+            }
+
+            let symbol = symbol - 256;
+            let mut length = symbol % 8;
+            if length == PRIMARY_LENGTHS {
+                length += bits.symbol(&lengths)?;
+            }
+            length += MINIMUM_MATCH;
+
+            let slot = symbol / 8;
+            let offset = if slot < 3 {
+                recent.swap(slot, 0);
+                recent[0]
+            } else {
+                let extra = extra_bits(slot);
+                let offset = match &aligned {
+                    Some(aligned) if extra >= 3 => {
+                        let verbatim = (bits.read(extra - 3) as usize) << 3;
+                        verbatim + bits.symbol(aligned)?
+                    }
+                    _ => bits.read(extra) as usize,
+                };
+                let offset = slot_base(slot) + offset - OFFSET_ADJUSTMENT;
+                recent[2] = recent[1];
+                recent[1] = recent[0];
+                recent[0] = offset;
+                offset
+            };
+
+            if offset > out || length > end - out {
+                return invalid(&format!("Invalid LZX match: {} x {} at {}", offset, length, out));
+            }
+            for _ in 0..length {
+                output[out] = output[out - offset];
+                out += 1;
+            }
+        }
+    }
+
+    undo_e8(&mut output[..out]);
+    Ok(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::utils::hex_decode;
+
+    #[test]
+    fn verbatim_block() {
+        // the alphabet, a match of 52 at 26 using the length tree, "XY", then 4 at the repeated offset
+        let data = hex_decode("
+            0520554555555555555555557e5963516c22186331c6638cc6188c31186331c6648c2fbf6ac9aaaaaaaaaaaa
+            aaaaa0aa250315f9fc922fbfd5c4555555555555555565554b78fcf22fbf43804c212574634bf85c53466da5
+            677cea5b3c00
+        ");
+        let mut output = [0; 84];
+        assert_eq!(lzx_decompress(&data, &mut output).unwrap(), 84);
+        assert_eq!(&output[..], &b"abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyzXYcdef"[..]);
+    }
+
+    #[test]
+    fn uncompressed_block() {
+        let mut data = hex_decode("00600050 01000000 01000000 01000000");
+        data.extend_from_slice(b"hello\0");
+        let mut output = [0; 5];
+        assert_eq!(lzx_decompress(&data, &mut output).unwrap(), 5);
+        assert_eq!(&output, b"hello");
+
+        // truncated, or a block larger than the output
+        assert!(lzx_decompress(&data[..20], &mut output).is_err());
+        assert!(lzx_decompress(&data, &mut [0; 4]).is_err());
+    }
+
+    #[test]
+    fn e8_translation() {
+        // call targets made absolute and negative ones, within the last 10 bytes nothing is changed
+        let mut output = *b"-----\xE8\x19\x00\x00\x00\xE8\xFB\xFF\xFF\xFF-----\xE8\x19\x00\x00\x00";
+        undo_e8(&mut output);
+        assert_eq!(&output[5..10], b"\xE8\x14\x00\x00\x00");
+        assert_eq!(&output[10..15], b"\xE8\xFB\x1A\xB7\x00");
+        assert_eq!(&output[20..], b"\xE8\x19\x00\x00\x00");
+    }
+}
@@ -0,0 +1,8 @@
+mod huffman;
+mod lznt1;
+mod lzx;
+mod xpress;
+
+pub use lznt1::lznt1_decompress;
+pub use lzx::lzx_decompress;
+pub use xpress::xpress_decompress;
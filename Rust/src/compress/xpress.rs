@@ -0,0 +1,188 @@
+use std::io;
+
+use super::huffman::Huffman;
+
+
+const BLOCK_SIZE: usize = 65536;
+const SYMBOLS: usize = 512;
+const CODE_BITS: u32 = 15;
+const MINIMUM_MATCH: usize = 3;
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// The bit stream of a block, which interleaves 16 bit words with whole bytes.
+///
+/// Two words are always buffered, so bytes are read from after them.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    next: u32,
+    extra: i32,         // buffered bits beyond the first 16
+}
+
+impl<'a> Bits<'a> {
+
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        let mut bits = Self { data: data, pos: pos, next: 0, extra: 16 };
+        bits.next = (bits.word() as u32) << 16;
+        bits.next |= bits.word() as u32;
+        bits
+    }
+
+    /// Read a word, past the end of the input reads as zero.
+    fn word(&mut self) -> u16 {
+        let word = match self.data.get(self.pos..self.pos + 2) {
+            Some(b) => u16::from_le_bytes([b[0], b[1]]),
+            None => 0,
+        };
+        self.pos += 2;
+        word
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        match self.data.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                Ok(b)
+            }
+            None => invalid("XPRESS stream is truncated"),
+        }
+    }
+
+    fn bytes<T: AsMut<[u8]>>(&mut self, mut buf: T) -> io::Result<T> {
+        for b in buf.as_mut() {
+            *b = self.byte()?;
+        }
+        Ok(buf)
+    }
+
+    fn consume(&mut self, count: u32) {
+        if count == 0 {
+            return;
+        }
+        self.next <<= count;
+        self.extra -= count as i32;
+        if self.extra < 0 {
+            self.next |= (self.word() as u32) << -self.extra;
+            self.extra += 16;
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = self.next >> (32 - count);
+        self.consume(count);
+        value
+    }
+}
+
+/// Decompress XPRESS Huffman data, returning the number of bytes written to `output`.
+///
+/// Output is produced in blocks of 64 KiB, each starting with the code lengths of its 512
+/// symbols.  Decompression stops once `output` is full.
+pub fn xpress_decompress(data: &[u8], output: &mut [u8]) -> io::Result<usize> {
+    let mut pos = 0;
+    let mut out = 0;
+    while out < output.len() {
+        if pos + SYMBOLS / 2 > data.len() {
+            return invalid("XPRESS block is truncated");
+        }
+        let lengths: Vec<u8> = data[pos..pos + SYMBOLS / 2].iter().flat_map(|b| vec![b & 0x0F, b >> 4]).collect();
+        let code = Huffman::new(&lengths, CODE_BITS)?;
+        let mut bits = Bits::new(data, pos + SYMBOLS / 2);
+
+        let end = if output.len() - out < BLOCK_SIZE { output.len() } else { out + BLOCK_SIZE };
+        while out < end {
+            let (symbol, length) = code.decode(bits.next >> (32 - CODE_BITS))?;
+            bits.consume(length);
+            if symbol < 256 {
+                output[out] = symbol as u8;
+                out += 1;
+                continue;
+            }
+
+            let symbol = symbol as usize - 256;
+            let mut length = symbol & 0x0F;
+            let offset_bits = (symbol >> 4) as u32;
+            if length == 0x0F {
+                length = bits.byte()? as usize;
+                if length == 0xFF {
+                    length = u16::from_le_bytes(bits.bytes([0; 2])?) as usize;
+                    if length == 0 {
+                        length = u32::from_le_bytes(bits.bytes([0; 4])?) as usize;
+                    }
+                    if length < 0x0F {
+                        return invalid(&format!("Invalid XPRESS match length: {}", length));
+                    }
+                    length -= 0x0F;
+                }
+                length += 0x0F;
+            }
+            length += MINIMUM_MATCH;
+            let offset = (1 << offset_bits) + bits.read(offset_bits) as usize;
+
+            if offset > out {
+                return invalid(&format!("Invalid XPRESS match offset: {} at {}", offset, out));
+            }
+            // the final match may run past the end of the output
+            let length = if length > output.len() - out { output.len() - out } else { length };
+            for _ in 0..length {
+                output[out] = output[out - offset];
+                out += 1;
+            }
+        }
+        pos = bits.pos;
+    }
+    Ok(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::utils::hex_decode;
+
+    /// Every symbol used by the stream has a 5 bit code.
+    fn code_lengths(symbols: &[usize]) -> Vec<u8> {
+        let mut lengths = vec![0u8; SYMBOLS / 2];
+        for &symbol in symbols {
+            lengths[symbol / 2] |= 5 << (symbol % 2 * 4);
+        }
+        lengths
+    }
+
+    #[test]
+    fn literals_and_matches() {
+        // the alphabet, a match of 52 at 26 with an extra length byte, "hello ", a match of 5 at 6, "!"
+        let mut symbols: Vec<usize> = b" !abcdefghijklmnopqrstuvwxyz".iter().map(|&b| b as usize).collect();
+        symbols.extend_from_slice(&[256 + 0x4F, 256 + 0x22]);
+        let mut data = code_lengths(&symbols);
+        data.extend(hex_decode("c8101d535209d7d8113ee9945f5bd61949fb5b3322720000080000"));
+
+        let mut output = [0; 90];
+        assert_eq!(xpress_decompress(&data, &mut output).unwrap(), 90);
+        assert_eq!(&output[..], &b"abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyzhello hello!"[..]);
+
+        // the final match is cut short by the end of the output
+        let mut output = [0; 40];
+        assert_eq!(xpress_decompress(&data, &mut output).unwrap(), 40);
+        assert_eq!(&output[..], &b"abcdefghijklmnopqrstuvwxyzabcdefghijklmn"[..]);
+    }
+
+    #[test]
+    fn invalid_streams() {
+        let mut output = [0; 16];
+        assert!(xpress_decompress(&[0; SYMBOLS / 2 - 1], &mut output).is_err());
+
+        // a match before the start of the output
+        let mut data = code_lengths(&[256]);
+        data.extend_from_slice(&[0; 4]);
+        assert!(xpress_decompress(&data, &mut output).is_err());
+    }
+}
@@ -0,0 +1,500 @@
+use std::io;
+
+
+const AES_CONSTANT: [u32; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+const AES_FORWARD_BOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const AES_REVERSE_BOX: [u8; 256] = reverse_box();
+
+// the round tables are derived from the boxes at compile time
+const AES_FORWARD_TABLE: [[u32; 256]; 4] = forward_table();
+const AES_REVERSE_TABLE: [[u32; 256]; 4] = reverse_table();
+
+
+const fn multiply(mut a: u8, mut b: u8) -> u8 {
+    let mut result = 0;
+    while b > 0 {
+        if b & 1 != 0 {
+            result ^= a;
+        }
+        a = (a << 1) ^ (((a >> 7) & 1) * 0x1b);
+        b >>= 1;
+    }
+    result
+}
+
+const fn reverse_box() -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[AES_FORWARD_BOX[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+}
+
+const fn forward_table() -> [[u32; 256]; 4] {
+    let mut table = [[0; 256]; 4];
+    let mut i = 0;
+    while i < 256 {
+        let s = AES_FORWARD_BOX[i];
+        let word = (multiply(s, 2) as u32) << 24 | (s as u32) << 16 |
+                   (s as u32) << 8 | multiply(s, 3) as u32;
+        table[0][i] = word;
+        table[1][i] = word.rotate_right(8);
+        table[2][i] = word.rotate_right(16);
+        table[3][i] = word.rotate_right(24);
+        i += 1;
+    }
+    table
+}
+
+const fn reverse_table() -> [[u32; 256]; 4] {
+    let mut table = [[0; 256]; 4];
+    let mut i = 0;
+    while i < 256 {
+        let s = AES_REVERSE_BOX[i];
+        let word = (multiply(s, 14) as u32) << 24 | (multiply(s, 9) as u32) << 16 |
+                   (multiply(s, 13) as u32) << 8 | multiply(s, 11) as u32;
+        table[0][i] = word;
+        table[1][i] = word.rotate_right(8);
+        table[2][i] = word.rotate_right(16);
+        table[3][i] = word.rotate_right(24);
+        i += 1;
+    }
+    table
+}
+
+#[inline]
+fn substitute(word: u32) -> u32 {
+    (AES_FORWARD_BOX[(word >> 24) as usize] as u32) << 24 |
+    (AES_FORWARD_BOX[(word >> 16 & 0xff) as usize] as u32) << 16 |
+    (AES_FORWARD_BOX[(word >> 8 & 0xff) as usize] as u32) << 8 |
+    AES_FORWARD_BOX[(word & 0xff) as usize] as u32
+}
+
+
+/// AES-128, AES-192 or AES-256 block cipher.
+#[derive(Clone)]
+pub struct Aes {
+    cycles: usize,
+    encrypt_key: [u32; 60],
+    decrypt_key: [u32; 60],
+}
+
+impl Aes {
+
+    /// Expand a 16, 24 or 32 byte key.
+    pub fn new(key: &[u8]) -> io::Result<Self> {
+        if key.len() != 16 && key.len() != 24 && key.len() != 32 {
+            eprintln!("ERROR: Invalid AES Key Size: {}", key.len());
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        let words = key.len() / 4;
+        let cycles = words + 6;
+        let total = 4 * (cycles + 1);
+
+        let mut encrypt_key = [0; 60];
+        for i in 0..words {
+            encrypt_key[i] = u32::from_be_bytes([key[4 * i], key[4 * i + 1], key[4 * i + 2], key[4 * i + 3]]);
+        }
+        for i in words..total {
+            let mut rotate = encrypt_key[i - 1];
+            if i % words == 0 {
+                rotate = substitute(rotate.rotate_left(8)) ^ (AES_CONSTANT[i / words - 1] << 24);
+            } else if words > 6 && i % words == 4 {
+                rotate = substitute(rotate);
+            }
+            encrypt_key[i] = encrypt_key[i - words] ^ rotate;
+        }
+
+        // the decryption schedule is the encryption schedule in reverse
+        // with InvMixColumns applied to all but the first and last round
+        let mut decrypt_key = [0; 60];
+        for cycle in 0..=cycles {
+            for i in 0..4 {
+                let word = encrypt_key[4 * (cycles - cycle) + i];
+                decrypt_key[4 * cycle + i] = if cycle == 0 || cycle == cycles {
+                    word
+                } else {
+                    AES_REVERSE_TABLE[0][AES_FORWARD_BOX[(word >> 24) as usize] as usize] ^
+                    AES_REVERSE_TABLE[1][AES_FORWARD_BOX[(word >> 16 & 0xff) as usize] as usize] ^
+                    AES_REVERSE_TABLE[2][AES_FORWARD_BOX[(word >> 8 & 0xff) as usize] as usize] ^
+                    AES_REVERSE_TABLE[3][AES_FORWARD_BOX[(word & 0xff) as usize] as usize]
+                };
+            }
+        }
+
+        Ok(Self {
+            cycles: cycles,
+            encrypt_key: encrypt_key,
+            decrypt_key: decrypt_key,
+        })
+    }
+
+    /// Encrypt a single 16 byte block in place.
+    pub fn encrypt_block(&self, block: &mut [u8]) {
+        self.cipher(block, &self.encrypt_key, &AES_FORWARD_TABLE, &AES_FORWARD_BOX, 1);
+    }
+
+    /// Decrypt a single 16 byte block in place.
+    pub fn decrypt_block(&self, block: &mut [u8]) {
+        self.cipher(block, &self.decrypt_key, &AES_REVERSE_TABLE, &AES_REVERSE_BOX, 3);
+    }
+
+    /// Encrypt every 16 byte block in place.
+    pub fn ecb_encrypt(&self, data: &mut [u8]) {
+        for block in data.chunks_exact_mut(16) {
+            self.encrypt_block(block);
+        }
+    }
+
+    /// Decrypt every 16 byte block in place.
+    pub fn ecb_decrypt(&self, data: &mut [u8]) {
+        for block in data.chunks_exact_mut(16) {
+            self.decrypt_block(block);
+        }
+    }
+
+    /// Encrypt in place using cipher block chaining.
+    pub fn cbc_encrypt(&self, iv: &[u8; 16], data: &mut [u8]) {
+        let mut previous = *iv;
+        for block in data.chunks_exact_mut(16) {
+            for (b, p) in block.iter_mut().zip(previous.iter()) {
+                *b ^= p;
+            }
+            self.encrypt_block(block);
+            previous.copy_from_slice(block);
+        }
+    }
+
+    /// Decrypt in place using cipher block chaining.
+    pub fn cbc_decrypt(&self, iv: &[u8; 16], data: &mut [u8]) {
+        let mut previous = *iv;
+        let mut current = [0; 16];
+        for block in data.chunks_exact_mut(16) {
+            current.copy_from_slice(block);
+            self.decrypt_block(block);
+            for (b, p) in block.iter_mut().zip(previous.iter()) {
+                *b ^= p;
+            }
+            previous = current;
+        }
+    }
+
+    /// Decrypt in place using counter with CBC-MAC (RFC 3610), returning whether the MAC matches
+    /// the data and the associated data.
+    pub fn ccm_decrypt(&self, nonce: &[u8], associated: &[u8], mac: &[u8], data: &mut [u8]) -> bool {
+        if nonce.len() < 7 || nonce.len() > 13 || mac.len() < 4 || mac.len() > 16 || !mac.len().is_multiple_of(2) ||
+           associated.len() >= 0xFF00 {
+            return false;
+        }
+        let length_size = 15 - nonce.len();
+
+        let mut counter = [0u8; 16];
+        counter[0] = (length_size - 1) as u8;
+        counter[1..1 + nonce.len()].copy_from_slice(nonce);
+
+        for (i, chunk) in data.chunks_mut(16).enumerate() {
+            let mut stream = counter;
+            let index = (i as u64 + 1).to_be_bytes();
+            for j in 0..length_size.min(8) {
+                stream[15 - j] = index[7 - j];
+            }
+            self.encrypt_block(&mut stream);
+            for (d, s) in chunk.iter_mut().zip(stream.iter()) {
+                *d ^= s;
+            }
+        }
+
+        let mut tag = [0u8; 16];
+        tag[0] = ((mac.len() as u8 - 2) / 2) << 3 | (length_size - 1) as u8;
+        if !associated.is_empty() {
+            tag[0] |= 0x40;
+        }
+        tag[1..1 + nonce.len()].copy_from_slice(nonce);
+        let length = (data.len() as u64).to_be_bytes();
+        for j in 0..length_size.min(8) {
+            tag[15 - j] = length[7 - j];
+        }
+        self.encrypt_block(&mut tag);
+
+        // the associated data follows its 16 bit length, padded to whole blocks
+        if !associated.is_empty() {
+            let mut header = (associated.len() as u16).to_be_bytes().to_vec();
+            header.extend_from_slice(associated);
+            for chunk in header.chunks(16) {
+                for (t, a) in tag.iter_mut().zip(chunk.iter()) {
+                    *t ^= a;
+                }
+                self.encrypt_block(&mut tag);
+            }
+        }
+        for chunk in data.chunks(16) {
+            for (t, d) in tag.iter_mut().zip(chunk.iter()) {
+                *t ^= d;
+            }
+            self.encrypt_block(&mut tag);
+        }
+
+        self.encrypt_block(&mut counter);
+        let mut difference = 0;
+        for i in 0..mac.len() {
+            difference |= tag[i] ^ counter[i] ^ mac[i];
+        }
+        difference == 0
+    }
+
+    fn cipher(&self, block: &mut [u8], key: &[u32; 60], table: &[[u32; 256]; 4], sbox: &[u8; 256], increment: usize) {
+        let mut values = [0u32; 4];
+        for i in 0..4 {
+            values[i] = u32::from_be_bytes([block[4 * i], block[4 * i + 1], block[4 * i + 2], block[4 * i + 3]]) ^ key[i];
+        }
+
+        let mut cipher = [0u32; 4];
+        let mut round = 4;
+        for _ in 1..self.cycles {
+            for i in 0..4 {
+                cipher[i] = table[0][(values[i] >> 24) as usize] ^
+                            table[1][(values[(i + increment) % 4] >> 16 & 0xff) as usize] ^
+                            table[2][(values[(i + 2 * increment) % 4] >> 8 & 0xff) as usize] ^
+                            table[3][(values[(i + 3 * increment) % 4] & 0xff) as usize] ^
+                            key[round + i];
+            }
+            values = cipher;
+            round += 4;
+        }
+
+        for i in 0..4 {
+            let word = (sbox[(values[i] >> 24) as usize] as u32) << 24 |
+                       (sbox[(values[(i + increment) % 4] >> 16 & 0xff) as usize] as u32) << 16 |
+                       (sbox[(values[(i + 2 * increment) % 4] >> 8 & 0xff) as usize] as u32) << 8 |
+                       sbox[(values[(i + 3 * increment) % 4] & 0xff) as usize] as u32;
+            block[4 * i..4 * i + 4].copy_from_slice(&(word ^ key[round + i]).to_be_bytes());
+        }
+    }
+}
+
+
+/// Multiply the XTS tweak by the primitive element of GF(2^128).
+fn update_tweak(tweak: &mut [u8; 16]) {
+    let mut carry_bit = 0;
+    for byte in tweak.iter_mut() {
+        let swap = (*byte << 1) | carry_bit;
+        carry_bit = *byte >> 7;
+        *byte = swap;
+    }
+    if carry_bit > 0 {
+        tweak[0] ^= 0x87;
+    }
+}
+
+
+/// AES in XEX-based tweaked-codebook mode with ciphertext stealing.
+#[derive(Clone)]
+pub struct Xts {
+    data: Aes,
+    tweak: Aes,
+}
+
+impl Xts {
+
+    /// Create from a double length key, the second half being the tweak key.
+    pub fn new(key: &[u8]) -> io::Result<Self> {
+        let half = key.len() / 2;
+        Self::with_tweak_key(&key[..half], &key[half..])
+    }
+
+    /// Create from separate data and tweak keys.
+    pub fn with_tweak_key(key: &[u8], tweak_key: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            data: Aes::new(key)?,
+            tweak: Aes::new(tweak_key)?,
+        })
+    }
+
+    /// Encrypt a data unit in place.
+    pub fn encrypt(&self, tweak: &[u8; 16], data: &mut [u8]) {
+        self.xts(tweak, data, true);
+    }
+
+    /// Decrypt a data unit in place.
+    pub fn decrypt(&self, tweak: &[u8; 16], data: &mut [u8]) {
+        self.xts(tweak, data, false);
+    }
+
+    fn xts(&self, tweak_data: &[u8; 16], data: &mut [u8], encrypt: bool) {
+        let size = data.len();
+        if size < 16 {
+            return;
+        }
+
+        let mut tweak = *tweak_data;
+        self.tweak.encrypt_block(&mut tweak);
+
+        let block = |tweak: &[u8; 16], data: &mut [u8]| {
+            for (d, t) in data.iter_mut().zip(tweak.iter()) {
+                *d ^= t;
+            }
+            if encrypt {
+                self.data.encrypt_block(data);
+            } else {
+                self.data.decrypt_block(data);
+            }
+            for (d, t) in data.iter_mut().zip(tweak.iter()) {
+                *d ^= t;
+            }
+        };
+
+        let remainder = size % 16;
+        let full = if remainder > 0 { size - remainder - 16 } else { size };
+
+        let mut offset = 0;
+        while offset < full {
+            block(&tweak, &mut data[offset..offset + 16]);
+            update_tweak(&mut tweak);
+            offset += 16;
+        }
+
+        if remainder > 0 {
+            // ciphertext stealing, decryption consumes the tweaks out of order
+            let mut next = tweak;
+            update_tweak(&mut next);
+            let (first, second) = if encrypt { (tweak, next) } else { (next, tweak) };
+
+            block(&first, &mut data[offset..offset + 16]);
+            for i in 0..remainder {
+                data.swap(offset + i, offset + 16 + i);
+            }
+            block(&second, &mut data[offset..offset + 16]);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::utils::hex_decode;
+
+    // FIPS-197 Appendix C
+    #[test]
+    fn fips_197() {
+        let plaintext = hex_decode("00112233445566778899aabbccddeeff");
+        let vectors = [
+            ("000102030405060708090a0b0c0d0e0f", "69c4e0d86a7b0430d8cdb78070b4c55a"),
+            ("000102030405060708090a0b0c0d0e0f1011121314151617", "dda97ca4864cdfe06eaf70a0ec0d7191"),
+            ("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f", "8ea2b7ca516745bfeafc49904b496089"),
+        ];
+        for (key, ciphertext) in vectors.iter() {
+            let aes = Aes::new(&hex_decode(key)).unwrap();
+            let mut block = plaintext.clone();
+            aes.encrypt_block(&mut block);
+            assert_eq!(block, hex_decode(ciphertext));
+            aes.decrypt_block(&mut block);
+            assert_eq!(block, plaintext);
+        }
+    }
+
+    // NIST SP 800-38C Appendix C, Examples 1 to 3
+    #[test]
+    fn ccm() {
+        let aes = Aes::new(&hex_decode("404142434445464748494a4b4c4d4e4f")).unwrap();
+        let vectors = [
+            ("10111213141516", "0001020304050607", "20212223", "7162015b4dac255d"),
+            (
+                "1011121314151617", "000102030405060708090a0b0c0d0e0f", "202122232425262728292a2b2c2d2e2f",
+                "d2a1f0e051ea5f62081a7792073d593d1fc64fbfaccd",
+            ),
+            (
+                "101112131415161718191a1b", "000102030405060708090a0b0c0d0e0f10111213",
+                "202122232425262728292a2b2c2d2e2f3031323334353637",
+                "e3b201a9f5b71a7a9b1ceaeccd97e70b6176aad9a4428aa5484392fbc1b09951",
+            ),
+        ];
+        for (nonce, associated, plaintext, ciphertext) in vectors.iter() {
+            let (nonce, associated, plaintext) = (hex_decode(nonce), hex_decode(associated), hex_decode(plaintext));
+            let ciphertext = hex_decode(ciphertext);
+            let (data, mac) = ciphertext.split_at(plaintext.len());
+            let mut data = data.to_vec();
+            assert!(aes.ccm_decrypt(&nonce, &associated, mac, &mut data));
+            assert_eq!(data, plaintext);
+
+            let mut data = ciphertext[..plaintext.len()].to_vec();
+            assert!(!aes.ccm_decrypt(&nonce, &associated[1..], mac, &mut data));
+        }
+    }
+
+    #[test]
+    fn invalid_key_size() {
+        assert!(Aes::new(&[0; 20]).is_err());
+    }
+
+    // NIST SP 800-38A F.2.1
+    #[test]
+    fn cbc() {
+        let aes = Aes::new(&hex_decode("2b7e151628aed2a6abf7158809cf4f3c")).unwrap();
+        let mut iv = [0; 16];
+        iv.copy_from_slice(&hex_decode("000102030405060708090a0b0c0d0e0f"));
+        let plaintext = hex_decode("6bc1bee22e409f96e93d7e117393172a ae2d8a571e03ac9c9eb76fac45af8e51");
+        let mut data = plaintext.clone();
+        aes.cbc_encrypt(&iv, &mut data);
+        assert_eq!(data, hex_decode("7649abac8119b246cee98e9b12e9197d 5086cb9b507219ee95db113a917678b2"));
+        aes.cbc_decrypt(&iv, &mut data);
+        assert_eq!(data, plaintext);
+    }
+
+    // IEEE 1619-2007 Appendix B vectors 1, 2 and 15
+    #[test]
+    fn ieee_1619() {
+        let vectors = [
+            (
+                "00000000000000000000000000000000 00000000000000000000000000000000",
+                0u64,
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "917cf69ebd68b2ec9b9fe9a3eadda692cd43d2f59598ed858c02c2652fbf922e",
+            ),
+            (
+                "11111111111111111111111111111111 22222222222222222222222222222222",
+                0x3333333333,
+                "4444444444444444444444444444444444444444444444444444444444444444",
+                "c454185e6a16936e39334038acef838bfb186fff7480adc4289382ecd6d394f0",
+            ),
+            (
+                "fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0 bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0",
+                0x123456789a,
+                "000102030405060708090a0b0c0d0e0f10",
+                "6c1625db4671522d3d7599601de7ca09ed",
+            ),
+        ];
+        for (key, sequence, plaintext, ciphertext) in vectors.iter() {
+            let xts = Xts::new(&hex_decode(key)).unwrap();
+            let mut tweak = [0; 16];
+            tweak[..8].copy_from_slice(&sequence.to_le_bytes());
+            let mut data = hex_decode(plaintext);
+            xts.encrypt(&tweak, &mut data);
+            assert_eq!(data, hex_decode(ciphertext));
+            xts.decrypt(&tweak, &mut data);
+            assert_eq!(data, hex_decode(plaintext));
+        }
+    }
+}
\ No newline at end of file
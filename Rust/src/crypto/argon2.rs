@@ -0,0 +1,379 @@
+use std::io;
+
+use super::{Blake2b, Hash};
+
+
+const ARGON2_VERSION: u32 = 0x13;
+const ARGON2_SYNC_POINTS: usize = 4;
+const ARGON2_BLOCK_WORDS: usize = 128;
+
+
+/// Argon2 variants.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Argon2Type {
+    Argon2d = 0,
+    Argon2i = 1,
+    Argon2id = 2,
+}
+
+#[derive(Clone, Copy)]
+struct Block([u64; ARGON2_BLOCK_WORDS]);
+
+impl Block {
+
+    fn zero() -> Self {
+        Block([0; ARGON2_BLOCK_WORDS])
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut block = Self::zero();
+        for (i, word) in bytes.chunks_exact(8).enumerate() {
+            let mut raw = [0u8; 8];
+            raw.copy_from_slice(word);
+            block.0[i] = u64::from_le_bytes(raw);
+        }
+        block
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        self.0.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+    }
+}
+
+
+/// Argon2 memory-hard key derivation function (RFC 9106), version 1.3.
+///
+/// Lanes are computed one after another rather than in parallel,
+/// which produces the same output.
+pub fn argon2(variant: Argon2Type, password: &[u8], salt: &[u8], iterations: u32,
+              memory: u32, parallelism: u32, key: &mut [u8]) -> io::Result<()> {
+    let parameters = Parameters {
+        variant: variant,
+        password: password,
+        salt: salt,
+        secret: &[],
+        associated: &[],
+        iterations: iterations,
+        memory: memory,
+        parallelism: parallelism,
+    };
+    derive(&parameters, key)
+}
+
+
+/// Every input to Argon2, including the rarely used secret and associated data.
+struct Parameters<'a> {
+    variant: Argon2Type,
+    password: &'a [u8],
+    salt: &'a [u8],
+    secret: &'a [u8],
+    associated: &'a [u8],
+    iterations: u32,
+    memory: u32,        // KiB
+    parallelism: u32,
+}
+
+/// The shape of the memory matrix.
+struct Layout {
+    variant: Argon2Type,
+    lanes: usize,
+    segment_length: usize,
+    iterations: usize,
+}
+
+fn derive(parameters: &Parameters, key: &mut [u8]) -> io::Result<()> {
+    let Parameters { variant, iterations, memory, parallelism, .. } = *parameters;
+    if iterations < 1 || parallelism < 1 || memory < 8 * parallelism || key.len() < 4 {
+        eprintln!("ERROR: Invalid Argon2 Parameters: t={} m={} p={}", iterations, memory, parallelism);
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+
+    let mut hash = Blake2b::new();
+    hash.update(&parallelism.to_le_bytes());
+    hash.update(&(key.len() as u32).to_le_bytes());
+    hash.update(&memory.to_le_bytes());
+    hash.update(&iterations.to_le_bytes());
+    hash.update(&ARGON2_VERSION.to_le_bytes());
+    hash.update(&(variant as u32).to_le_bytes());
+    for field in [parameters.password, parameters.salt, parameters.secret, parameters.associated].iter() {
+        hash.update(&(field.len() as u32).to_le_bytes());
+        hash.update(field);
+    }
+    let initial = hash.finalize();
+
+    let segment_length = memory as usize / (ARGON2_SYNC_POINTS * parallelism as usize);
+    let layout = Layout {
+        variant: variant,
+        lanes: parallelism as usize,
+        segment_length: segment_length,
+        iterations: iterations as usize,
+    };
+    let lanes = layout.lanes;
+    let lane_length = segment_length * ARGON2_SYNC_POINTS;
+    let blocks = lane_length * lanes;
+
+    debug!("Argon2: {:?} t={} m={} p={} blocks={}", variant, iterations, memory, parallelism, blocks);
+
+    let mut memory_blocks = vec![Block::zero(); blocks];
+    for lane in 0..lanes {
+        for column in 0..2 {
+            let mut seed = initial.clone();
+            seed.extend_from_slice(&(column as u32).to_le_bytes());
+            seed.extend_from_slice(&(lane as u32).to_le_bytes());
+            let mut bytes = [0u8; 1024];
+            variable_hash(&seed, &mut bytes);
+            memory_blocks[lane * lane_length + column] = Block::from_bytes(&bytes);
+        }
+    }
+
+    for pass in 0..layout.iterations {
+        for slice in 0..ARGON2_SYNC_POINTS {
+            for lane in 0..lanes {
+                fill_segment(&mut memory_blocks, &layout, pass, slice, lane);
+            }
+        }
+    }
+
+    let mut last = memory_blocks[lane_length - 1];
+    for lane in 1..lanes {
+        let block = &memory_blocks[lane * lane_length + lane_length - 1];
+        for (l, b) in last.0.iter_mut().zip(block.0.iter()) {
+            *l ^= b;
+        }
+    }
+
+    variable_hash(&last.to_bytes(), key);
+    Ok(())
+}
+
+
+/// The variable length hash function H'.
+fn variable_hash(data: &[u8], output: &mut [u8]) {
+    let size = output.len();
+    if size <= 64 {
+        let mut hash = Blake2b::with_size(size);
+        hash.update(&(size as u32).to_le_bytes());
+        hash.update(data);
+        output.copy_from_slice(&hash.finalize());
+        return;
+    }
+
+    let mut hash = Blake2b::new();
+    hash.update(&(size as u32).to_le_bytes());
+    hash.update(data);
+    let mut value = hash.finalize();
+
+    let mut pos = 0;
+    while size - pos > 64 {
+        output[pos..pos + 32].copy_from_slice(&value[..32]);
+        pos += 32;
+
+        let mut hash = Blake2b::with_size(core::cmp::min(64, size - pos));
+        hash.update(&value);
+        value = hash.finalize();
+    }
+    output[pos..].copy_from_slice(&value);
+}
+
+
+fn fill_segment(memory: &mut [Block], layout: &Layout, pass: usize, slice: usize, lane: usize) {
+    let Layout { variant, lanes, segment_length, iterations } = *layout;
+    let lane_length = segment_length * ARGON2_SYNC_POINTS;
+    let data_independent = match variant {
+        Argon2Type::Argon2d => false,
+        Argon2Type::Argon2i => true,
+        Argon2Type::Argon2id => pass == 0 && slice < ARGON2_SYNC_POINTS / 2,
+    };
+
+    let mut input = Block::zero();
+    let mut addresses = Block::zero();
+    if data_independent {
+        input.0[0] = pass as u64;
+        input.0[1] = lane as u64;
+        input.0[2] = slice as u64;
+        input.0[3] = memory.len() as u64;
+        input.0[4] = iterations as u64;
+        input.0[5] = variant as u64;
+    }
+
+    let mut start = 0;
+    if pass == 0 && slice == 0 {
+        start = 2;
+        if data_independent {
+            next_addresses(&mut input, &mut addresses);
+        }
+    }
+
+    for index in start..segment_length {
+        let current = lane * lane_length + slice * segment_length + index;
+        let previous = if current.is_multiple_of(lane_length) { current + lane_length - 1 } else { current - 1 };
+
+        let random = if data_independent {
+            if index % ARGON2_BLOCK_WORDS == 0 {
+                next_addresses(&mut input, &mut addresses);
+            }
+            addresses.0[index % ARGON2_BLOCK_WORDS]
+        } else {
+            memory[previous].0[0]
+        };
+
+        let reference_lane = if pass == 0 && slice == 0 {
+            lane
+        } else {
+            (random >> 32) as usize % lanes
+        };
+        let same_lane = reference_lane == lane;
+
+        // the window of blocks that may be referenced
+        let area = if pass == 0 {
+            if same_lane {
+                slice * segment_length + index - 1
+            } else if index == 0 {
+                slice * segment_length - 1
+            } else {
+                slice * segment_length
+            }
+        } else if same_lane {
+            lane_length - segment_length + index - 1
+        } else if index == 0 {
+            lane_length - segment_length - 1
+        } else {
+            lane_length - segment_length
+        } as u64;
+
+        let relative = random & 0xffffffff;
+        let relative = (relative * relative) >> 32;
+        let relative = area - 1 - ((area * relative) >> 32);
+
+        let origin = if pass != 0 && slice != ARGON2_SYNC_POINTS - 1 {
+            (slice + 1) * segment_length
+        } else {
+            0
+        };
+        let reference = reference_lane * lane_length + (origin + relative as usize) % lane_length;
+
+        let result = compress(&memory[previous], &memory[reference]);
+        let block = &mut memory[current];
+        if pass == 0 {
+            *block = result;
+        } else {
+            for i in 0..ARGON2_BLOCK_WORDS {
+                block.0[i] ^= result.0[i];
+            }
+        }
+    }
+}
+
+
+fn next_addresses(input: &mut Block, addresses: &mut Block) {
+    input.0[6] += 1;
+    let zero = Block::zero();
+    *addresses = compress(&zero, &compress(&zero, input));
+}
+
+
+/// The compression function G.
+fn compress(x: &Block, y: &Block) -> Block {
+    let mut r = Block::zero();
+    for i in 0..ARGON2_BLOCK_WORDS {
+        r.0[i] = x.0[i] ^ y.0[i];
+    }
+    let mut z = r;
+
+    for row in 0..8 {
+        let mut indices = [0; 16];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = 16 * row + i;
+        }
+        permute(&mut z.0, &indices);
+    }
+    for column in 0..8 {
+        let mut indices = [0; 16];
+        for i in 0..8 {
+            indices[2 * i] = 2 * column + 16 * i;
+            indices[2 * i + 1] = 2 * column + 16 * i + 1;
+        }
+        permute(&mut z.0, &indices);
+    }
+
+    for i in 0..ARGON2_BLOCK_WORDS {
+        z.0[i] ^= r.0[i];
+    }
+    z
+}
+
+
+fn permute(v: &mut [u64; ARGON2_BLOCK_WORDS], i: &[usize; 16]) {
+    mix(v, i[0], i[4], i[8], i[12]);
+    mix(v, i[1], i[5], i[9], i[13]);
+    mix(v, i[2], i[6], i[10], i[14]);
+    mix(v, i[3], i[7], i[11], i[15]);
+    mix(v, i[0], i[5], i[10], i[15]);
+    mix(v, i[1], i[6], i[11], i[12]);
+    mix(v, i[2], i[7], i[8], i[13]);
+    mix(v, i[3], i[4], i[9], i[14]);
+}
+
+
+#[inline]
+fn multiply(x: u64, y: u64) -> u64 {
+    x.wrapping_add(y).wrapping_add(2u64.wrapping_mul(x & 0xffffffff).wrapping_mul(y & 0xffffffff))
+}
+
+#[inline]
+fn mix(v: &mut [u64; ARGON2_BLOCK_WORDS], a: usize, b: usize, c: usize, d: usize) {
+    v[a] = multiply(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = multiply(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = multiply(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = multiply(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::utils::hex_decode;
+
+    fn rfc_9106(variant: Argon2Type) -> Vec<u8> {
+        let parameters = Parameters {
+            variant: variant,
+            password: &[0x01; 32],
+            salt: &[0x02; 16],
+            secret: &[0x03; 8],
+            associated: &[0x04; 12],
+            iterations: 3,
+            memory: 32,
+            parallelism: 4,
+        };
+        let mut key = vec![0; 32];
+        derive(&parameters, &mut key).unwrap();
+        key
+    }
+
+    // RFC 9106 sections 5.1, 5.2 and 5.3
+    #[test]
+    fn argon2d() {
+        assert_eq!(rfc_9106(Argon2Type::Argon2d), hex_decode("512b391b6f1162975371d30919734294f868e3be3984f3c1a13a4db9fabe4acb"));
+    }
+
+    #[test]
+    fn argon2i() {
+        assert_eq!(rfc_9106(Argon2Type::Argon2i), hex_decode("c814d9d1dc7f37aa13f0d77f2494bda1c8de6b016dd388d29952a4c4672b6ce8"));
+    }
+
+    #[test]
+    fn argon2id() {
+        assert_eq!(rfc_9106(Argon2Type::Argon2id), hex_decode("0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659"));
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let mut key = [0; 32];
+        assert!(argon2(Argon2Type::Argon2id, b"password", b"somesalt", 1, 7, 1, &mut key).is_err());
+        assert!(argon2(Argon2Type::Argon2id, b"password", b"somesalt", 0, 64, 1, &mut key).is_err());
+    }
+}
\ No newline at end of file
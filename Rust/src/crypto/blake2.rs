@@ -0,0 +1,149 @@
+use super::Hash;
+
+
+const BLAKE2B_INITIAL: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+];
+
+const BLAKE2B_SIGMA: [[usize; 16]; 12] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+];
+
+
+/// BLAKE2b message digest (RFC 7693) without a key.
+#[derive(Clone)]
+pub struct Blake2b {
+    hash: [u64; 8],
+    processed: u128,
+    partial: usize,
+    buffer: [u8; 128],
+    size: usize,
+}
+
+impl Blake2b {
+
+    /// Create a new hash with a digest of 1 to 64 bytes.
+    pub fn with_size(size: usize) -> Self {
+        debug_assert!(size > 0 && size <= 64);
+        let mut hash = BLAKE2B_INITIAL;
+        hash[0] ^= 0x01010000 ^ size as u64;
+        Self {
+            hash: hash,
+            processed: 0,
+            partial: 0,
+            buffer: [0; 128],
+            size: size,
+        }
+    }
+
+    fn process(&mut self, last: bool) {
+        let mut message = [0u64; 16];
+        for (m, raw) in message.iter_mut().zip(self.buffer.chunks_exact(8)) {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(raw);
+            *m = u64::from_le_bytes(word);
+        }
+
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&self.hash);
+        v[8..].copy_from_slice(&BLAKE2B_INITIAL);
+        v[12] ^= self.processed as u64;
+        v[13] ^= (self.processed >> 64) as u64;
+        if last {
+            v[14] = !v[14];
+        }
+
+        for sigma in BLAKE2B_SIGMA.iter() {
+            mix(&mut v, 0, 4, 8, 12, message[sigma[0]], message[sigma[1]]);
+            mix(&mut v, 1, 5, 9, 13, message[sigma[2]], message[sigma[3]]);
+            mix(&mut v, 2, 6, 10, 14, message[sigma[4]], message[sigma[5]]);
+            mix(&mut v, 3, 7, 11, 15, message[sigma[6]], message[sigma[7]]);
+            mix(&mut v, 0, 5, 10, 15, message[sigma[8]], message[sigma[9]]);
+            mix(&mut v, 1, 6, 11, 12, message[sigma[10]], message[sigma[11]]);
+            mix(&mut v, 2, 7, 8, 13, message[sigma[12]], message[sigma[13]]);
+            mix(&mut v, 3, 4, 9, 14, message[sigma[14]], message[sigma[15]]);
+        }
+
+        for i in 0..8 {
+            self.hash[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+}
+
+impl Hash for Blake2b {
+    const BLOCK_SIZE: usize = 128;
+    const DIGEST_SIZE: usize = 64;
+
+    fn new() -> Self {
+        Self::with_size(64)
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        // the final block is processed differently, so always keep one buffered
+        while !data.is_empty() {
+            if self.partial == 128 {
+                self.process(false);
+                self.partial = 0;
+            }
+            let size = core::cmp::min(128 - self.partial, data.len());
+            self.buffer[self.partial..self.partial + size].copy_from_slice(&data[..size]);
+            self.partial += size;
+            self.processed += size as u128;
+            data = &data[size..];
+        }
+    }
+
+    fn finalize(mut self) -> Vec<u8> {
+        for i in self.partial..128 {
+            self.buffer[i] = 0;
+        }
+        self.process(true);
+        let mut digest: Vec<u8> = self.hash.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+        digest.truncate(self.size);
+        digest
+    }
+}
+
+
+#[inline]
+fn mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::utils::hex_decode;
+
+    // RFC 7693 Appendix A
+    #[test]
+    fn rfc_7693() {
+        let mut hash = Blake2b::new();
+        hash.update(b"abc");
+        assert_eq!(
+            hash.finalize(),
+            hex_decode("ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1
+                        7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"),
+        );
+    }
+}
\ No newline at end of file
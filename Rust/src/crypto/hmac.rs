@@ -0,0 +1,98 @@
+use super::Hash;
+
+
+/// Keyed-hash message authentication code.
+#[derive(Clone)]
+pub struct Hmac<H> {
+    inner: H,
+    outer: H,
+}
+
+impl<H> Hmac<H>
+where H: Hash {
+
+    /// Create a new HMAC with the specified key.
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = vec![0u8; H::BLOCK_SIZE];
+        if key.len() <= H::BLOCK_SIZE {
+            block[..key.len()].copy_from_slice(key);
+        } else {
+            let mut hash = H::new();
+            hash.update(key);
+            let digest = hash.finalize();
+            block[..digest.len()].copy_from_slice(&digest);
+        }
+
+        let mut inner = H::new();
+        let xored: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+        inner.update(&xored);
+
+        let mut outer = H::new();
+        let xored: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+        outer.update(&xored);
+
+        Self {
+            inner: inner,
+            outer: outer,
+        }
+    }
+
+    /// Process more of the message.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Get the message authentication code.
+    pub fn finalize(self) -> Vec<u8> {
+        let mut outer = self.outer;
+        outer.update(&self.inner.finalize());
+        outer.finalize()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Sha256, Sha512};
+    use super::super::super::utils::hex_decode;
+
+    fn mac<H: Hash>(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut hmac = Hmac::<H>::new(key);
+        hmac.update(data);
+        hmac.finalize()
+    }
+
+    // RFC 4231 test cases 1, 2 and 6
+    #[test]
+    fn rfc_4231() {
+        let large_key = [0xaa; 131];
+        let vectors: [(&[u8], &[u8], &str, &str); 3] = [
+            (
+                &[0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+                "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cde
+                 daa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554
+                 9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+            ),
+            (
+                &large_key,
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+                "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f352
+                 6b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598",
+            ),
+        ];
+        for (key, data, sha256, sha512) in vectors.iter() {
+            assert_eq!(mac::<Sha256>(key, data), hex_decode(sha256));
+            assert_eq!(mac::<Sha512>(key, data), hex_decode(sha512));
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,111 @@
+use std::io;
+
+use super::Aes;
+
+
+const DEFAULT_IV: [u8; 8] = [0xa6; 8];
+
+
+/// AES Key Wrap (RFC 3394).
+pub fn aes_wrap(kek: &[u8], key: &[u8]) -> io::Result<Vec<u8>> {
+    if key.len() < 16 || !key.len().is_multiple_of(8) {
+        eprintln!("ERROR: Invalid Wrapped Key Size: {}", key.len());
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+    let aes = Aes::new(kek)?;
+    let n = key.len() / 8;
+
+    let mut block = [0u8; 16];
+    block[..8].copy_from_slice(&DEFAULT_IV);
+    let mut wrapped = vec![0; 8];
+    wrapped.extend_from_slice(key);
+
+    for j in 0..6 {
+        for i in 1..=n {
+            block[8..].copy_from_slice(&wrapped[i * 8..(i + 1) * 8]);
+            aes.encrypt_block(&mut block);
+            let t = (n * j + i) as u64;
+            for (b, t) in block[..8].iter_mut().zip(t.to_be_bytes().iter()) {
+                *b ^= t;
+            }
+            wrapped[i * 8..(i + 1) * 8].copy_from_slice(&block[8..]);
+        }
+    }
+
+    wrapped[..8].copy_from_slice(&block[..8]);
+    Ok(wrapped)
+}
+
+/// AES Key Unwrap (RFC 3394), returning `None` if the integrity check fails.
+pub fn aes_unwrap(kek: &[u8], wrapped: &[u8]) -> Option<Vec<u8>> {
+    if wrapped.len() < 24 || !wrapped.len().is_multiple_of(8) {
+        return None;
+    }
+    let aes = Aes::new(kek).ok()?;
+    let n = wrapped.len() / 8 - 1;
+
+    let mut block = [0u8; 16];
+    block[..8].copy_from_slice(&wrapped[..8]);
+    let mut key = wrapped[8..].to_vec();
+
+    for j in (0..6).rev() {
+        for i in (1..=n).rev() {
+            let t = (n * j + i) as u64;
+            for (b, t) in block[..8].iter_mut().zip(t.to_be_bytes().iter()) {
+                *b ^= t;
+            }
+            block[8..].copy_from_slice(&key[(i - 1) * 8..i * 8]);
+            aes.decrypt_block(&mut block);
+            key[(i - 1) * 8..i * 8].copy_from_slice(&block[8..]);
+        }
+    }
+
+    if block[..8] == DEFAULT_IV {
+        Some(key)
+    } else {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::utils::hex_decode;
+
+    // RFC 3394 sections 4.1, 4.3 and 4.6
+    #[test]
+    fn rfc_3394() {
+        let vectors = [
+            (
+                "000102030405060708090a0b0c0d0e0f",
+                "00112233445566778899aabbccddeeff",
+                "1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                "00112233445566778899aabbccddeeff",
+                "64e8c3f9ce0f5ba263e9777905818a2a93c8191e7d6e8ae7",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                "00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f",
+                "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326cbc7f0e71a99f43bfb988b9b7a02dd21",
+            ),
+        ];
+        for (kek, key, wrapped) in vectors.iter() {
+            let (kek, key, wrapped) = (hex_decode(kek), hex_decode(key), hex_decode(wrapped));
+            assert_eq!(aes_wrap(&kek, &key).unwrap(), wrapped);
+            assert_eq!(aes_unwrap(&kek, &wrapped), Some(key));
+        }
+    }
+
+    #[test]
+    fn integrity_check() {
+        let kek = hex_decode("000102030405060708090a0b0c0d0e0f");
+        let mut wrapped = hex_decode("1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5");
+        wrapped[12] ^= 1;
+        assert_eq!(aes_unwrap(&kek, &wrapped), None);
+        assert!(aes_wrap(&kek, &[0; 12]).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,35 @@
+mod aes;
+mod argon2;
+mod blake2;
+mod hmac;
+mod kek;
+mod pbkdf;
+mod sha;
+
+pub use aes::{Aes, Xts};
+pub use argon2::{argon2, Argon2Type};
+pub use blake2::Blake2b;
+pub use hmac::Hmac;
+pub use kek::{aes_unwrap, aes_wrap};
+pub use pbkdf::pbkdf2;
+pub use sha::{Sha1, Sha256, Sha512};
+
+
+/// A trait for message digests.
+pub trait Hash: Clone {
+
+    /// Size of the internal block in bytes.
+    const BLOCK_SIZE: usize;
+
+    /// Size of the digest in bytes.
+    const DIGEST_SIZE: usize;
+
+    /// Create a new empty digest.
+    fn new() -> Self;
+
+    /// Process more of the message.
+    fn update(&mut self, data: &[u8]);
+
+    /// Get the digest of the message.
+    fn finalize(self) -> Vec<u8>;
+}
@@ -0,0 +1,58 @@
+use super::{Hash, Hmac};
+
+
+/// Password-Based Key Derivation Function 2 (RFC 8018).
+pub fn pbkdf2<H>(password: &[u8], salt: &[u8], iterations: u32, key: &mut [u8])
+where H: Hash {
+    let hmac = Hmac::<H>::new(password);
+
+    for (index, chunk) in key.chunks_mut(H::DIGEST_SIZE).enumerate() {
+        let mut init = hmac.clone();
+        init.update(salt);
+        init.update(&(index as u32 + 1).to_be_bytes());
+        let mut hash = init.finalize();
+
+        chunk.copy_from_slice(&hash[..chunk.len()]);
+
+        for _ in 1..iterations {
+            let mut iter = hmac.clone();
+            iter.update(&hash);
+            hash = iter.finalize();
+
+            for (k, h) in chunk.iter_mut().zip(hash.iter()) {
+                *k ^= h;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Sha1;
+    use super::super::super::utils::hex_decode;
+
+    // RFC 6070
+    #[test]
+    fn rfc_6070() {
+        let vectors: [(&[u8], &[u8], u32, &str); 5] = [
+            (b"password", b"salt", 1, "0c60c80f961f0e71f3a9b524af6012062fe037a6"),
+            (b"password", b"salt", 2, "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957"),
+            (b"password", b"salt", 4096, "4b007901b765489abead49d926f721d065a429c1"),
+            (
+                b"passwordPASSWORDpassword",
+                b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                4096,
+                "3d2eec4fe41c849b80c8d83662c0e44a8b291a964cf2f07038",
+            ),
+            (b"pass\0word", b"sa\0lt", 4096, "56fa6aa75548099dcc37d7f03425e0c3"),
+        ];
+        for (password, salt, iterations, expected) in vectors.iter() {
+            let expected = hex_decode(expected);
+            let mut key = vec![0; expected.len()];
+            pbkdf2::<Sha1>(password, salt, *iterations, &mut key);
+            assert_eq!(key, expected);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,358 @@
+use super::Hash;
+
+
+const SHA1_INITIAL: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+const SHA256_INITIAL: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA256_CONSTANT: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const SHA512_INITIAL: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+];
+
+const SHA512_CONSTANT: [u64; 80] = [
+    0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc,
+    0x3956c25bf348b538, 0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118,
+    0xd807aa98a3030242, 0x12835b0145706fbe, 0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235, 0xc19bf174cf692694,
+    0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5,
+    0x983e5152ee66dfab, 0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2, 0xd5a79147930aa725, 0x06ca6351e003826f, 0x142929670a0e6e70,
+    0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed, 0x53380d139d95b3df,
+    0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+    0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30,
+    0xd192e819d6ef5218, 0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8, 0x1e376c085141ab53, 0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373, 0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+    0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b,
+    0xca273eceea26619c, 0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178,
+    0x06f067aa72176fba, 0x0a637dc5a2c898a6, 0x113f9804bef90dae, 0x1b710b35131c471b,
+    0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc, 0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+];
+
+
+/// Buffer partial blocks, calling `process` on each complete block.
+macro_rules! buffer_update {
+    ($self:ident, $data:ident, $chunk:expr) => {
+        {
+            let mut data = $data;
+            $self.processed += data.len() as u128;
+
+            if $self.partial > 0 {
+                let size = core::cmp::min($chunk - $self.partial, data.len());
+                $self.buffer[$self.partial..$self.partial + size].copy_from_slice(&data[..size]);
+                $self.partial += size;
+                data = &data[size..];
+                if $self.partial < $chunk {
+                    return;
+                }
+                let block = $self.buffer;
+                $self.process(&block[..$chunk]);
+                $self.partial = 0;
+            }
+
+            while data.len() >= $chunk {
+                $self.process(&data[..$chunk]);
+                data = &data[$chunk..];
+            }
+
+            $self.buffer[..data.len()].copy_from_slice(data);
+            $self.partial = data.len();
+        }
+    };
+}
+
+/// Append the padding and message length in bits.
+macro_rules! buffer_finalize {
+    ($self:ident, $chunk:expr, $length:expr) => {
+        {
+            let bits = $self.processed * 8;
+            let mut padding = [0u8; 2 * $chunk];
+            padding[0] = 0x80;
+            let size = if $self.partial < $chunk - $length {
+                $chunk - $self.partial
+            } else {
+                2 * $chunk - $self.partial
+            };
+            padding[size - $length..size].copy_from_slice(&bits.to_be_bytes()[16 - $length..]);
+            $self.update(&padding[..size]);
+        }
+    };
+}
+
+
+/// SHA-1 message digest.
+#[derive(Clone)]
+pub struct Sha1 {
+    hash: [u32; 5],
+    processed: u128,
+    partial: usize,
+    buffer: [u8; 64],
+}
+
+impl Sha1 {
+
+    fn process(&mut self, block: &[u8]) {
+        let mut words = [0u32; 80];
+        for i in 0..16 {
+            words[i] = u32::from_be_bytes([block[4 * i], block[4 * i + 1], block[4 * i + 2], block[4 * i + 3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = self.hash;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        self.hash[0] = self.hash[0].wrapping_add(a);
+        self.hash[1] = self.hash[1].wrapping_add(b);
+        self.hash[2] = self.hash[2].wrapping_add(c);
+        self.hash[3] = self.hash[3].wrapping_add(d);
+        self.hash[4] = self.hash[4].wrapping_add(e);
+    }
+}
+
+impl Hash for Sha1 {
+    const BLOCK_SIZE: usize = 64;
+    const DIGEST_SIZE: usize = 20;
+
+    fn new() -> Self {
+        Self {
+            hash: SHA1_INITIAL,
+            processed: 0,
+            partial: 0,
+            buffer: [0; 64],
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        buffer_update!(self, data, 64);
+    }
+
+    fn finalize(mut self) -> Vec<u8> {
+        buffer_finalize!(self, 64, 8);
+        self.hash.iter().flat_map(|word| word.to_be_bytes().to_vec()).collect()
+    }
+}
+
+
+/// SHA-256 message digest.
+#[derive(Clone)]
+pub struct Sha256 {
+    hash: [u32; 8],
+    processed: u128,
+    partial: usize,
+    buffer: [u8; 64],
+}
+
+impl Sha256 {
+
+    fn process(&mut self, block: &[u8]) {
+        let mut words = [0u32; 64];
+        for i in 0..16 {
+            words[i] = u32::from_be_bytes([block[4 * i], block[4 * i + 1], block[4 * i + 2], block[4 * i + 3]]);
+        }
+        for i in 16..64 {
+            let s0 = words[i - 15].rotate_right(7) ^ words[i - 15].rotate_right(18) ^ (words[i - 15] >> 3);
+            let s1 = words[i - 2].rotate_right(17) ^ words[i - 2].rotate_right(19) ^ (words[i - 2] >> 10);
+            words[i] = words[i - 16].wrapping_add(s0).wrapping_add(words[i - 7]).wrapping_add(s1);
+        }
+
+        let mut state = self.hash;
+        for i in 0..64 {
+            let [a, b, c, d, e, f, g, h] = state;
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choose = (e & f) ^ (!e & g);
+            let temp1 = h.wrapping_add(s1).wrapping_add(choose).wrapping_add(SHA256_CONSTANT[i]).wrapping_add(words[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            state = [temp1.wrapping_add(temp2), a, b, c, d.wrapping_add(temp1), e, f, g];
+        }
+
+        for (h, s) in self.hash.iter_mut().zip(state.iter()) {
+            *h = h.wrapping_add(*s);
+        }
+    }
+}
+
+impl Hash for Sha256 {
+    const BLOCK_SIZE: usize = 64;
+    const DIGEST_SIZE: usize = 32;
+
+    fn new() -> Self {
+        Self {
+            hash: SHA256_INITIAL,
+            processed: 0,
+            partial: 0,
+            buffer: [0; 64],
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        buffer_update!(self, data, 64);
+    }
+
+    fn finalize(mut self) -> Vec<u8> {
+        buffer_finalize!(self, 64, 8);
+        self.hash.iter().flat_map(|word| word.to_be_bytes().to_vec()).collect()
+    }
+}
+
+
+/// SHA-512 message digest.
+#[derive(Clone)]
+pub struct Sha512 {
+    hash: [u64; 8],
+    processed: u128,
+    partial: usize,
+    buffer: [u8; 128],
+}
+
+impl Sha512 {
+
+    fn process(&mut self, block: &[u8]) {
+        let mut words = [0u64; 80];
+        for i in 0..16 {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&block[8 * i..8 * i + 8]);
+            words[i] = u64::from_be_bytes(word);
+        }
+        for i in 16..80 {
+            let s0 = words[i - 15].rotate_right(1) ^ words[i - 15].rotate_right(8) ^ (words[i - 15] >> 7);
+            let s1 = words[i - 2].rotate_right(19) ^ words[i - 2].rotate_right(61) ^ (words[i - 2] >> 6);
+            words[i] = words[i - 16].wrapping_add(s0).wrapping_add(words[i - 7]).wrapping_add(s1);
+        }
+
+        let mut state = self.hash;
+        for i in 0..80 {
+            let [a, b, c, d, e, f, g, h] = state;
+            let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+            let choose = (e & f) ^ (!e & g);
+            let temp1 = h.wrapping_add(s1).wrapping_add(choose).wrapping_add(SHA512_CONSTANT[i]).wrapping_add(words[i]);
+            let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            state = [temp1.wrapping_add(temp2), a, b, c, d.wrapping_add(temp1), e, f, g];
+        }
+
+        for (h, s) in self.hash.iter_mut().zip(state.iter()) {
+            *h = h.wrapping_add(*s);
+        }
+    }
+}
+
+impl Hash for Sha512 {
+    const BLOCK_SIZE: usize = 128;
+    const DIGEST_SIZE: usize = 64;
+
+    fn new() -> Self {
+        Self {
+            hash: SHA512_INITIAL,
+            processed: 0,
+            partial: 0,
+            buffer: [0; 128],
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        buffer_update!(self, data, 128);
+    }
+
+    fn finalize(mut self) -> Vec<u8> {
+        buffer_finalize!(self, 128, 16);
+        self.hash.iter().flat_map(|word| word.to_be_bytes().to_vec()).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::utils::hex_decode;
+
+    fn digest<H: Hash>(message: &[u8]) -> Vec<u8> {
+        let mut hash = H::new();
+        hash.update(message);
+        hash.finalize()
+    }
+
+    // FIPS 180-4 examples
+    #[test]
+    fn sha1() {
+        assert_eq!(digest::<Sha1>(b"abc"), hex_decode("a9993e364706816aba3e25717850c26c9cd0d89d"));
+        assert_eq!(
+            digest::<Sha1>(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            hex_decode("84983e441c3bd26ebaae4aa1f95129e5e54670f1"),
+        );
+    }
+
+    #[test]
+    fn sha256() {
+        assert_eq!(
+            digest::<Sha256>(b""),
+            hex_decode("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+        );
+        assert_eq!(
+            digest::<Sha256>(b"abc"),
+            hex_decode("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+        );
+        assert_eq!(
+            digest::<Sha256>(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            hex_decode("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"),
+        );
+    }
+
+    #[test]
+    fn sha512() {
+        assert_eq!(
+            digest::<Sha512>(b"abc"),
+            hex_decode("ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a
+                        2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"),
+        );
+        assert_eq!(
+            digest::<Sha512>(b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu"),
+            hex_decode("8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018
+                        501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909"),
+        );
+    }
+
+    #[test]
+    fn split_updates() {
+        let message = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        let mut hash = Sha256::new();
+        for chunk in message.chunks(7) {
+            hash.update(chunk);
+        }
+        assert_eq!(hash.finalize(), digest::<Sha256>(message));
+    }
+}
\ No newline at end of file
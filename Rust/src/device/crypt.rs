@@ -0,0 +1,143 @@
+use core::cmp;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::Block;
+use super::super::utils::iadd;
+
+
+/// A trait for ciphers where each sector is encrypted independently.
+pub trait SectorCipher {
+
+    /// Get the sector size in bytes.
+    fn sector_size(&self) -> usize;
+
+    /// Decrypt one sector in place.
+    fn decrypt(&self, sector: u64, data: &mut [u8]);
+
+    /// Get the offset of a sector relative to the start of the encrypted data.
+    fn locate(&self, sector: u64) -> u64 {
+        sector * self.sector_size() as u64
+    }
+}
+
+
+/// A Decrypting Block Reader.
+///
+/// Exposes `size` bytes of plaintext stored starting at `offset` in the inner reader.
+pub struct Decryptor<R, C> {
+    inner: R,
+    cipher: C,
+    offset: u64,
+    size: u64,
+    pos: u64,
+    sector: Option<u64>,
+    buf: Box<[u8]>,
+}
+
+impl<R, C> Decryptor<R, C>
+where C: SectorCipher {
+
+    /// Creates a new `Decryptor`.
+    pub fn new(inner: R, cipher: C, offset: u64, size: u64) -> Self {
+        let sector_size = cipher.sector_size();
+        Self {
+            inner: inner,
+            cipher: cipher,
+            offset: offset,
+            size: size,
+            pos: 0,
+            sector: None,
+            buf: vec![0; sector_size].into_boxed_slice(),
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R { &self.inner }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R { &mut self.inner }
+
+    /// Unwraps this `Decryptor`, returning the underlying reader.
+    pub fn into_inner(self) -> R { self.inner }
+
+    /// Gets a reference to the sector cipher.
+    pub fn cipher(&self) -> &C { &self.cipher }
+
+    /// Size of the plaintext in bytes.
+    pub fn len(&self) -> u64 { self.size }
+
+    /// Is there no plaintext?
+    pub fn is_empty(&self) -> bool { self.size == 0 }
+}
+
+impl<R, C> Block for Decryptor<R, C>
+where C: SectorCipher {
+    fn get_block_size(&self) -> io::Result<usize> {
+        Ok(self.cipher.sector_size())
+    }
+}
+
+impl<R, C> Read for Decryptor<R, C>
+where R: Read + Seek, C: SectorCipher {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let sector_size = self.buf.len() as u64;
+        let sector = self.pos / sector_size;
+        let skip = (self.pos % sector_size) as usize;
+        let remaining = self.size - self.pos;
+
+        // decrypt whole sectors straight into large aligned reads
+        if skip == 0 && buf.len() as u64 >= sector_size && remaining >= sector_size {
+            let count = cmp::min(buf.len() as u64, remaining) / sector_size;
+            let length = (count * sector_size) as usize;
+
+            // sectors may be relocated, so read each contiguous run separately
+            let mut start = 0;
+            while start < count {
+                let location = self.cipher.locate(sector + start);
+                let mut end = start + 1;
+                while end < count && self.cipher.locate(sector + end) == location + (end - start) * sector_size {
+                    end += 1;
+                }
+                self.inner.seek(SeekFrom::Start(self.offset + location))?;
+                self.inner.read_exact(&mut buf[(start * sector_size) as usize..(end * sector_size) as usize])?;
+                start = end;
+            }
+            for (i, chunk) in buf[..length].chunks_exact_mut(sector_size as usize).enumerate() {
+                self.cipher.decrypt(sector + i as u64, chunk);
+            }
+
+            self.pos += length as u64;
+            return Ok(length);
+        }
+
+        if self.sector != Some(sector) {
+            self.sector = None;
+            self.inner.seek(SeekFrom::Start(self.offset + self.cipher.locate(sector)))?;
+            self.inner.read_exact(&mut self.buf)?;
+            self.cipher.decrypt(sector, &mut self.buf);
+            self.sector = Some(sector);
+        }
+
+        let size = cmp::min(cmp::min(buf.len(), self.buf.len() - skip) as u64, remaining) as usize;
+        buf[..size].copy_from_slice(&self.buf[skip..skip + size]);
+        self.pos += size as u64;
+        Ok(size)
+    }
+}
+
+impl<R, C> Seek for Decryptor<R, C>
+where R: Read + Seek, C: SectorCipher {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Current(n) => { iadd(self.pos, n) }
+            SeekFrom::Start(n) => { Ok(n) }
+            SeekFrom::End(n) => { iadd(self.size, n) }
+        }?;
+        Ok(self.pos)
+    }
+}
@@ -7,6 +7,12 @@ use std::io::{BufRead, Read, Seek, SeekFrom};
 #[cfg_attr(windows, path = "windows.rs")]
 mod os;
 
+mod crypt;
+mod overlay;
+
+pub use crypt::{Decryptor, SectorCipher};
+pub use overlay::Overlay;
+
 use super::utils::iadd;
 
 
@@ -33,11 +39,9 @@ impl<R> Device<R> {
 
     /// Creates a new `BlockDevice` with the specified block size.
     pub fn with_block_size(inner: R, block_size: usize) -> Self {
-        let mut buffer = Vec::with_capacity(block_size);
-        buffer.resize(block_size, 0);
         Self {
             inner: inner,
-            buf: buffer.into_boxed_slice(),
+            buf: vec![0; block_size].into_boxed_slice(),
             pos: 0,
             cap: 0,
         }
@@ -57,9 +61,9 @@ impl<R> Device<R> {
         &self.buf[self.pos..self.cap]
     }
 
-    /// Invalidates all data in the internal buffer.
+    /// Invalidates all data in the internal buffer, eg. after writing to the underlying reader.
     #[inline]
-    fn discard_buffer(&mut self) {
+    pub fn discard_buffer(&mut self) {
         self.pos = 0;
         self.cap = 0;
     }
@@ -130,7 +134,7 @@ where R: Read {
 impl<R> Seek for Device<R>
 where R: Read + Seek {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        let maximum = self.inner.seek(SeekFrom::Current(0))?;
+        let maximum = self.inner.stream_position()?;
         let minimum = maximum - self.cap as u64;
         let current = minimum + self.pos as u64;
 
@@ -182,9 +186,9 @@ where R: Read {
     fn new(mut inner: R) -> io::Result<Self>
     where Self: Sized {
         let mut header: [u8; 512] = [0; 512];
-        inner.read_exact(&mut header).or_else(|err| {
+        inner.read_exact(&mut header).map_err(|err| {
             eprintln!("ERROR: Read Failed: {}", err);
-            Err(err)
+            err
         }).and_then(|_| {
 
             debug_xxd!(&header, 0);
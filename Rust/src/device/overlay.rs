@@ -0,0 +1,123 @@
+use core::cmp;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use super::Block;
+use super::super::utils::iadd;
+
+
+/// A reader with a layer of changes kept in memory.
+///
+/// Writes go to the overlay, never to the inner reader.  Reads see the overlay where it has been
+/// written and the inner reader everywhere else.
+pub struct Overlay<R> {
+    inner: R,
+    block_size: u64,
+    blocks: BTreeMap<u64, Box<[u8]>>,   // by block number
+    pos: u64,
+}
+
+impl<R> Overlay<R> {
+
+    /// Creates a new `Overlay` which keeps changes in blocks of `block_size` bytes.
+    pub fn with_block_size(inner: R, block_size: usize) -> Self {
+        Self {
+            inner: inner,
+            block_size: block_size as u64,
+            blocks: BTreeMap::new(),
+            pos: 0,
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R { &self.inner }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R { &mut self.inner }
+
+    /// Unwraps this `Overlay`, returning the underlying reader and discarding any changes.
+    pub fn into_inner(self) -> R { self.inner }
+
+    /// Has anything been written?
+    pub fn is_empty(&self) -> bool { self.blocks.is_empty() }
+
+    /// Discard all changes.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+}
+
+impl<R> Block for Overlay<R> {
+    fn get_block_size(&self) -> io::Result<usize> {
+        Ok(self.block_size as usize)
+    }
+}
+
+impl<R> Read for Overlay<R>
+where R: Read + Seek {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let block = self.pos / self.block_size;
+        let skip = (self.pos % self.block_size) as usize;
+        if let Some(data) = self.blocks.get(&block) {
+            let count = cmp::min(buf.len(), data.len() - skip);
+            buf[..count].copy_from_slice(&data[skip..skip + count]);
+            self.pos += count as u64;
+            return Ok(count);
+        }
+
+        // stop at the next block which has been written
+        let count = match self.blocks.range(block..).next() {
+            Some((next, _)) => cmp::min(buf.len() as u64, next * self.block_size - self.pos) as usize,
+            None => buf.len(),
+        };
+        self.inner.seek(SeekFrom::Start(self.pos))?;
+        let nread = self.inner.read(&mut buf[..count])?;
+        self.pos += nread as u64;
+        Ok(nread)
+    }
+}
+
+impl<R> Write for Overlay<R>
+where R: Read + Seek {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let block = self.pos / self.block_size;
+        let skip = (self.pos % self.block_size) as usize;
+        if !self.blocks.contains_key(&block) {
+            let mut data = vec![0; self.block_size as usize].into_boxed_slice();
+            self.inner.seek(SeekFrom::Start(block * self.block_size))?;
+            let mut filled = 0;
+            while filled < data.len() {
+                match self.inner.read(&mut data[filled..])? {
+                    0 => break,
+                    n => filled += n,
+                }
+            }
+            self.blocks.insert(block, data);
+        }
+        let data = self.blocks.get_mut(&block).unwrap();
+        let count = cmp::min(buf.len(), data.len() - skip);
+        data[skip..skip + count].copy_from_slice(&buf[..count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<R> Seek for Overlay<R>
+where R: Seek {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Current(n) => { iadd(self.pos, n) }
+            SeekFrom::Start(n) => { Ok(n) }
+            SeekFrom::End(n) => {
+                let end = self.inner.seek(SeekFrom::End(0))?;
+                iadd(end, n)
+            }
+        }?;
+        Ok(self.pos)
+    }
+}
@@ -1,8 +1,9 @@
 use std::fs::File;
+use std::io;
 use std::io::Result;
-use std::os::unix::io::IntoRawFd;
+use std::os::unix::io::AsRawFd;
 
-use nix::sys::ioctl;
+use nix::{convert_ioctl_res, ioc, ioctl, ior};
 
 use super::Block;
 
@@ -10,17 +11,17 @@ use super::Block;
 const BLKBSZGET_TYPE: u8 = 0x12;
 const BLKBSZGET_NUMBER: u8 = 112;
 
-ioctl::ioctl!(read ioctl_blkbszget with BLKBSZGET_TYPE, BLKBSZGET_NUMBER; usize);
+ioctl!(read ioctl_blkbszget with BLKBSZGET_TYPE, BLKBSZGET_NUMBER; usize);
 
 
 impl Block for File {
     fn get_block_size(&self) -> Result<usize> {
-        let fd = self.into_raw_fd();
+        let fd = self.as_raw_fd();
         let mut block_size: usize = 0;
 
-        ioctl_blkbszget(fd, &mut block_size).or_else(|err| {
+        unsafe { ioctl_blkbszget(fd, &mut block_size) }.map(|_| block_size).map_err(|err| {
             eprintln!("IOCTL BLKBSZGET Failed: {}\n", err);
-            Err(err)
+            io::Error::other(err)
         })
     }
 }
\ No newline at end of file
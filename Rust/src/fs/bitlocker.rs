@@ -0,0 +1,767 @@
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::super::crypto::{Aes, Hash, Sha256, Xts};
+use super::super::device::{Decryptor, SectorCipher, Volume};
+
+
+const FVE_SIGNATURE: &[u8; 8] = b"-FVE-FS-";
+
+// 4967d63b-2e29-4ad8-8399-f6a339e3d001
+const FVE_IDENTIFIER: [u8; 16] = [
+    0x3b, 0xd6, 0x67, 0x49, 0x29, 0x2e, 0xd8, 0x4a, 0x83, 0x99, 0xf6, 0xa3, 0x39, 0xe3, 0xd0, 0x01,
+];
+
+// space reserved for each copy of the metadata, read back as zeros
+const FVE_METADATA_REGION_SIZE: u64 = 0x10000;
+
+// Vista leaves the NTFS boot area unencrypted
+const FVE_VISTA_PLAIN_SECTORS: u64 = 16;
+
+const FVE_STRETCH_COUNT: u64 = 0x100000;
+
+const FVE_ENTRY_VMK: u16 = 0x0002;
+const FVE_ENTRY_FVEK: u16 = 0x0003;
+const FVE_ENTRY_DESCRIPTION: u16 = 0x0007;
+
+const FVE_VALUE_KEY: u16 = 0x0001;
+const FVE_VALUE_STRING: u16 = 0x0002;
+const FVE_VALUE_STRETCH_KEY: u16 = 0x0003;
+const FVE_VALUE_USE_KEY: u16 = 0x0004;
+const FVE_VALUE_AES_CCM_KEY: u16 = 0x0005;
+const FVE_VALUE_VMK: u16 = 0x0008;
+const FVE_VALUE_EXTERNAL_KEY: u16 = 0x0009;
+
+const FVE_PROTECTION_CLEAR_KEY: u16 = 0x0000;
+const FVE_PROTECTION_STARTUP_KEY: u16 = 0x0200;
+const FVE_PROTECTION_RECOVERY_PASSWORD: u16 = 0x0800;
+const FVE_PROTECTION_PASSWORD: u16 = 0x2000;
+
+const FVE_METHOD_AES_128_CBC_DIFFUSER: u16 = 0x8000;
+const FVE_METHOD_AES_256_CBC_DIFFUSER: u16 = 0x8001;
+const FVE_METHOD_AES_128_CBC: u16 = 0x8002;
+const FVE_METHOD_AES_256_CBC: u16 = 0x8003;
+const FVE_METHOD_AES_128_XTS: u16 = 0x8004;
+const FVE_METHOD_AES_256_XTS: u16 = 0x8005;
+
+const DIFFUSER_A_ROTATE: [u32; 4] = [9, 0, 13, 0];
+const DIFFUSER_B_ROTATE: [u32; 4] = [0, 10, 0, 25];
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct FVE_BLOCK_HEADER {
+    Signature: [u8; 8],         // '-FVE-FS-'
+    Size: u16,
+    Version: u16,               // 1 = Vista, 2 = Windows 7 and later
+    Unknown0: u16,
+    Unknown1: u16,
+    EncryptedVolumeSize: u64,   // Bytes
+    Unknown2: u32,
+    VolumeHeaderSectors: u32,
+    MetadataOffsets: [u64; 3],  // Bytes
+    VolumeHeaderOffset: u64,    // Bytes, or the MFT mirror cluster on Vista
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct FVE_METADATA_HEADER {
+    MetadataSize: u32,          // including this header
+    Version: u32,               // 1
+    HeaderSize: u32,            // 48
+    MetadataSizeCopy: u32,
+    VolumeIdentifier: [u8; 16],
+    NextNonceCounter: u32,
+    EncryptionMethod: u16,      // FVE_METHOD_*
+    Unknown: u16,
+    CreationTime: u64,
+}
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn format_guid(guid: &[u8; 16]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        read_u32(guid, 0), read_u16(guid, 4), read_u16(guid, 6),
+        guid[8], guid[9], guid[10], guid[11], guid[12], guid[13], guid[14], guid[15]
+    )
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    let mut hash = Sha256::new();
+    hash.update(data);
+    hash.finalize()
+}
+
+/// Convert a 48 digit recovery password into its 16 byte key.
+fn recovery_key(password: &str) -> Option<[u8; 16]> {
+    let groups: Vec<&str> = password.trim().split('-').collect();
+    if groups.len() != 8 {
+        return None;
+    }
+
+    let mut key = [0u8; 16];
+    for (i, group) in groups.iter().enumerate() {
+        if group.len() != 6 || !group.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let value: u32 = group.parse().ok()?;
+        if !value.is_multiple_of(11) || value / 11 > 0xffff {
+            return None;
+        }
+        key[2 * i..2 * i + 2].copy_from_slice(&((value / 11) as u16).to_le_bytes());
+    }
+    Some(key)
+}
+
+/// Strengthen a password hash with the salt of a stretch key.
+fn stretch_key(password_hash: &[u8], salt: &[u8]) -> Vec<u8> {
+    // last hash, password hash, salt, count
+    let mut state = [0u8; 88];
+    state[32..64].copy_from_slice(&password_hash[..32]);
+    state[64..80].copy_from_slice(&salt[..16]);
+    for count in 0..FVE_STRETCH_COUNT {
+        state[80..88].copy_from_slice(&count.to_le_bytes());
+        let hash = sha256(&state);
+        state[..32].copy_from_slice(&hash);
+    }
+    state[..32].to_vec()
+}
+
+
+/// A metadata entry, which may contain nested entries.
+#[derive(Clone, Debug)]
+struct Entry {
+    entry_type: u16,
+    value_type: u16,
+    data: Vec<u8>,
+}
+
+impl Entry {
+
+    fn parse_all(mut data: &[u8]) -> Vec<Entry> {
+        let mut entries = Vec::new();
+        while data.len() >= 8 {
+            let size = read_u16(data, 0) as usize;
+            if size < 8 || size > data.len() {
+                break;
+            }
+            entries.push(Entry {
+                entry_type: read_u16(data, 2),
+                value_type: read_u16(data, 4),
+                data: data[8..size].to_vec(),
+            });
+            data = &data[size..];
+        }
+        entries
+    }
+
+    /// Parse the nested entries.
+    fn children(&self) -> Vec<Entry> {
+        let start = match self.value_type {
+            FVE_VALUE_STRETCH_KEY => 20,
+            FVE_VALUE_USE_KEY => 4,
+            FVE_VALUE_VMK => 28,
+            FVE_VALUE_EXTERNAL_KEY => 24,
+            _ => return Vec::new(),
+        };
+        Entry::parse_all(self.data.get(start..).unwrap_or(&[]))
+    }
+
+    fn child(&self, value_type: u16) -> Option<Entry> {
+        self.children().into_iter().find(|e| e.value_type == value_type)
+    }
+
+    fn identifier(&self) -> Option<&[u8]> {
+        match self.value_type {
+            FVE_VALUE_VMK | FVE_VALUE_EXTERNAL_KEY => self.data.get(..16),
+            _ => None,
+        }
+    }
+
+    fn protection(&self) -> Option<u16> {
+        match self.value_type {
+            FVE_VALUE_VMK if self.data.len() >= 28 => Some(read_u16(&self.data, 26)),
+            _ => None,
+        }
+    }
+
+    /// Get the key material of an unencrypted key.
+    fn key(&self) -> Option<&[u8]> {
+        match self.value_type {
+            FVE_VALUE_KEY => self.data.get(4..),
+            _ => None,
+        }
+    }
+
+    fn string(&self) -> Option<String> {
+        match self.value_type {
+            FVE_VALUE_STRING => {
+                let units: Vec<u16> = self.data.chunks_exact(2).map(|c| read_u16(c, 0))
+                    .take_while(|&c| c != 0).collect();
+                String::from_utf16(&units).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Decrypt an AES-CCM encrypted key, returning the encryption method and key material.
+    fn decrypt(&self, key: &[u8]) -> Option<(u16, Vec<u8>)> {
+        if self.value_type != FVE_VALUE_AES_CCM_KEY || self.data.len() < 28 {
+            return None;
+        }
+        let aes = Aes::new(key).ok()?;
+        let mut decrypted = self.data[28..].to_vec();
+        if !aes.ccm_decrypt(&self.data[..12], &[], &self.data[12..28], &mut decrypted) {
+            return None;
+        }
+
+        let entry = Entry::parse_all(&decrypted).into_iter().next()?;
+        let material = entry.key()?;
+        Some((read_u16(&entry.data, 0), material.to_vec()))
+    }
+}
+
+
+/// Key used to unlock a BitLocker volume.
+pub enum BitLockerKey<'a> {
+    /// A user password or a 48 digit recovery password.
+    Password(&'a str),
+    /// The contents of a startup key (.BEK) file.
+    StartupKey(&'a [u8]),
+    /// The unprotected key left by suspending BitLocker.
+    ClearKey,
+}
+
+
+#[derive(Clone)]
+enum EncryptionMode {
+    Cbc(Aes),
+    CbcDiffuser(Aes, Aes),
+    Xts(Xts),
+}
+
+/// BitLocker sector cipher, also handling the relocated boot sectors and metadata.
+#[derive(Clone)]
+pub struct BitLockerCipher {
+    mode: EncryptionMode,
+    sector_size: usize,
+    version: u16,
+    encrypted_size: u64,
+    header_offset: u64,
+    header_size: u64,
+    mft_mirror: u64,
+    reserved: Vec<(u64, u64)>,
+}
+
+impl BitLockerCipher {
+
+    fn encryption_mode(method: u16, key: &[u8], sector_size: usize) -> io::Result<EncryptionMode> {
+        let size = match method {
+            FVE_METHOD_AES_128_CBC_DIFFUSER | FVE_METHOD_AES_256_CBC_DIFFUSER => 64,
+            FVE_METHOD_AES_128_CBC | FVE_METHOD_AES_128_XTS => 16,
+            FVE_METHOD_AES_256_CBC | FVE_METHOD_AES_256_XTS => 32,
+            _ => return invalid(&format!("Unsupported Encryption Method: {:#06x}", method)),
+        };
+        if key.len() < size || sector_size < 512 || !sector_size.is_multiple_of(16) {
+            return invalid("Invalid Full Volume Encryption Key");
+        }
+
+        Ok(match method {
+            FVE_METHOD_AES_128_CBC_DIFFUSER => EncryptionMode::CbcDiffuser(Aes::new(&key[..16])?, Aes::new(&key[32..48])?),
+            FVE_METHOD_AES_256_CBC_DIFFUSER => EncryptionMode::CbcDiffuser(Aes::new(&key[..32])?, Aes::new(&key[32..64])?),
+            FVE_METHOD_AES_128_CBC | FVE_METHOD_AES_256_CBC => EncryptionMode::Cbc(Aes::new(&key[..size])?),
+            _ => EncryptionMode::Xts(Xts::new(&key[..2 * size])?),
+        })
+    }
+
+    fn diffuser_a_decrypt(words: &mut [u32]) {
+        let n = words.len();
+        for _ in 0..5 {
+            for i in 0..n {
+                words[i] = words[i].wrapping_add(
+                    words[(i + n - 2) % n] ^ words[(i + n - 5) % n].rotate_left(DIFFUSER_A_ROTATE[i % 4]));
+            }
+        }
+    }
+
+    fn diffuser_b_decrypt(words: &mut [u32]) {
+        let n = words.len();
+        for _ in 0..3 {
+            for i in 0..n {
+                words[i] = words[i].wrapping_add(
+                    words[(i + 2) % n] ^ words[(i + 5) % n].rotate_left(DIFFUSER_B_ROTATE[i % 4]));
+            }
+        }
+    }
+}
+
+impl SectorCipher for BitLockerCipher {
+
+    fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn locate(&self, sector: u64) -> u64 {
+        let offset = sector * self.sector_size as u64;
+        if offset < self.header_size {
+            self.header_offset + offset
+        } else {
+            offset
+        }
+    }
+
+    fn decrypt(&self, sector: u64, data: &mut [u8]) {
+        let logical = sector * self.sector_size as u64;
+        if self.reserved.iter().any(|&(start, size)| logical >= start && logical < start + size) {
+            for b in data.iter_mut() {
+                *b = 0;
+            }
+            return;
+        }
+
+        if self.version == 1 && sector < FVE_VISTA_PLAIN_SECTORS {
+            if sector == 0 {
+                data[3..11].copy_from_slice(b"NTFS    ");
+                data[0x38..0x40].copy_from_slice(&self.mft_mirror.to_le_bytes());
+            }
+            return;
+        }
+
+        let offset = self.locate(sector);
+        if offset >= self.encrypted_size {
+            return;
+        }
+
+        let mut block = [0u8; 16];
+        block[..8].copy_from_slice(&offset.to_le_bytes());
+
+        match &self.mode {
+            EncryptionMode::Cbc(aes) => {
+                let mut iv = block;
+                aes.encrypt_block(&mut iv);
+                aes.cbc_decrypt(&iv, data);
+            }
+            EncryptionMode::CbcDiffuser(aes, tweak) => {
+                let mut iv = block;
+                aes.encrypt_block(&mut iv);
+                aes.cbc_decrypt(&iv, data);
+
+                let mut words: Vec<u32> = data.chunks_exact(4).map(|c| read_u32(c, 0)).collect();
+                Self::diffuser_b_decrypt(&mut words);
+                Self::diffuser_a_decrypt(&mut words);
+
+                let mut sector_key = [0u8; 32];
+                sector_key[..16].copy_from_slice(&block);
+                sector_key[16..].copy_from_slice(&block);
+                sector_key[31] = 0x80;
+                tweak.encrypt_block(&mut sector_key[..16]);
+                tweak.encrypt_block(&mut sector_key[16..]);
+
+                for (i, (chunk, word)) in data.chunks_exact_mut(4).zip(words.iter()).enumerate() {
+                    chunk.copy_from_slice(&word.to_le_bytes());
+                    for (j, b) in chunk.iter_mut().enumerate() {
+                        *b ^= sector_key[(4 * i + j) % 32];
+                    }
+                }
+            }
+            EncryptionMode::Xts(xts) => {
+                let mut tweak = [0u8; 16];
+                tweak[..8].copy_from_slice(&(offset / self.sector_size as u64).to_le_bytes());
+                xts.decrypt(&tweak, data);
+            }
+        }
+    }
+}
+
+
+/// BitLocker Drive Encryption volume.
+pub struct BitLocker<R> {
+    inner: R,
+    version: u16,
+    sector_size: usize,
+    metadata_offsets: [u64; 3],
+    block: Option<FVE_BLOCK_HEADER>,
+    identifier: [u8; 16],
+    method: u16,
+    entries: Vec<Entry>,
+}
+
+impl<R> BitLocker<R> {
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R { &self.inner }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R { &mut self.inner }
+
+    /// Unwraps this `BitLocker`, returning the underlying reader.
+    pub fn into_inner(self) -> R { self.inner }
+
+    /// Metadata version, 1 for Vista or 2 for Windows 7 and later.
+    pub fn version(&self) -> u16 { self.version }
+
+    /// Volume identifier GUID.
+    pub fn identifier(&self) -> String { format_guid(&self.identifier) }
+
+    /// Volume description, such as the computer name and date.
+    pub fn description(&self) -> Option<String> {
+        self.entries.iter().find(|e| e.entry_type == FVE_ENTRY_DESCRIPTION).and_then(Entry::string)
+    }
+
+    /// Whether the volume can be unlocked without a key.
+    pub fn has_clear_key(&self) -> bool {
+        self.volume_master_keys(FVE_PROTECTION_CLEAR_KEY).next().is_some()
+    }
+
+    fn volume_master_keys(&self, protection: u16) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(move |e| {
+            e.entry_type == FVE_ENTRY_VMK && e.protection() == Some(protection)
+        })
+    }
+
+    /// Decrypt the volume master key with a user or recovery password.
+    fn open_password(&self, password: &str) -> Option<Vec<u8>> {
+        let (protection, password_hash) = match recovery_key(password) {
+            Some(key) => (FVE_PROTECTION_RECOVERY_PASSWORD, sha256(&key)),
+            None => {
+                let utf16: Vec<u8> = password.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+                (FVE_PROTECTION_PASSWORD, sha256(&sha256(&utf16)))
+            }
+        };
+
+        for vmk in self.volume_master_keys(protection) {
+            let stretch = match vmk.child(FVE_VALUE_STRETCH_KEY) {
+                Some(stretch) if stretch.data.len() >= 20 => stretch,
+                _ => continue,
+            };
+            let key = stretch_key(&password_hash, &stretch.data[4..20]);
+            if let Some((_, vmk)) = vmk.child(FVE_VALUE_AES_CCM_KEY).and_then(|e| e.decrypt(&key)) {
+                return Some(vmk);
+            }
+        }
+        None
+    }
+
+    /// Decrypt the volume master key with the external key from a startup key file.
+    fn open_startup_key(&self, bek: &[u8]) -> Option<Vec<u8>> {
+        if bek.len() < 48 {
+            return None;
+        }
+        let size = (read_u32(bek, 0) as usize).min(bek.len());
+        let start = read_u32(bek, 8) as usize;
+
+        for external in Entry::parse_all(bek.get(start..size)?).iter().filter(|e| e.value_type == FVE_VALUE_EXTERNAL_KEY) {
+            let key = match external.child(FVE_VALUE_KEY) {
+                Some(key) => key,
+                None => continue,
+            };
+            for vmk in self.volume_master_keys(FVE_PROTECTION_STARTUP_KEY) {
+                if vmk.identifier() != external.identifier() {
+                    continue;
+                }
+                if let Some((_, vmk)) = vmk.child(FVE_VALUE_AES_CCM_KEY).and_then(|e| e.decrypt(key.key()?)) {
+                    return Some(vmk);
+                }
+            }
+        }
+        None
+    }
+
+    /// Decrypt the volume master key with the key stored next to it.
+    fn open_clear_key(&self) -> Option<Vec<u8>> {
+        for vmk in self.volume_master_keys(FVE_PROTECTION_CLEAR_KEY) {
+            let key = match vmk.child(FVE_VALUE_KEY) {
+                Some(key) => key,
+                None => continue,
+            };
+            if let Some((_, vmk)) = vmk.child(FVE_VALUE_AES_CCM_KEY).and_then(|e| e.decrypt(key.key()?)) {
+                return Some(vmk);
+            }
+        }
+        None
+    }
+}
+
+impl<R> BitLocker<R>
+where R: Read + Seek {
+
+    /// Unlock the volume, exposing the decrypted file system.
+    pub fn unlock(mut self, key: &BitLockerKey) -> io::Result<Decryptor<R, BitLockerCipher>> {
+        let vmk = match key {
+            BitLockerKey::Password(password) => self.open_password(password),
+            BitLockerKey::StartupKey(bek) => self.open_startup_key(bek),
+            BitLockerKey::ClearKey => self.open_clear_key(),
+        };
+        let vmk = match vmk {
+            Some(vmk) => vmk,
+            None => {
+                eprintln!("ERROR: No Volume Master Key available with this key");
+                return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+            }
+        };
+        debug!("Unlocked Volume Master Key");
+
+        let fvek = self.entries.iter()
+            .filter(|e| e.entry_type == FVE_ENTRY_FVEK)
+            .find_map(|e| e.decrypt(&vmk));
+        let (method, fvek) = match fvek {
+            Some(fvek) => fvek,
+            None => return invalid("Unable to decrypt the Full Volume Encryption Key"),
+        };
+        let method = if method & 0x8000 != 0 { method } else { self.method };
+        debug!("Encryption Method: {:#06x}", method);
+
+        let block = match self.block {
+            Some(block) => block,
+            None => return invalid("Missing FVE Metadata"),
+        };
+        let size = self.inner.seek(SeekFrom::End(0))?;
+
+        let mut reserved: Vec<(u64, u64)> = self.metadata_offsets.iter()
+            .filter(|&&offset| offset != 0)
+            .map(|&offset| (offset, FVE_METADATA_REGION_SIZE))
+            .collect();
+        let (encrypted_size, header_offset, header_size) = if self.version == 1 {
+            (size, 0, 0)
+        } else {
+            let header_size = block.VolumeHeaderSectors as u64 * self.sector_size as u64;
+            reserved.push((block.VolumeHeaderOffset, header_size));
+            (block.EncryptedVolumeSize, block.VolumeHeaderOffset, header_size)
+        };
+
+        let cipher = BitLockerCipher {
+            mode: BitLockerCipher::encryption_mode(method, &fvek, self.sector_size)?,
+            sector_size: self.sector_size,
+            version: self.version,
+            encrypted_size: encrypted_size,
+            header_offset: header_offset,
+            header_size: header_size,
+            mft_mirror: block.VolumeHeaderOffset,
+            reserved: reserved,
+        };
+        Ok(Decryptor::new(self.inner, cipher, 0, size))
+    }
+
+    fn load_metadata(&mut self, offset: u64) -> io::Result<()> {
+        let inner = &mut self.inner;
+        let block = read_struct!(FVE_BLOCK_HEADER, inner, offset, core::mem::size_of::<FVE_BLOCK_HEADER>())?;
+        if &block.Signature != FVE_SIGNATURE {
+            return invalid(&format!("Invalid FVE Metadata Block at {}", offset));
+        }
+
+        let header_offset = offset + core::mem::size_of::<FVE_BLOCK_HEADER>() as u64;
+        let header = read_struct!(FVE_METADATA_HEADER, inner, header_offset, core::mem::size_of::<FVE_METADATA_HEADER>())?;
+        let size = header.MetadataSize as usize;
+        if header.Version != 1 || size < header.HeaderSize as usize || size > FVE_METADATA_REGION_SIZE as usize {
+            return invalid(&format!("Invalid FVE Metadata Header at {}", header_offset));
+        }
+
+        let mut metadata = vec![0; size];
+        inner.seek(SeekFrom::Start(header_offset))?;
+        inner.read_exact(&mut metadata)?;
+
+        self.entries = Entry::parse_all(&metadata[header.HeaderSize as usize..]);
+        self.identifier = header.VolumeIdentifier;
+        self.method = header.EncryptionMethod;
+        if self.version == 1 {
+            // Vista only records the first offset in the volume header
+            self.metadata_offsets = block.MetadataOffsets;
+        }
+        self.block = Some(block);
+
+        debug!("{:#?}\n{:#?}\n{:#?}", block, header, self.entries);
+        Ok(())
+    }
+}
+
+impl<R> Volume<R> for BitLocker<R>
+where R: Read + Seek {
+
+    fn is_supported(header: &[u8; 512]) -> bool {
+        &header[3..11] == FVE_SIGNATURE
+    }
+
+    fn with_header(inner: R, header: &[u8; 512]) -> io::Result<Self> {
+        let sector_size = read_u16(header, 0x0b) as usize;
+        let cluster_size = sector_size as u64 * header[0x0d] as u64;
+
+        let mut metadata_offsets = [0u64; 3];
+        let version = if header[0xa0..0xb0] == FVE_IDENTIFIER {
+            for (i, offset) in metadata_offsets.iter_mut().enumerate() {
+                let start = 0xb0 + 8 * i;
+                let mut raw = [0u8; 8];
+                raw.copy_from_slice(&header[start..start + 8]);
+                *offset = u64::from_le_bytes(raw);
+            }
+            2
+        } else {
+            let mut raw = [0u8; 8];
+            raw.copy_from_slice(&header[0x38..0x40]);
+            metadata_offsets[0] = u64::from_le_bytes(raw) * cluster_size;
+            1
+        };
+        debug!("BitLocker Version: {}\nMetadata Offsets: {:?}", version, metadata_offsets);
+
+        let mut bitlocker = Self {
+            inner: inner,
+            version: version,
+            sector_size: sector_size,
+            metadata_offsets: metadata_offsets,
+            block: None,
+            identifier: [0; 16],
+            method: 0,
+            entries: Vec::new(),
+        };
+
+        bitlocker.refresh()?;
+        Ok(bitlocker)
+    }
+
+    fn refresh(&mut self) -> io::Result<()> {
+        // fall back on the copies when the first block is damaged
+        for offset in self.metadata_offsets {
+            if offset != 0 && self.load_metadata(offset).is_ok() {
+                return Ok(());
+            }
+        }
+        invalid("No valid FVE Metadata Blocks")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use super::super::super::utils::hex_decode;
+
+    fn entry(entry_type: u16, value_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut entry = Vec::new();
+        for field in [8 + data.len() as u16, entry_type, value_type, 1].iter() {
+            entry.extend_from_slice(&field.to_le_bytes());
+        }
+        entry.extend_from_slice(data);
+        entry
+    }
+
+    /// A Windows 7 volume with a clear key, whose FVEK is 40..5f for AES-128-XTS.
+    ///
+    /// The boot sector is relocated to 0x1000 and the metadata is at 0x2000, only the first 32
+    /// bytes of each encrypted sector are kept.
+    fn bitlocker_fixture() -> Vec<u8> {
+        let mut image = vec![0u8; 0x12000];
+        image[3..11].copy_from_slice(FVE_SIGNATURE);
+        image[0x0b..0x0d].copy_from_slice(&512u16.to_le_bytes());
+        image[0x0d] = 8;
+        image[0xa0..0xb0].copy_from_slice(&FVE_IDENTIFIER);
+        image[0xb0..0xb8].copy_from_slice(&0x2000u64.to_le_bytes());
+        image[0x200..0x220].copy_from_slice(&hex_decode("f862db1ba4f783eddb06a042c23e484e03907aa8b2aabf4a8afc678c4f6ac66d"));
+        image[0x1000..0x1020].copy_from_slice(&hex_decode("08d92f8ad8248de51f58ee88faf0d379b43a52dc931e25ba54e772683df21417"));
+
+        // the VMK is 20..3f, protected by the clear key 00..1f
+        let mut vmk = vec![0xaa; 16];
+        vmk.extend_from_slice(&[0; 12]);
+        let clear_key: Vec<u8> = (0..36).map(|i| if i < 4 { [0, 0x20, 0, 0][i] } else { i as u8 - 4 }).collect();
+        vmk.extend_from_slice(&entry(0, FVE_VALUE_KEY, &clear_key));
+        vmk.extend_from_slice(&entry(0, FVE_VALUE_AES_CCM_KEY, &hex_decode("
+            0011223344556677010000008b45917511fe94759b2b885a71bd1a26a9a1e557725aaaf5b083bcbb173b895e
+            d91ba97aa2c21911d84e15cdaebffc6e2bfd025f92dce4e37b48d725
+        ")));
+        let description: Vec<u8> = "warped-drive".encode_utf16().chain(Some(0)).flat_map(|c| c.to_le_bytes().to_vec()).collect();
+        let mut entries = entry(FVE_ENTRY_DESCRIPTION, FVE_VALUE_STRING, &description);
+        entries.extend_from_slice(&entry(FVE_ENTRY_VMK, FVE_VALUE_VMK, &vmk));
+        entries.extend_from_slice(&entry(FVE_ENTRY_FVEK, FVE_VALUE_AES_CCM_KEY, &hex_decode("
+            0011223344556677020000003745c7657d69f125705db984cbee7227c21b6731399f825a17ad92e8fbb7586a
+            b89b9b4114b610dac85b854a927cafa9a0daa1c98790724e6ed8cce8
+        ")));
+
+        let block = &mut image[0x2000..];
+        block[0..8].copy_from_slice(FVE_SIGNATURE);
+        block[10..12].copy_from_slice(&2u16.to_le_bytes());
+        block[16..24].copy_from_slice(&0x2000u64.to_le_bytes());
+        block[28..32].copy_from_slice(&1u32.to_le_bytes());
+        block[32..40].copy_from_slice(&0x2000u64.to_le_bytes());
+        block[56..64].copy_from_slice(&0x1000u64.to_le_bytes());
+
+        let header = &mut block[64..];
+        let size = 48 + entries.len() as u32;
+        header[0..4].copy_from_slice(&size.to_le_bytes());
+        header[4..8].copy_from_slice(&1u32.to_le_bytes());
+        header[8..12].copy_from_slice(&48u32.to_le_bytes());
+        header[12..16].copy_from_slice(&size.to_le_bytes());
+        header[16..32].copy_from_slice(&[0x11; 16]);
+        header[36..38].copy_from_slice(&FVE_METHOD_AES_128_XTS.to_le_bytes());
+        header[48..48 + entries.len()].copy_from_slice(&entries);
+        image
+    }
+
+    fn open(image: Vec<u8>) -> io::Result<BitLocker<Cursor<Vec<u8>>>> {
+        let mut header = [0u8; 512];
+        header.copy_from_slice(&image[..512]);
+        assert!(BitLocker::<Cursor<Vec<u8>>>::is_supported(&header));
+        BitLocker::with_header(Cursor::new(image), &header)
+    }
+
+    #[test]
+    fn recovery_password() {
+        let key = recovery_key("236808-089419-192665-495704-618299-073414-538373-542366").unwrap();
+        assert_eq!(key, [0x18, 0x54, 0xc1, 0x1f, 0x6b, 0x44, 0x08, 0xb0, 0x91, 0xdb, 0x12, 0x1a, 0x2f, 0xbf, 0x9a, 0xc0]);
+        assert_eq!(recovery_key("236808-089419-192665-495704-618299-073414-538373-542367"), None);
+        assert_eq!(recovery_key("236808-089419-192665-495704"), None);
+    }
+
+    #[test]
+    fn unlock_clear_key() {
+        let bitlocker = open(bitlocker_fixture()).unwrap();
+        assert_eq!(bitlocker.version(), 2);
+        assert_eq!(bitlocker.identifier(), "11111111-1111-1111-1111-111111111111");
+        assert_eq!(bitlocker.description().as_deref(), Some("warped-drive"));
+        assert!(bitlocker.has_clear_key());
+
+        let mut plaintext = bitlocker.unlock(&BitLockerKey::ClearKey).unwrap();
+        let mut buf = [0; 32];
+        plaintext.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\xEB\x52\x90NTFS    relocated boot sector");
+        plaintext.seek(SeekFrom::Start(0x200)).unwrap();
+        plaintext.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"BitLocker test second sector....");
+
+        // the relocated boot sector and metadata read back as zeros
+        for &offset in [0x1000, 0x2000].iter() {
+            plaintext.seek(SeekFrom::Start(offset)).unwrap();
+            plaintext.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, [0; 32]);
+        }
+    }
+
+    #[test]
+    fn wrong_keys() {
+        let bitlocker = open(bitlocker_fixture()).unwrap();
+        let err = bitlocker.unlock(&BitLockerKey::StartupKey(&[0; 64])).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        // a damaged FVEK fails its MAC, it follows the description and VMK entries
+        let mut image = bitlocker_fixture();
+        image[0x2000 + 64 + 48 + 34 + 160 + 8 + 28] ^= 1;
+        let err = open(image).unwrap().unlock(&BitLockerKey::ClearKey).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut image = bitlocker_fixture();
+        image[0x2000] = b'+';
+        assert!(open(image).is_err());
+    }
+}
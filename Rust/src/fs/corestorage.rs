@@ -0,0 +1,624 @@
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::super::crypto::{aes_unwrap, pbkdf2, Hash, Sha256, Xts};
+use super::super::device::{Decryptor, SectorCipher, Volume};
+use super::super::plist::Value;
+
+
+const CS_SIGNATURE: &[u8; 2] = b"CS";
+const CS_WIPED_SIGNATURE: &[u8; 8] = b"LVFwiped";
+
+const CS_BLOCK_VOLUME_HEADER: u16 = 0x0010;
+const CS_BLOCK_METADATA: u16 = 0x0011;
+const CS_BLOCK_ENCRYPTION_CONTEXT: u16 = 0x0019;
+const CS_BLOCK_LOGICAL_VOLUME: u16 = 0x001a;
+const CS_BLOCK_SEGMENT: u16 = 0x0505;
+
+const CS_ENCRYPTED_METADATA_BLOCK_SIZE: usize = 8192;
+const CS_SECTOR_SIZE: usize = 512;
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct CS_BLOCK_HEADER {
+    Checksum: u32,              // CRC-32 of bytes 8-512
+    ChecksumSeed: u32,
+    Version: u16,
+    BlockType: u16,             // CS_BLOCK_*
+    SequenceNumber: u32,
+    RevisionNumber: u64,
+    Unknown_0: [u8; 8],
+    BlockNumber: u64,
+    Unknown_1: [u8; 8],
+    HeaderSize: u32,
+    Unknown_2: [u8; 4],
+    Unknown_3: [u8; 8],
+    BlockSize: u64,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct CS_VOLUME_HEADER {
+    // CS_BLOCK_HEADER
+    Unknown_0: [u8; 16],
+    Signature: [u8; 2],         // 'CS'
+    ChecksumAlgorithm: u32,     // 1 - CRC-32C
+    MetadataBlocks: u16,
+    BlockSize: u32,
+    MetadataSize: u32,
+    MetadataBlock: [u64; 8],
+    KeyDataSize: u32,
+    EncryptionAlgorithm: u32,   // 2 - AES-XTS
+    KeyData: [u8; 16],
+    Unknown_1: [u8; 112],
+    PhysicalVolumeUUID: [u8; 16],
+    GroupVolumeUUID: [u8; 16],
+    Unknown_2: [u8; 176],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct CS_METADATA_HEADER {
+    // CS_BLOCK_HEADER
+    Checksum: u32,
+    ChecksumSeed: u32,
+    Unknown_0: [u8; 140],
+    VolumeGroupsOffset: u32,    // Bytes from the start of the block
+    XmlOffset: u32,
+    XmlSize: u32,
+    Unknown_1: [u32; 8],
+    PhysicalBlocks: u64,
+    Unknown_2: [u8; 8],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct CS_VOLUME_GROUPS_DESCRIPTOR {
+    Unknown_0: [u8; 8],
+    EncryptedMetadataSize: u64, // Blocks
+    Unknown_1: [u8; 8],
+    EncryptedMetadataBlocks: u64,
+    EncryptedMetadataBlock: [u64; 2],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct CS_ENCRYPTION_CONTEXT_HEADER {
+    // CS_BLOCK_HEADER
+    Unknown_0: [u8; 40],
+    XmlOffset: u32,             // Bytes from the start of the block
+    XmlSize: u32,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct CS_LOGICAL_VOLUME_HEADER {
+    // CS_BLOCK_HEADER
+    Unknown_0: [u8; 56],
+    XmlOffset: u32,             // Bytes from the start of the block
+    XmlSize: u32,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct CS_SEGMENT_HEADER {
+    // CS_BLOCK_HEADER
+    Blocks: u64,
+    FirstBlock: u64,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct CS_PASSPHRASE_WRAPPED_KEK {
+    SaltType: u32,              // 3
+    SaltSize: u32,              // 16
+    Salt: [u8; 16],
+    KeyType: u32,               // 16
+    KeySize: u32,               // 24
+    Key: [u8; 24],
+    Unknown_0: [u8; 116],
+    Iterations: u32,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct CS_KEK_WRAPPED_VOLUME_KEY {
+    KeyType: u32,
+    KeySize: u32,               // 24
+    Key: [u8; 24],
+}
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Read a packed structure from the start of a buffer.
+fn cast<T: Copy>(data: &[u8]) -> Option<T> {
+    if data.len() < core::mem::size_of::<T>() {
+        return None;
+    }
+    Some(unsafe{ *(data.as_ptr() as *const T) })
+}
+
+/// Get the XML property list stored in a metadata block.
+fn block_plist(block: &[u8], offset: u32, size: u32) -> Option<Value> {
+    let start = offset as usize;
+    let xml = block.get(start..start.checked_add(size as usize)?)?;
+    let plist = Value::parse(xml).ok();
+    debug!("{:#?}", plist);
+    plist
+}
+
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    let hex: Vec<String> = uuid.iter().map(|b| format!("{:02X}", b)).collect();
+    format!("{}-{}-{}-{}-{}", hex[..4].concat(), hex[4..6].concat(), hex[6..8].concat(),
+            hex[8..10].concat(), hex[10..].concat())
+}
+
+fn parse_uuid(text: &str) -> Option<[u8; 16]> {
+    let hex: Vec<u8> = text.bytes().filter(|&c| c != b'-').collect();
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut uuid = [0u8; 16];
+    for (u, pair) in uuid.iter_mut().zip(hex.chunks_exact(2)) {
+        *u = u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(uuid)
+}
+
+
+/// FileVault 2 sector cipher, AES-XTS with the sector number as the tweak.
+#[derive(Clone)]
+pub struct CoreStorageCipher {
+    xts: Xts,
+}
+
+impl SectorCipher for CoreStorageCipher {
+
+    fn sector_size(&self) -> usize {
+        CS_SECTOR_SIZE
+    }
+
+    fn decrypt(&self, sector: u64, data: &mut [u8]) {
+        let mut tweak = [0u8; 16];
+        tweak[..8].copy_from_slice(&sector.to_le_bytes());
+        self.xts.decrypt(&tweak, data);
+    }
+}
+
+
+/// Mac OS X Core Storage physical volume, as used by FileVault 2.
+pub struct CoreStorage<R> {
+    inner: R,
+    block_size: u64,
+    metadata_blocks: Vec<u64>,
+    key_data: [u8; 16],
+    physical_uuid: [u8; 16],
+    group_uuid: [u8; 16],
+    family_uuid: Option<[u8; 16]>,
+    logical_uuid: Option<[u8; 16]>,
+    name: String,
+    volume_size: u64,
+    first_block: u64,
+    passphrase_keks: Vec<CS_PASSPHRASE_WRAPPED_KEK>,
+    volume_keys: Vec<CS_KEK_WRAPPED_VOLUME_KEY>,
+}
+
+impl<R> CoreStorage<R> {
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R { &self.inner }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R { &mut self.inner }
+
+    /// Unwraps this `CoreStorage`, returning the underlying reader.
+    pub fn into_inner(self) -> R { self.inner }
+
+    /// Physical volume UUID.
+    pub fn physical_uuid(&self) -> String { format_uuid(&self.physical_uuid) }
+
+    /// Logical volume group UUID.
+    pub fn group_uuid(&self) -> String { format_uuid(&self.group_uuid) }
+
+    /// Logical volume UUID.
+    pub fn logical_uuid(&self) -> Option<String> { self.logical_uuid.as_ref().map(format_uuid) }
+
+    /// Logical volume name.
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Recover the volume master key with a passphrase.
+    fn open_passphrase(&self, passphrase: &[u8]) -> Option<Vec<u8>> {
+        for wrapped in &self.passphrase_keks {
+            let salt = &wrapped.Salt[..(wrapped.SaltSize as usize).min(16)];
+            let mut passphrase_key = [0u8; 16];
+            pbkdf2::<Sha256>(passphrase, salt, wrapped.Iterations, &mut passphrase_key);
+
+            let kek = match aes_unwrap(&passphrase_key, &wrapped.Key[..(wrapped.KeySize as usize).min(24)]) {
+                Some(kek) => kek,
+                None => continue,
+            };
+            debug!("Unwrapped Key Encrypting Key");
+
+            for volume_key in &self.volume_keys {
+                if let Some(key) = aes_unwrap(&kek, &volume_key.Key[..(volume_key.KeySize as usize).min(24)]) {
+                    return Some(key);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<R> CoreStorage<R>
+where R: Read + Seek {
+
+    /// Unlock the logical volume with a passphrase.
+    pub fn unlock(mut self, passphrase: &[u8]) -> io::Result<Decryptor<R, CoreStorageCipher>> {
+        let family_uuid = match self.family_uuid {
+            Some(uuid) if !self.passphrase_keks.is_empty() => uuid,
+            _ => return invalid("Core Storage volume is not encrypted"),
+        };
+
+        let key = match self.open_passphrase(passphrase) {
+            Some(key) => key,
+            None => {
+                eprintln!("ERROR: No key available with this passphrase");
+                return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+            }
+        };
+
+        let mut hash = Sha256::new();
+        hash.update(&key);
+        hash.update(&family_uuid);
+        let tweak_key = hash.finalize();
+
+        let offset = self.first_block * self.block_size;
+        let size = if self.volume_size > 0 {
+            self.volume_size
+        } else {
+            self.inner.seek(SeekFrom::End(0))?.saturating_sub(offset)
+        };
+        debug!("Logical Volume Offset: {}\nSize: {}", offset, size);
+
+        let cipher = CoreStorageCipher { xts: Xts::with_tweak_key(&key, &tweak_key[..key.len()])? };
+        Ok(Decryptor::new(self.inner, cipher, offset, size))
+    }
+
+    fn load_metadata(&mut self, offset: u64) -> io::Result<()> {
+        let inner = &mut self.inner;
+        let block = read_struct!(CS_BLOCK_HEADER, inner, offset, core::mem::size_of::<CS_BLOCK_HEADER>())?;
+        if block.BlockType != CS_BLOCK_METADATA {
+            return invalid(&format!("Invalid Core Storage Metadata at {}", offset));
+        }
+
+        let header_offset = offset + core::mem::size_of::<CS_BLOCK_HEADER>() as u64;
+        let header = read_struct!(CS_METADATA_HEADER, inner, header_offset, core::mem::size_of::<CS_METADATA_HEADER>())?;
+
+        let descriptor_offset = offset + header.VolumeGroupsOffset as u64;
+        let descriptor = read_struct!(CS_VOLUME_GROUPS_DESCRIPTOR, inner, descriptor_offset, core::mem::size_of::<CS_VOLUME_GROUPS_DESCRIPTOR>())?;
+
+        if header.XmlSize > 0 {
+            let mut xml = vec![0; header.XmlSize as usize];
+            inner.seek(SeekFrom::Start(offset + header.XmlOffset as u64))?;
+            inner.read_exact(&mut xml)?;
+            debug!("{:#?}", Value::parse(&xml).ok());
+        }
+
+        let size = descriptor.EncryptedMetadataSize * self.block_size;
+        let copies = (descriptor.EncryptedMetadataBlocks as usize).min(2);
+        if size == 0 || copies == 0 {
+            return Ok(());
+        }
+
+        // fall back on the backup when the first copy is damaged
+        let blocks = descriptor.EncryptedMetadataBlock;
+        for &block in blocks[..copies].iter() {
+            if self.load_encrypted_metadata(block * self.block_size, size).is_ok() {
+                return Ok(());
+            }
+        }
+        invalid("No valid Core Storage Encrypted Metadata")
+    }
+
+    fn load_encrypted_metadata(&mut self, offset: u64, size: u64) -> io::Result<()> {
+        self.family_uuid = None;
+        self.logical_uuid = None;
+        self.first_block = 0;
+        self.passphrase_keks.clear();
+        self.volume_keys.clear();
+
+        let mut metadata = vec![0; size as usize];
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.inner.read_exact(&mut metadata)?;
+
+        let xts = Xts::with_tweak_key(&self.key_data, &self.physical_uuid)?;
+        for (index, block) in metadata.chunks_exact_mut(CS_ENCRYPTED_METADATA_BLOCK_SIZE).enumerate() {
+            // unused blocks are zero filled, anything else uniform ends the metadata
+            if block.iter().all(|&b| b == block[0]) {
+                if block[0] == 0 {
+                    continue;
+                }
+                break;
+            }
+
+            let mut tweak = [0u8; 16];
+            tweak[..8].copy_from_slice(&(index as u64).to_le_bytes());
+            xts.decrypt(&tweak, block);
+            self.parse_block(block)?;
+        }
+
+        if self.family_uuid.is_none() {
+            return invalid("Missing Core Storage Logical Volume");
+        }
+        Ok(())
+    }
+
+    fn parse_block(&mut self, block: &[u8]) -> io::Result<()> {
+        if block.starts_with(CS_WIPED_SIGNATURE) {
+            return Ok(());
+        }
+        let header: CS_BLOCK_HEADER = match cast(block) {
+            Some(header) => header,
+            None => return invalid("Truncated Core Storage Block"),
+        };
+        let payload = &block[core::mem::size_of::<CS_BLOCK_HEADER>()..];
+
+        match header.BlockType {
+            CS_BLOCK_ENCRYPTION_CONTEXT => {
+                let context: CS_ENCRYPTION_CONTEXT_HEADER = match cast(payload) {
+                    Some(context) => context,
+                    None => return invalid("Truncated Core Storage Encryption Context"),
+                };
+                let plist = block_plist(block, context.XmlOffset, context.XmlSize);
+                let context = match plist.as_ref().and_then(|p| p.get("com.apple.corestorage.lvf.encryption.context")) {
+                    Some(context) => context,
+                    None => return Ok(()),
+                };
+
+                let users = context.get("CryptoUsers").and_then(Value::as_array).unwrap_or(&[]);
+                for user in users {
+                    if let Some(kek) = user.get("PassphraseWrappedKEKStruct").and_then(Value::as_data).and_then(cast) {
+                        debug!("{:#?}", kek);
+                        self.passphrase_keks.push(kek);
+                    }
+                }
+
+                let keys = context.get("WrappedVolumeKeys").and_then(Value::as_array).unwrap_or(&[]);
+                for key in keys {
+                    if let Some(key) = key.get("KEKWrappedVolumeKeyStruct").and_then(Value::as_data).and_then(cast) {
+                        debug!("{:#?}", key);
+                        self.volume_keys.push(key);
+                    }
+                }
+            }
+            CS_BLOCK_LOGICAL_VOLUME if self.family_uuid.is_none() => {
+                let volume: CS_LOGICAL_VOLUME_HEADER = match cast(payload) {
+                    Some(volume) => volume,
+                    None => return invalid("Truncated Core Storage Logical Volume"),
+                };
+                let plist = match block_plist(block, volume.XmlOffset, volume.XmlSize) {
+                    Some(plist) => plist,
+                    None => return invalid("Invalid Core Storage Logical Volume"),
+                };
+
+                let uuid = |key| plist.get(key).and_then(Value::as_str).and_then(parse_uuid);
+                self.family_uuid = uuid("com.apple.corestorage.lv.familyUUID");
+                self.logical_uuid = uuid("com.apple.corestorage.lv.uuid");
+                if self.family_uuid.is_none() || self.logical_uuid.is_none() {
+                    return invalid("Invalid Core Storage Logical Volume");
+                }
+                self.name = plist.get("com.apple.corestorage.lv.name").and_then(Value::as_str).unwrap_or("").to_string();
+                self.volume_size = plist.get("com.apple.corestorage.lv.size").and_then(Value::as_u64).unwrap_or(0);
+            }
+            // only the first segment holds the total number of blocks
+            CS_BLOCK_SEGMENT if self.first_block == 0 => {
+                let segment: CS_SEGMENT_HEADER = match cast(payload) {
+                    Some(segment) => segment,
+                    None => return invalid("Truncated Core Storage Segment"),
+                };
+                debug!("{:#?}", segment);
+                self.first_block = segment.FirstBlock;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl<R> Volume<R> for CoreStorage<R>
+where R: Read + Seek {
+
+    fn is_supported(header: &[u8; 512]) -> bool {
+        &header[88..90] == CS_SIGNATURE && u16::from_le_bytes([header[10], header[11]]) == CS_BLOCK_VOLUME_HEADER
+    }
+
+    fn with_header(inner: R, header: &[u8; 512]) -> io::Result<Self> {
+        let volume: CS_VOLUME_HEADER = match cast(&header[core::mem::size_of::<CS_BLOCK_HEADER>()..]) {
+            Some(volume) => volume,
+            None => return invalid("Truncated Core Storage Volume Header"),
+        };
+        debug!("{:#?}", volume);
+
+        if volume.BlockSize == 0 {
+            return invalid("Invalid Core Storage Block Size");
+        }
+        let count = (volume.MetadataBlocks as usize).min(8);
+        let metadata_blocks = volume.MetadataBlock;
+
+        let mut corestorage = Self {
+            inner: inner,
+            block_size: volume.BlockSize as u64,
+            metadata_blocks: metadata_blocks[..count].to_vec(),
+            key_data: volume.KeyData,
+            physical_uuid: volume.PhysicalVolumeUUID,
+            group_uuid: volume.GroupVolumeUUID,
+            family_uuid: None,
+            logical_uuid: None,
+            name: String::new(),
+            volume_size: 0,
+            first_block: 0,
+            passphrase_keks: Vec::new(),
+            volume_keys: Vec::new(),
+        };
+
+        corestorage.refresh()?;
+        Ok(corestorage)
+    }
+
+    fn refresh(&mut self) -> io::Result<()> {
+        // subsequent metadata blocks are backups of the first
+        for block in self.metadata_blocks.clone() {
+            if self.load_metadata(block * self.block_size).is_ok() {
+                return Ok(());
+            }
+        }
+        invalid("No valid Core Storage Metadata")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use super::super::super::utils::hex_decode;
+
+    const FAMILY_UUID: &str = "01234567-89AB-CDEF-0123-456789ABCDEF";
+
+    fn metadata_block(block_type: u16, xml_field: usize, xml: &str) -> Vec<u8> {
+        let mut block = vec![0u8; CS_ENCRYPTED_METADATA_BLOCK_SIZE];
+        block[10..12].copy_from_slice(&block_type.to_le_bytes());
+        if !xml.is_empty() {
+            block[xml_field..xml_field + 4].copy_from_slice(&256u32.to_le_bytes());
+            block[xml_field + 4..xml_field + 8].copy_from_slice(&(xml.len() as u32).to_le_bytes());
+            block[256..256 + xml.len()].copy_from_slice(xml.as_bytes());
+        }
+        block
+    }
+
+    /// A physical volume of 4 KiB blocks with a logical volume of one block at block 8.
+    ///
+    /// The volume key 50..5f is wrapped by a KEK, itself wrapped with "password" and the salt
+    /// 10..1f.  Only the first 32 bytes of the encrypted sector are kept.
+    fn corestorage_fixture() -> Vec<u8> {
+        let mut image = vec![0u8; 0x9000];
+        image[10..12].copy_from_slice(&CS_BLOCK_VOLUME_HEADER.to_le_bytes());
+        image[88..90].copy_from_slice(CS_SIGNATURE);
+        image[94..96].copy_from_slice(&1u16.to_le_bytes());
+        image[96..100].copy_from_slice(&4096u32.to_le_bytes());
+        image[104..112].copy_from_slice(&1u64.to_le_bytes());
+        let key_data: Vec<u8> = (0x70..0x80).collect();
+        let physical_uuid: Vec<u8> = (0x80..0x90).collect();
+        image[176..192].copy_from_slice(&key_data);
+        image[304..320].copy_from_slice(&physical_uuid);
+        image[320..336].copy_from_slice(&[0x90; 16]);
+
+        // the metadata points at 6 blocks of encrypted metadata from block 2
+        image[4096 + 10..4096 + 12].copy_from_slice(&CS_BLOCK_METADATA.to_le_bytes());
+        image[4096 + 220..4096 + 224].copy_from_slice(&512u32.to_le_bytes());
+        image[4608 + 8..4608 + 16].copy_from_slice(&6u64.to_le_bytes());
+        image[4608 + 24..4608 + 32].copy_from_slice(&1u64.to_le_bytes());
+        image[4608 + 32..4608 + 40].copy_from_slice(&2u64.to_le_bytes());
+
+        let volume = format!("<dict>
+            <key>com.apple.corestorage.lv.familyUUID</key><string>{}</string>
+            <key>com.apple.corestorage.lv.uuid</key><string>FEDCBA98-7654-3210-FEDC-BA9876543210</string>
+            <key>com.apple.corestorage.lv.name</key><string>Macintosh HD</string>
+            <key>com.apple.corestorage.lv.size</key><integer>4096</integer>
+        </dict>", FAMILY_UUID);
+        let context = "<dict><key>com.apple.corestorage.lvf.encryption.context</key><dict>
+            <key>CryptoUsers</key><array><dict><key>PassphraseWrappedKEKStruct</key><data>
+                AwAAABAAAAAQERITFBUWFxgZGhscHR4fEAAAABgAAACDpAmWhDOVqFqpwK0gcVa+kl0mslrBTqsAAAAAAAAAAAAA
+                AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA
+                AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAOgDAAA=
+            </data></dict></array>
+            <key>WrappedVolumeKeys</key><array><dict><key>KEKWrappedVolumeKeyStruct</key><data>
+                EAAAABgAAADjo/uOkPrUND8gL/QnApCjqYzEeapFyhI=
+            </data></dict></array>
+        </dict></dict>";
+        let mut segment = metadata_block(CS_BLOCK_SEGMENT, 0, "");
+        segment[72..80].copy_from_slice(&1u64.to_le_bytes());
+        segment[80..88].copy_from_slice(&8u64.to_le_bytes());
+
+        let xts = Xts::with_tweak_key(&key_data, &physical_uuid).unwrap();
+        let blocks = [
+            metadata_block(CS_BLOCK_LOGICAL_VOLUME, 128, &volume),
+            metadata_block(CS_BLOCK_ENCRYPTION_CONTEXT, 112, context),
+            segment,
+        ];
+        for (index, block) in blocks.iter().enumerate() {
+            let mut tweak = [0u8; 16];
+            tweak[0] = index as u8;
+            let start = 0x2000 + index * CS_ENCRYPTED_METADATA_BLOCK_SIZE;
+            let encrypted = &mut image[start..start + CS_ENCRYPTED_METADATA_BLOCK_SIZE];
+            encrypted.copy_from_slice(block);
+            xts.encrypt(&tweak, encrypted);
+        }
+
+        image[0x8000..0x8020].copy_from_slice(&hex_decode("64ead404ef19ba38cc54b1d2877c7b142c528d0fb44986533ae31c96c5417581"));
+        image
+    }
+
+    fn open(image: Vec<u8>) -> io::Result<CoreStorage<Cursor<Vec<u8>>>> {
+        let mut header = [0u8; 512];
+        header.copy_from_slice(&image[..512]);
+        assert!(CoreStorage::<Cursor<Vec<u8>>>::is_supported(&header));
+        CoreStorage::with_header(Cursor::new(image), &header)
+    }
+
+    #[test]
+    fn uuids() {
+        let uuid = parse_uuid(FAMILY_UUID).unwrap();
+        assert_eq!(uuid[..4], [0x01, 0x23, 0x45, 0x67]);
+        assert_eq!(format_uuid(&uuid), FAMILY_UUID);
+        assert_eq!(parse_uuid("01234567-89AB-CDEF-0123-456789ABCD"), None);
+        assert_eq!(parse_uuid("01234567-89AB-CDEF-0123-456789ABCDEG"), None);
+    }
+
+    #[test]
+    fn unlock_passphrase() {
+        let corestorage = open(corestorage_fixture()).unwrap();
+        assert_eq!(corestorage.physical_uuid(), "80818283-8485-8687-8889-8A8B8C8D8E8F");
+        assert_eq!(corestorage.group_uuid(), "90909090-9090-9090-9090-909090909090");
+        assert_eq!(corestorage.logical_uuid().as_deref(), Some("FEDCBA98-7654-3210-FEDC-BA9876543210"));
+        assert_eq!(corestorage.name(), "Macintosh HD");
+
+        let mut plaintext = corestorage.unlock(b"password").unwrap();
+        assert_eq!(plaintext.len(), 4096);
+        let mut buf = [0; 32];
+        plaintext.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"FileVault 2 logical volume data!");
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let corestorage = open(corestorage_fixture()).unwrap();
+        let err = corestorage.unlock(b"letmein").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn invalid_metadata() {
+        // damaged encrypted metadata leaves no logical volume
+        let mut image = corestorage_fixture();
+        image[0x2000] ^= 1;
+        assert!(open(image).is_err());
+
+        let mut image = corestorage_fixture();
+        image[4096 + 10] = 0;
+        assert!(open(image).is_err());
+    }
+}
@@ -0,0 +1,796 @@
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::super::crypto::{argon2, pbkdf2, Aes, Argon2Type, Hash, Sha1, Sha256, Sha512, Xts};
+use super::super::device::{Decryptor, SectorCipher, Volume};
+use super::super::json::Value;
+use super::super::utils::base64_decode;
+
+
+const LUKS_MAGIC: &[u8; 6] = b"LUKS\xba\xbe";
+const LUKS2_SECONDARY_MAGIC: &[u8; 6] = b"SKUL\xba\xbe";
+
+const LUKS_SECTOR_SIZE: u64 = 512;
+
+const LUKS_KEY_ENABLED: u32 = 0x00AC71F3;
+const LUKS_KEY_DISABLED: u32 = 0x0000DEAD;
+
+const LUKS2_ARGON2_MAX_MEMORY: u32 = 4 * 1024 * 1024;   // KiB, the limit of cryptsetup
+
+// possible locations of the LUKS2 secondary header
+const LUKS2_HEADER_OFFSETS: [u64; 9] = [
+    0x4000, 0x8000, 0x10000, 0x20000, 0x40000, 0x80000, 0x100000, 0x200000, 0x400000,
+];
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct LUKS_KEYSLOT {
+    Active: u32,                // LUKS_KEY_*
+    PasswordIterations: u32,
+    PasswordSalt: [u8; 32],
+    KeyMaterialOffset: u32,     // Sectors
+    Stripes: u32,
+}
+
+// all integers are big endian
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct LUKS_PHDR {
+    Magic: [u8; 6],             // 'LUKS' ba be
+    Version: u16,               // 1
+    CipherName: [u8; 32],
+    CipherMode: [u8; 32],
+    HashSpec: [u8; 32],
+    PayloadOffset: u32,         // Sectors
+    KeyBytes: u32,
+    MkDigest: [u8; 20],
+    MkDigestSalt: [u8; 32],
+    MkDigestIterations: u32,
+    Uuid: [u8; 40],
+    KeySlots: [LUKS_KEYSLOT; 8],
+}
+
+// all integers are big endian
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct LUKS2_HDR_DISK {
+    Magic: [u8; 6],             // 'LUKS' ba be or 'SKUL' ba be
+    Version: u16,               // 2
+    HeaderSize: u64,            // including the JSON area
+    SequenceId: u64,
+    Label: [u8; 48],
+    ChecksumAlgorithm: [u8; 32],
+    Salt: [u8; 64],
+    Uuid: [u8; 40],
+    Subsystem: [u8; 48],
+    HeaderOffset: u64,
+    Padding: [u8; 184],
+    Checksum: [u8; 64],
+    // Padding: [u8; 7 * 512],
+}
+
+
+/// Get a NUL terminated string from a fixed size field.
+fn fixed_string(raw: &[u8]) -> String {
+    let end = raw.iter().position(|&c| c == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).into_owned()
+}
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+
+/// Run a function generic over `Hash` given the hash name.
+macro_rules! with_hash {
+    ($name:expr, $func:ident ( $($arg:expr),* )) => {
+        match $name {
+            "sha1" => Ok($func::<Sha1>($($arg),*)),
+            "sha256" => Ok($func::<Sha256>($($arg),*)),
+            "sha512" => Ok($func::<Sha512>($($arg),*)),
+            name => invalid(&format!("Unsupported Hash: {}", name)),
+        }
+    };
+}
+
+
+fn digest<H>(data: &[u8]) -> Vec<u8>
+where H: Hash {
+    let mut hash = H::new();
+    hash.update(data);
+    hash.finalize()
+}
+
+fn derive<H>(password: &[u8], salt: &[u8], iterations: u32, size: usize) -> Vec<u8>
+where H: Hash {
+    let mut key = vec![0; size];
+    pbkdf2::<H>(password, salt, iterations, &mut key);
+    key
+}
+
+/// Anti-forensic information merger.
+fn af_merge<H>(material: &[u8], size: usize, stripes: usize) -> Vec<u8>
+where H: Hash {
+    let mut buffer = vec![0u8; size];
+    for stripe in 0..stripes - 1 {
+        for (b, m) in buffer.iter_mut().zip(material[stripe * size..].iter()) {
+            *b ^= m;
+        }
+
+        // diffuse
+        for (i, chunk) in buffer.chunks_mut(H::DIGEST_SIZE).enumerate() {
+            let mut hash = H::new();
+            hash.update(&(i as u32).to_be_bytes());
+            hash.update(chunk);
+            let len = chunk.len();
+            chunk.copy_from_slice(&hash.finalize()[..len]);
+        }
+    }
+    for (b, m) in buffer.iter_mut().zip(material[(stripes - 1) * size..].iter()) {
+        *b ^= m;
+    }
+    buffer
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InitializationVector {
+    Null,
+    Plain,
+    Plain64,
+    Plain64Be,
+    Essiv,
+}
+
+#[derive(Clone)]
+enum CipherMode {
+    Ecb(Aes),
+    Cbc(Aes),
+    Xts(Box<Xts>),
+}
+
+/// dm-crypt style sector cipher, such as `aes-xts-plain64` or `aes-cbc-essiv:sha256`.
+#[derive(Clone)]
+pub struct LuksCipher {
+    mode: CipherMode,
+    iv: InitializationVector,
+    essiv: Option<Aes>,
+    sector_size: usize,
+    iv_offset: u64,     // 512 byte sectors
+    iv_shift: u32,
+}
+
+impl LuksCipher {
+
+    /// Create a cipher from a specification such as `aes-xts-plain64`.
+    pub fn new(spec: &str, key: &[u8], sector_size: usize, iv_offset: u64) -> io::Result<Self> {
+        let mut parts = spec.splitn(3, '-');
+        let cipher = parts.next().unwrap_or("");
+        let mode = parts.next().unwrap_or("");
+        let iv = parts.next().unwrap_or("");
+
+        if cipher != "aes" {
+            return invalid(&format!("Unsupported Cipher: {}", spec));
+        }
+        if sector_size < 512 || !sector_size.is_power_of_two() {
+            return invalid(&format!("Invalid Sector Size: {}", sector_size));
+        }
+
+        let (iv, essiv) = if let Some(hash) = iv.strip_prefix("essiv:") {
+            let salt = with_hash!(hash, digest(key))?;
+            (InitializationVector::Essiv, Some(Aes::new(&salt)?))
+        } else {
+            (match iv {
+                "" | "null" => InitializationVector::Null,
+                "plain" => InitializationVector::Plain,
+                "plain64" => InitializationVector::Plain64,
+                "plain64be" => InitializationVector::Plain64Be,
+                _ => return invalid(&format!("Unsupported IV: {}", spec)),
+            }, None)
+        };
+
+        let mode = match mode {
+            "ecb" => CipherMode::Ecb(Aes::new(key)?),
+            "cbc" => CipherMode::Cbc(Aes::new(key)?),
+            "xts" => CipherMode::Xts(Box::new(Xts::new(key)?)),
+            _ => return invalid(&format!("Unsupported Cipher Mode: {}", spec)),
+        };
+
+        Ok(Self {
+            mode: mode,
+            iv: iv,
+            essiv: essiv,
+            sector_size: sector_size,
+            iv_offset: iv_offset,
+            iv_shift: (sector_size / 512).trailing_zeros(),
+        })
+    }
+}
+
+impl SectorCipher for LuksCipher {
+
+    fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn decrypt(&self, sector: u64, data: &mut [u8]) {
+        let sector = ((sector << self.iv_shift) + self.iv_offset) >> self.iv_shift;
+
+        let mut iv = [0u8; 16];
+        match self.iv {
+            InitializationVector::Null => {}
+            InitializationVector::Plain => {
+                iv[..4].copy_from_slice(&(sector as u32).to_le_bytes());
+            }
+            InitializationVector::Plain64 => {
+                iv[..8].copy_from_slice(&sector.to_le_bytes());
+            }
+            InitializationVector::Plain64Be => {
+                iv[8..].copy_from_slice(&sector.to_be_bytes());
+            }
+            InitializationVector::Essiv => {
+                iv[..8].copy_from_slice(&sector.to_le_bytes());
+                if let Some(essiv) = &self.essiv {
+                    essiv.encrypt_block(&mut iv);
+                }
+            }
+        }
+
+        match &self.mode {
+            CipherMode::Ecb(aes) => aes.ecb_decrypt(data),
+            CipherMode::Cbc(aes) => aes.cbc_decrypt(&iv, data),
+            CipherMode::Xts(xts) => xts.decrypt(&iv, data),
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+enum Kdf {
+    Pbkdf2 {
+        hash: String,
+        iterations: u32,
+        salt: Vec<u8>,
+    },
+    Argon2 {
+        variant: Argon2Type,
+        time: u32,
+        memory: u32,    // KiB
+        cpus: u32,
+        salt: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct KeySlot {
+    id: String,
+    priority: u64,
+    key_size: usize,
+    kdf: Kdf,
+    af_stripes: usize,
+    af_hash: String,
+    area_offset: u64,
+    area_size: u64,
+    area_encryption: String,
+    area_key_size: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Digest {
+    keyslots: Vec<String>,
+    segments: Vec<String>,
+    hash: String,
+    iterations: u32,
+    salt: Vec<u8>,
+    digest: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+struct Segment {
+    id: String,
+    offset: u64,
+    size: Option<u64>,  // None => dynamic
+    iv_tweak: u64,
+    encryption: String,
+    sector_size: usize,
+}
+
+
+/// Linux Unified Key Setup encrypted volume, version 1 or 2.
+pub struct Luks<R> {
+    inner: R,
+    version: u16,
+    uuid: String,
+    label: String,
+    keyslots: Vec<KeySlot>,
+    digests: Vec<Digest>,
+    segments: Vec<Segment>,
+}
+
+impl<R> Luks<R> {
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R { &self.inner }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R { &mut self.inner }
+
+    /// Unwraps this `Luks`, returning the underlying reader.
+    pub fn into_inner(self) -> R { self.inner }
+
+    /// LUKS header version, 1 or 2.
+    pub fn version(&self) -> u16 { self.version }
+
+    /// Volume UUID.
+    pub fn uuid(&self) -> &str { &self.uuid }
+
+    /// Volume label, always empty for LUKS1.
+    pub fn label(&self) -> &str { &self.label }
+}
+
+impl<R> Luks<R>
+where R: Read + Seek {
+
+    /// Unlock the volume with a passphrase or the contents of a key file.
+    pub fn unlock(mut self, passphrase: &[u8]) -> io::Result<Decryptor<R, LuksCipher>> {
+        let mut keyslots: Vec<&KeySlot> = self.keyslots.iter().filter(|k| k.priority > 0).collect();
+        keyslots.sort_by_key(|k| Reverse(k.priority));
+
+        let mut unlocked = None;
+        for keyslot in keyslots {
+            debug!("Trying Key Slot: {}", keyslot.id);
+            let key = Self::open_keyslot(&mut self.inner, keyslot, passphrase)?;
+            if let Some(segment) = self.verify(&keyslot.id, &key)? {
+                debug!("Unlocked Key Slot: {}", keyslot.id);
+                unlocked = Some((key, segment.clone()));
+                break;
+            }
+        }
+
+        let (key, segment) = match unlocked {
+            Some(unlocked) => unlocked,
+            None => {
+                eprintln!("ERROR: No key available with this passphrase");
+                return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+            }
+        };
+
+        let size = match segment.size {
+            Some(size) => size,
+            None => self.inner.seek(SeekFrom::End(0))?.saturating_sub(segment.offset),
+        };
+        debug!("Segment: {:#?}\nSize: {}", segment, size);
+
+        let cipher = LuksCipher::new(&segment.encryption, &key, segment.sector_size, segment.iv_tweak)?;
+        Ok(Decryptor::new(self.inner, cipher, segment.offset, size))
+    }
+
+    /// Derive the area key and recover a candidate volume key from a key slot.
+    fn open_keyslot(inner: &mut R, keyslot: &KeySlot, passphrase: &[u8]) -> io::Result<Vec<u8>> {
+        let mut area_key = vec![0; keyslot.area_key_size];
+        match &keyslot.kdf {
+            Kdf::Pbkdf2 { hash, iterations, salt } => {
+                area_key = with_hash!(hash.as_str(), derive(passphrase, salt, *iterations, area_key.len()))?;
+            }
+            Kdf::Argon2 { variant, time, memory, cpus, salt } => {
+                argon2(*variant, passphrase, salt, *time, *memory, *cpus, &mut area_key)?;
+            }
+        }
+
+        let material_size = keyslot.key_size.checked_mul(keyslot.af_stripes).unwrap_or(0);
+        let sectors = (material_size as u64).div_ceil(LUKS_SECTOR_SIZE);
+        if material_size == 0 || sectors * LUKS_SECTOR_SIZE > keyslot.area_size {
+            return invalid(&format!("Key Slot {} material does not fit its area", keyslot.id));
+        }
+
+        let mut material = vec![0; (sectors * LUKS_SECTOR_SIZE) as usize];
+        inner.seek(SeekFrom::Start(keyslot.area_offset))?;
+        inner.read_exact(&mut material)?;
+
+        let cipher = LuksCipher::new(&keyslot.area_encryption, &area_key, LUKS_SECTOR_SIZE as usize, 0)?;
+        for (sector, chunk) in material.chunks_exact_mut(LUKS_SECTOR_SIZE as usize).enumerate() {
+            cipher.decrypt(sector as u64, chunk);
+        }
+
+        with_hash!(keyslot.af_hash.as_str(), af_merge(&material, keyslot.key_size, keyslot.af_stripes))
+    }
+
+    /// Check a candidate volume key against the digests, returning the segment it unlocks.
+    fn verify(&self, keyslot: &str, key: &[u8]) -> io::Result<Option<&Segment>> {
+        for digest in self.digests.iter().filter(|d| d.keyslots.iter().any(|k| k == keyslot)) {
+            let computed = with_hash!(digest.hash.as_str(), derive(key, &digest.salt, digest.iterations, digest.digest.len()))?;
+            if computed == digest.digest {
+                return Ok(self.segments.iter().find(|s| digest.segments.contains(&s.id)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn load_luks1(&mut self) -> io::Result<()> {
+        let inner = &mut self.inner;
+        let header = read_struct!(LUKS_PHDR, inner, 0, core::mem::size_of::<LUKS_PHDR>())?;
+
+        let cipher = format!("{}-{}", fixed_string(&header.CipherName), fixed_string(&header.CipherMode));
+        let hash = fixed_string(&header.HashSpec);
+        let key_size = u32::from_be(header.KeyBytes) as usize;
+
+        debug!(
+            "Cipher: {}\nHash: {}\nPayload Offset: {}\nKey Bytes: {}",
+            cipher, hash, u32::from_be(header.PayloadOffset), key_size
+        );
+
+        self.uuid = fixed_string(&header.Uuid);
+        self.label = String::new();
+        self.keyslots.clear();
+        self.digests.clear();
+        self.segments.clear();
+
+        let key_slots = header.KeySlots;
+        for (i, slot) in key_slots.iter().enumerate() {
+            debug!("{:#?}", slot);
+            match u32::from_be(slot.Active) {
+                LUKS_KEY_ENABLED => {}
+                LUKS_KEY_DISABLED => continue,
+                active => {
+                    eprintln!("WARNING: Key Slot {} has invalid state: {:#x}", i, active);
+                    continue;
+                }
+            }
+
+            let stripes = u32::from_be(slot.Stripes) as usize;
+            let material_size = match key_size.checked_mul(stripes) {
+                Some(size) => size as u64,
+                None => {
+                    eprintln!("WARNING: Key Slot {} has too many stripes: {}", i, stripes);
+                    continue;
+                }
+            };
+            self.keyslots.push(KeySlot {
+                id: i.to_string(),
+                priority: 1,
+                key_size: key_size,
+                kdf: Kdf::Pbkdf2 {
+                    hash: hash.clone(),
+                    iterations: u32::from_be(slot.PasswordIterations),
+                    salt: slot.PasswordSalt.to_vec(),
+                },
+                af_stripes: stripes,
+                af_hash: hash.clone(),
+                area_offset: u32::from_be(slot.KeyMaterialOffset) as u64 * LUKS_SECTOR_SIZE,
+                area_size: material_size.div_ceil(LUKS_SECTOR_SIZE) * LUKS_SECTOR_SIZE,
+                area_encryption: cipher.clone(),
+                area_key_size: key_size,
+            });
+        }
+
+        self.digests.push(Digest {
+            keyslots: self.keyslots.iter().map(|k| k.id.clone()).collect(),
+            segments: vec![String::from("0")],
+            hash: hash,
+            iterations: u32::from_be(header.MkDigestIterations),
+            salt: header.MkDigestSalt.to_vec(),
+            digest: header.MkDigest.to_vec(),
+        });
+
+        self.segments.push(Segment {
+            id: String::from("0"),
+            offset: u32::from_be(header.PayloadOffset) as u64 * LUKS_SECTOR_SIZE,
+            size: None,
+            iv_tweak: 0,
+            encryption: cipher,
+            sector_size: LUKS_SECTOR_SIZE as usize,
+        });
+
+        Ok(())
+    }
+
+    /// Read and validate a LUKS2 binary header and its JSON area.
+    fn read_luks2_header(inner: &mut R, offset: u64) -> io::Result<(LUKS2_HDR_DISK, Value)> {
+        let header = read_struct!(LUKS2_HDR_DISK, inner, offset, core::mem::size_of::<LUKS2_HDR_DISK>())?;
+
+        let magic = if offset == 0 { LUKS_MAGIC } else { LUKS2_SECONDARY_MAGIC };
+        if &header.Magic != magic || u16::from_be(header.Version) != 2 ||
+           u64::from_be(header.HeaderOffset) != offset {
+            return invalid(&format!("Invalid LUKS2 Header at {}", offset));
+        }
+
+        let size = u64::from_be(header.HeaderSize);
+        if size <= 4096 || size > 0x400000 {
+            return invalid(&format!("Invalid LUKS2 Header Size: {}", size));
+        }
+
+        let mut raw = vec![0; size as usize];
+        inner.seek(SeekFrom::Start(offset))?;
+        inner.read_exact(&mut raw)?;
+
+        // the checksum covers the whole header with the checksum field zeroed
+        let checksum_offset = core::mem::size_of::<LUKS2_HDR_DISK>() - 64;
+        for b in raw[checksum_offset..checksum_offset + 64].iter_mut() {
+            *b = 0;
+        }
+        let algorithm = fixed_string(&header.ChecksumAlgorithm);
+        let checksum = with_hash!(algorithm.as_str(), digest(&raw))?;
+        if checksum[..] != header.Checksum[..checksum.len()] {
+            return invalid(&format!("Invalid LUKS2 Header Checksum at {}", offset));
+        }
+
+        let end = raw[4096..].iter().position(|&c| c == 0).map_or(raw.len(), |p| p + 4096);
+        let text = match core::str::from_utf8(&raw[4096..end]) {
+            Ok(text) => text,
+            Err(_) => return invalid("Invalid LUKS2 JSON Area"),
+        };
+        debug!("{}", text);
+
+        Ok((header, Value::parse(text)?))
+    }
+
+    fn load_luks2(&mut self) -> io::Result<()> {
+        let mut best = Self::read_luks2_header(&mut self.inner, 0);
+
+        let secondary: Vec<u64> = match &best {
+            Ok((header, _)) => vec![u64::from_be(header.HeaderSize)],
+            Err(_) => LUKS2_HEADER_OFFSETS.to_vec(),
+        };
+        for offset in secondary {
+            if let Ok(candidate) = Self::read_luks2_header(&mut self.inner, offset) {
+                let newer = match &best {
+                    Ok((header, _)) => u64::from_be(candidate.0.SequenceId) > u64::from_be(header.SequenceId),
+                    Err(_) => true,
+                };
+                if newer {
+                    eprintln!("WARNING: Using LUKS2 secondary header at {}", offset);
+                    best = Ok(candidate);
+                }
+                break;
+            }
+        }
+        let (header, json) = best?;
+
+        self.uuid = fixed_string(&header.Uuid);
+        self.label = fixed_string(&header.Label);
+        self.keyslots = Vec::new();
+        self.digests = Vec::new();
+        self.segments = Vec::new();
+
+        let empty = Value::Object(Vec::new());
+
+        for (id, keyslot) in json.get("keyslots").unwrap_or(&empty).as_object().unwrap_or(&[]) {
+            if keyslot.get("type").and_then(Value::as_str) != Some("luks2") {
+                eprintln!("WARNING: Skipping unsupported Key Slot {}", id);
+                continue;
+            }
+            match Self::parse_keyslot(id, keyslot) {
+                Some(parsed) => self.keyslots.push(parsed),
+                None => return invalid(&format!("Invalid LUKS2 Key Slot: {}", id)),
+            }
+        }
+
+        for (id, digest) in json.get("digests").unwrap_or(&empty).as_object().unwrap_or(&[]) {
+            if digest.get("type").and_then(Value::as_str) != Some("pbkdf2") {
+                eprintln!("WARNING: Skipping unsupported Digest {}", id);
+                continue;
+            }
+            match Self::parse_digest(digest) {
+                Some(parsed) => self.digests.push(parsed),
+                None => return invalid(&format!("Invalid LUKS2 Digest: {}", id)),
+            }
+        }
+
+        for (id, segment) in json.get("segments").unwrap_or(&empty).as_object().unwrap_or(&[]) {
+            if segment.get("type").and_then(Value::as_str) != Some("crypt") {
+                eprintln!("WARNING: Skipping unsupported Segment {}", id);
+                continue;
+            }
+            match Self::parse_segment(id, segment) {
+                Some(parsed) => self.segments.push(parsed),
+                None => return invalid(&format!("Invalid LUKS2 Segment: {}", id)),
+            }
+        }
+
+        debug!("{:#?}\n{:#?}\n{:#?}", self.keyslots, self.digests, self.segments);
+        Ok(())
+    }
+
+    fn parse_keyslot(id: &str, keyslot: &Value) -> Option<KeySlot> {
+        let kdf = keyslot.get("kdf")?;
+        let salt = base64_decode(kdf.get("salt")?.as_str()?).ok()?;
+        let kdf = match kdf.get("type")?.as_str()? {
+            "pbkdf2" => Kdf::Pbkdf2 {
+                hash: kdf.get("hash")?.as_str()?.to_string(),
+                iterations: u32::try_from(kdf.get("iterations")?.as_u64()?).ok()?,
+                salt: salt,
+            },
+            variant => Kdf::Argon2 {
+                variant: match variant {
+                    "argon2i" => Argon2Type::Argon2i,
+                    "argon2id" => Argon2Type::Argon2id,
+                    _ => return None,
+                },
+                time: u32::try_from(kdf.get("time")?.as_u64()?).ok()?,
+                memory: u32::try_from(kdf.get("memory")?.as_u64()?).ok().filter(|&m| m <= LUKS2_ARGON2_MAX_MEMORY)?,
+                cpus: u32::try_from(kdf.get("cpus")?.as_u64()?).ok()?,
+                salt: salt,
+            },
+        };
+
+        let af = keyslot.get("af")?;
+        if af.get("type")?.as_str()? != "luks1" {
+            return None;
+        }
+        let area = keyslot.get("area")?;
+        if area.get("type")?.as_str()? != "raw" {
+            return None;
+        }
+
+        Some(KeySlot {
+            id: id.to_string(),
+            priority: keyslot.get("priority").and_then(Value::as_u64).unwrap_or(1),
+            key_size: keyslot.get("key_size")?.as_u64()? as usize,
+            kdf: kdf,
+            af_stripes: af.get("stripes")?.as_u64()? as usize,
+            af_hash: af.get("hash")?.as_str()?.to_string(),
+            area_offset: area.get("offset")?.as_u64()?,
+            area_size: area.get("size")?.as_u64()?,
+            area_encryption: area.get("encryption")?.as_str()?.to_string(),
+            area_key_size: area.get("key_size")?.as_u64()? as usize,
+        })
+    }
+
+    fn parse_digest(digest: &Value) -> Option<Digest> {
+        let ids = |key| -> Option<Vec<String>> {
+            digest.get(key)?.as_array()?.iter().map(|v| v.as_str().map(String::from)).collect()
+        };
+        Some(Digest {
+            keyslots: ids("keyslots")?,
+            segments: ids("segments")?,
+            hash: digest.get("hash")?.as_str()?.to_string(),
+            iterations: u32::try_from(digest.get("iterations")?.as_u64()?).ok()?,
+            salt: base64_decode(digest.get("salt")?.as_str()?).ok()?,
+            digest: base64_decode(digest.get("digest")?.as_str()?).ok()?,
+        })
+    }
+
+    fn parse_segment(id: &str, segment: &Value) -> Option<Segment> {
+        let size = segment.get("size")?;
+        Some(Segment {
+            id: id.to_string(),
+            offset: segment.get("offset")?.as_u64()?,
+            size: if size.as_str() == Some("dynamic") { None } else { Some(size.as_u64()?) },
+            iv_tweak: segment.get("iv_tweak")?.as_u64()?,
+            encryption: segment.get("encryption")?.as_str()?.to_string(),
+            sector_size: segment.get("sector_size")?.as_u64()? as usize,
+        })
+    }
+}
+
+impl<R> Volume<R> for Luks<R>
+where R: Read + Seek {
+
+    fn is_supported(header: &[u8; 512]) -> bool {
+        &header[0..6] == LUKS_MAGIC
+    }
+
+    fn with_header(inner: R, header: &[u8; 512]) -> io::Result<Self> {
+        let mut luks = Self {
+            inner: inner,
+            version: u16::from_be_bytes([header[6], header[7]]),
+            uuid: String::new(),
+            label: String::new(),
+            keyslots: Vec::new(),
+            digests: Vec::new(),
+            segments: Vec::new(),
+        };
+        debug!("LUKS Version: {}", luks.version);
+
+        luks.refresh()?;
+        Ok(luks)
+    }
+
+    fn refresh(&mut self) -> io::Result<()> {
+        match self.version {
+            1 => self.load_luks1(),
+            2 => self.load_luks2(),
+            version => invalid(&format!("Unsupported LUKS Version: {}", version)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use super::super::super::utils::hex_decode;
+
+    const PAYLOAD: &[u8; 32] = b"warped-drive LUKS1 test payload!";
+
+    /// A LUKS1 volume with a single aes-cbc-essiv:sha256 key slot of 2 stripes for "password".
+    fn luks1_fixture() -> Vec<u8> {
+        let mut image = vec![0u8; 3 * 512];
+        image[0..6].copy_from_slice(LUKS_MAGIC);
+        image[6..8].copy_from_slice(&1u16.to_be_bytes());
+        image[8..11].copy_from_slice(b"aes");
+        image[40..56].copy_from_slice(b"cbc-essiv:sha256");
+        image[72..78].copy_from_slice(b"sha256");
+        image[104..108].copy_from_slice(&2u32.to_be_bytes());
+        image[108..112].copy_from_slice(&16u32.to_be_bytes());
+        image[112..132].copy_from_slice(&hex_decode("c5f2190e572c512d18dbe9f197fe1a32a4e2c443"));
+        image[132..164].copy_from_slice(&[0xa5; 32]);
+        image[164..168].copy_from_slice(&1000u32.to_be_bytes());
+        for slot in image[208..592].chunks_exact_mut(48) {
+            slot[0..4].copy_from_slice(&LUKS_KEY_DISABLED.to_be_bytes());
+        }
+        image[208..212].copy_from_slice(&LUKS_KEY_ENABLED.to_be_bytes());
+        image[212..216].copy_from_slice(&1000u32.to_be_bytes());
+        image[216..248].copy_from_slice(&[0x5a; 32]);
+        image[248..252].copy_from_slice(&1u32.to_be_bytes());
+        image[252..256].copy_from_slice(&2u32.to_be_bytes());
+        image[512..544].copy_from_slice(&hex_decode("7670e4500ffd6a8e06c4892ce8283453b550affcc6acd60fe516e76a153373c7"));
+        image[1024..1056].copy_from_slice(&hex_decode("f945b581cce8b73c3274d3126f0511c1d9dd8d53b15168348dd12be3e655b099"));
+        image
+    }
+
+    #[test]
+    fn af_merge_partial_digest() {
+        let material: Vec<u8> = (0..96).collect();
+        assert_eq!(
+            af_merge::<Sha1>(&material, 32, 3),
+            hex_decode("60bbdd81b29404e5f7631469b8412ae5153655c23e5503a23418423454f2d6ae"),
+        );
+    }
+
+    #[test]
+    fn essiv() {
+        let key: Vec<u8> = (0..16).collect();
+        let cipher = LuksCipher::new("aes-cbc-essiv:sha256", &key, 512, 0).unwrap();
+        let mut sector = vec![0; 512];
+        sector[..32].copy_from_slice(&hex_decode("cfff9eb1c4f5f70a273bbe7cf21fc1c7c3afdd5d784a9fd79d462ea2ae058ab1"));
+        cipher.decrypt(5, &mut sector);
+        assert_eq!(&sector[..32], b"ESSIV sector IV!ESSIV sector IV!");
+    }
+
+    #[test]
+    fn unlock_luks1() {
+        let luks = Luks::new(Cursor::new(luks1_fixture())).unwrap();
+        assert_eq!(luks.version(), 1);
+
+        let mut plaintext = luks.unlock(b"password").unwrap();
+        assert_eq!(plaintext.len(), 512);
+        let mut buf = [0; 32];
+        plaintext.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, PAYLOAD);
+    }
+
+    #[test]
+    fn keyslot_limits() {
+        let keyslot = |kdf: &str| Value::parse(&format!(r#"{{
+            "type": "luks2", "key_size": 64,
+            "af": {{"type": "luks1", "stripes": 4000, "hash": "sha256"}},
+            "area": {{"type": "raw", "offset": "32768", "size": "258048", "encryption": "aes-xts-plain64", "key_size": 64}},
+            "kdf": {{"salt": "AAAA", {}}}
+        }}"#, kdf)).unwrap();
+
+        let parsed = Luks::<Cursor<Vec<u8>>>::parse_keyslot("0", &keyslot(r#""type": "argon2id", "time": 4, "memory": 1048576, "cpus": 4"#));
+        assert_eq!(parsed.map(|k| k.af_stripes), Some(4000));
+        for kdf in [
+            r#""type": "argon2id", "time": 4, "memory": 1073741824, "cpus": 4"#,
+            r#""type": "argon2i", "time": 4294967296, "memory": 1024, "cpus": 4"#,
+            r#""type": "pbkdf2", "hash": "sha256", "iterations": 4294967296"#,
+        ].iter() {
+            assert!(Luks::<Cursor<Vec<u8>>>::parse_keyslot("0", &keyslot(kdf)).is_none());
+        }
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let luks = Luks::new(Cursor::new(luks1_fixture())).unwrap();
+        let err = luks.unlock(b"letmein").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
\ No newline at end of file
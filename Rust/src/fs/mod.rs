@@ -3,10 +3,24 @@ use std::io::{Read, Seek};
 
 use super::device::{Block, Device, Volume};
 
+mod bitlocker;
+mod corestorage;
+#[allow(dead_code)]    // on-disk layouts only, there is no FAT reader yet
 mod fat;
+mod luks;
 mod ntfs;
 
-pub use ntfs::Ntfs;
+pub use bitlocker::{BitLocker, BitLockerCipher, BitLockerKey};
+pub use corestorage::{CoreStorage, CoreStorageCipher};
+pub use luks::{Luks, LuksCipher};
+pub use ntfs::{Ntfs, Records, Walk, WalkEntry};
+pub use ntfs::{AllocationRuns, ClusterBitmap, ClusterMap, ClusterOwner, Run, UnallocatedReader};
+pub use ntfs::{Attribute, AttributeValue, FileName, FileRecord, FileReference, RecordError, StandardInformation, ATTRIBUTE_TYPE_CODE, FILE_NAME_TYPE};
+pub use ntfs::{AttributeReader, IndexEntries, IndexEntry, StreamReader, WofReader, FILE_PROVIDER_COMPRESSION};
+pub use ntfs::{DeletedFile, DeletedStream, RecoveredRun};
+pub use ntfs::{LogClient, LogFile, LogOperation, LogRecord, LogRecords, LogUpdate, Replay, RestartArea};
+pub use ntfs::{Ace, Acl, ReparseData, ReparsePoint, SecurityDescriptor, Sid};
+pub use ntfs::{UsnExtent, UsnJournal, UsnRecord, UsnRecords, VolumeInfo};
 
 
 pub fn parse<R>(mut device: Device<R>) -> io::Result<impl Volume<Device<R>>>
@@ -15,16 +29,16 @@ where R: Block + Read + Seek {
     debug!("Block size: {}", block_size);
 
     let mut header: [u8; 512] = [0; 512];
-    device.read_exact(&mut header).or_else(|err| {
+    device.read_exact(&mut header).map_err(|err| {
         eprintln!("ERROR: Read Failed: {}", err);
-        Err(err)
+        err
     }).and_then(|_| {
 
         debug_xxd!(&header, 0);
 
         if Ntfs::<R>::is_supported(&header) {
             debug!("Filesystem: NTFS");
-            Ntfs::with_header(device, &mut header)
+            Ntfs::with_header(device, &header)
         } else {
             Err(io::Error::from(io::ErrorKind::NotFound))
         }
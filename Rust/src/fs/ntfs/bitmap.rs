@@ -0,0 +1,206 @@
+use core::cmp;
+use core::ops::Range;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::runs::Run;
+use super::super::super::utils::iadd;
+
+
+/// The cluster allocation bitmap of a volume, from `$Bitmap`.
+///
+/// Each bit is set if its cluster is in use.  Clusters beyond the end of the volume count as
+/// allocated.
+#[derive(Clone, Debug)]
+pub struct ClusterBitmap {
+    bits: Vec<u8>,
+    clusters: u64,
+}
+
+impl ClusterBitmap {
+
+    /// Creates a new `ClusterBitmap` from the value of `$Bitmap` for a volume of `clusters`.
+    pub fn new(bits: Vec<u8>, clusters: u64) -> Self {
+        let clusters = cmp::min(clusters, bits.len() as u64 * 8);
+        Self {
+            bits: bits,
+            clusters: clusters,
+        }
+    }
+
+    /// Get the number of clusters on the volume.
+    pub fn cluster_count(&self) -> u64 { self.clusters }
+
+    /// Is a cluster in use?
+    pub fn is_allocated(&self, lcn: u64) -> bool {
+        lcn >= self.clusters || self.bits[(lcn / 8) as usize] & (1 << (lcn % 8)) != 0
+    }
+
+    /// Count the clusters in use in a range.
+    pub fn count_allocated(&self, lcn: u64, length: u64) -> u64 {
+        let end = lcn.saturating_add(length);
+        let beyond = end - cmp::max(lcn, cmp::min(end, self.clusters));
+        let counted = self.runs(cmp::min(lcn, self.clusters), true)
+            .take_while(|r| r.start < end)
+            .map(|r| cmp::min(r.end, end) - r.start)
+            .sum::<u64>();
+        counted + beyond
+    }
+
+    /// Get the number of clusters in use.
+    pub fn allocated_count(&self) -> u64 {
+        self.clusters - self.free_count()
+    }
+
+    /// Get the number of free clusters.
+    pub fn free_count(&self) -> u64 {
+        self.free_runs().map(|r| r.end - r.start).sum()
+    }
+
+    /// Iterate over the ranges of clusters in use.
+    pub fn allocated_runs(&self) -> AllocationRuns<'_> {
+        self.runs(0, true)
+    }
+
+    /// Iterate over the ranges of free clusters.
+    pub fn free_runs(&self) -> AllocationRuns<'_> {
+        self.runs(0, false)
+    }
+
+    fn runs(&self, lcn: u64, allocated: bool) -> AllocationRuns<'_> {
+        AllocationRuns {
+            bitmap: self,
+            lcn: lcn,
+            allocated: allocated,
+        }
+    }
+
+    /// Find the next cluster from `lcn` which is or is not in use, or the end of the volume.
+    fn find(&self, lcn: u64, allocated: bool) -> u64 {
+        let skip = if allocated { 0x00 } else { 0xFF };
+        let mut lcn = lcn;
+        while lcn < self.clusters {
+            if lcn.is_multiple_of(8) && self.bits[(lcn / 8) as usize] == skip {
+                lcn += 8;
+            } else if self.is_allocated(lcn) == allocated {
+                return lcn;
+            } else {
+                lcn += 1;
+            }
+        }
+        self.clusters
+    }
+}
+
+
+/// An iterator over the ranges of clusters which are, or are not, in use.
+pub struct AllocationRuns<'a> {
+    bitmap: &'a ClusterBitmap,
+    lcn: u64,
+    allocated: bool,
+}
+
+impl<'a> Iterator for AllocationRuns<'a> {
+    type Item = Range<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.bitmap.find(self.lcn, self.allocated);
+        if start >= self.bitmap.clusters {
+            self.lcn = start;
+            return None;
+        }
+        let end = self.bitmap.find(start, !self.allocated);
+        self.lcn = end;
+        Some(start..end)
+    }
+}
+
+
+/// A reader for the free clusters of a volume, one after another.
+pub struct UnallocatedReader<'a, R> {
+    inner: &'a mut R,
+    runs: Vec<Run>,     // VCNs count the free clusters before each run
+    cluster_size: u64,
+    size: u64,
+    pos: u64,
+}
+
+impl<'a, R> UnallocatedReader<'a, R> {
+
+    /// Creates a new `UnallocatedReader` for the free clusters in a bitmap.
+    pub fn new(inner: &'a mut R, bitmap: &ClusterBitmap, cluster_size: u64) -> Self {
+        let mut runs = Vec::new();
+        let mut vcn = 0;
+        for range in bitmap.free_runs() {
+            runs.push(Run { vcn: vcn, lcn: Some(range.start), length: range.end - range.start });
+            vcn += range.end - range.start;
+        }
+        Self {
+            inner: inner,
+            runs: runs,
+            cluster_size: cluster_size,
+            size: vcn * cluster_size,
+            pos: 0,
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R { self.inner }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R { self.inner }
+
+    /// Get the number of free bytes.
+    pub fn size(&self) -> u64 { self.size }
+
+    /// Get the offset on the volume of an offset in the free clusters.
+    pub fn volume_offset(&self, offset: u64) -> Option<u64> {
+        let run = self.find_run(offset / self.cluster_size)?;
+        Some(run.lcn? * self.cluster_size + offset - run.vcn * self.cluster_size)
+    }
+
+    /// Find the run containing a VCN.
+    fn find_run(&self, vcn: u64) -> Option<&Run> {
+        let index = match self.runs.binary_search_by(|r| r.vcn.cmp(&vcn)) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        self.runs.get(index).filter(|r| vcn - r.vcn < r.length)
+    }
+}
+
+impl<'a, R> Read for UnallocatedReader<'a, R>
+where R: Read + Seek {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let (location, end) = match self.find_run(self.pos / self.cluster_size) {
+            Some(Run { vcn, lcn: Some(lcn), length }) => {
+                let offset = self.pos - vcn * self.cluster_size;
+                (lcn * self.cluster_size + offset, (vcn + length) * self.cluster_size)
+            }
+            _ => {
+                eprintln!("ERROR: Free cluster {} is not mapped", self.pos / self.cluster_size);
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+        };
+        let count = cmp::min(buf.len() as u64, end - self.pos) as usize;
+        self.inner.seek(SeekFrom::Start(location))?;
+        let nread = self.inner.read(&mut buf[..count])?;
+        self.pos += nread as u64;
+        Ok(nread)
+    }
+}
+
+impl<'a, R> Seek for UnallocatedReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(n) => n,
+            SeekFrom::Current(n) => iadd(self.pos, n)?,
+            SeekFrom::End(n) => iadd(self.size, n)?,
+        };
+        Ok(self.pos)
+    }
+}
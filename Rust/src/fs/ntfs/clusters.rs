@@ -0,0 +1,214 @@
+use core::cmp;
+
+use super::record::{FileRecord, FileReference, ATTRIBUTE_TYPE_CODE};
+use super::MFT_RECORD;
+
+
+/// The attribute holding some bytes of the volume.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterOwner {
+    /// The base record of the file.
+    pub file: FileReference,
+    /// The file record segment with the attribute, `file` itself or one of its extensions.
+    pub segment: u64,
+    pub type_code: u32,
+    pub name: String,
+    /// Offset in the value of the first of the bytes it holds.
+    pub offset: u64,
+    /// VCN of that byte, `None` for a value resident in its file record.
+    pub vcn: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct Attribute {
+    file: FileReference,
+    segment: u64,
+    type_code: u32,
+    name: String,
+}
+
+/// Part of the value of an attribute, clusters on the volume or bytes in the `$MFT` if resident.
+#[derive(Clone, Copy, Debug)]
+struct Extent {
+    start: u64,         // LCN, or offset in the $MFT for a resident value
+    length: u64,        // clusters, or bytes for a resident value
+    vcn: u64,
+    attribute: usize,
+}
+
+impl Extent {
+    fn end(&self) -> u64 { self.start.saturating_add(self.length) }
+}
+
+/// Find the extents overlapping a range, given they are sorted by start.
+fn overlapping(extents: &[Extent], longest: u64, start: u64, end: u64) -> Vec<&Extent> {
+    let last = extents.partition_point(|e| e.start < end);
+    let mut found: Vec<&Extent> = extents[..last].iter().rev()
+        .take_while(|e| e.start.saturating_add(longest) > start)
+        .filter(|e| e.end() > start)
+        .collect();
+    found.reverse();
+    found
+}
+
+
+/// A map from the clusters of a volume to the attributes which hold them.
+///
+/// Every run of every attribute of the records in use is included, so metadata files and index
+/// buffers are too.  Values resident in the `$MFT` are found within its clusters.
+#[derive(Clone, Debug)]
+pub struct ClusterMap {
+    attributes: Vec<Attribute>,
+    extents: Vec<Extent>,
+    longest: u64,
+    resident: Vec<Extent>,
+    cluster_size: u64,
+    record_size: u64,
+}
+
+impl ClusterMap {
+
+    /// Creates a new `ClusterMap` from file record segments, which are not merged with their
+    /// extensions.
+    pub fn new<I>(records: I, cluster_size: u64, record_size: u64) -> Self
+    where I: IntoIterator<Item = FileRecord> {
+        let mut map = Self {
+            attributes: Vec::new(),
+            extents: Vec::new(),
+            longest: 0,
+            resident: Vec::new(),
+            cluster_size: cluster_size,
+            record_size: record_size,
+        };
+        for record in records {
+            map.insert(&record);
+        }
+        map.extents.sort_unstable_by_key(|e| e.start);
+        map.resident.sort_unstable_by_key(|e| e.start);
+        map.longest = map.extents.iter().map(|e| e.length).max().unwrap_or(0);
+        debug!("{} extents, {} resident values", map.extents.len(), map.resident.len());
+        map
+    }
+
+    fn insert(&mut self, record: &FileRecord) {
+        let file = record.base_record().unwrap_or_else(|| record.reference());
+        for attribute in record.attributes() {
+            let index = self.attributes.len();
+            if let Some(offset) = attribute.value_offset() {
+                self.resident.push(Extent {
+                    start: record.number() * self.record_size + offset,
+                    length: attribute.size(),
+                    vcn: 0,
+                    attribute: index,
+                });
+            }
+            for run in attribute.runs() {
+                if let Some(lcn) = run.lcn {
+                    self.extents.push(Extent { start: lcn, length: run.length, vcn: run.vcn, attribute: index });
+                }
+            }
+            self.attributes.push(Attribute {
+                file: file,
+                segment: record.number(),
+                type_code: attribute.type_code(),
+                name: attribute.name().to_string(),
+            });
+        }
+    }
+
+    fn owner(&self, extent: &Extent, offset: u64, vcn: Option<u64>) -> ClusterOwner {
+        let attribute = &self.attributes[extent.attribute];
+        ClusterOwner {
+            file: attribute.file,
+            segment: attribute.segment,
+            type_code: attribute.type_code,
+            name: attribute.name.clone(),
+            offset: offset,
+            vcn: vcn,
+        }
+    }
+
+    /// Find the attributes holding any of `length` bytes at an offset on the volume.
+    ///
+    /// Clusters of the `$MFT` are also held by the values resident in its records.  More than
+    /// one attribute holding the same cluster means they are cross-linked.
+    pub fn owners(&self, offset: u64, length: u64) -> Vec<ClusterOwner> {
+        let end = offset.saturating_add(cmp::max(length, 1));
+        let (first, last) = (offset / self.cluster_size, (end - 1) / self.cluster_size + 1);
+        let mut owners = Vec::new();
+        for extent in overlapping(&self.extents, self.longest, first, last) {
+            // extents of corrupt runs may lie past the largest offset
+            let (extent_start, extent_end, vcn_start) = match (
+                extent.start.checked_mul(self.cluster_size),
+                extent.end().checked_mul(self.cluster_size),
+                extent.vcn.checked_mul(self.cluster_size),
+            ) {
+                (Some(start), Some(end), Some(vcn)) => (start, end, vcn),
+                _ => continue,
+            };
+            // the first byte asked about in this extent
+            let start = cmp::max(offset, extent_start);
+            let value_offset = vcn_start + start - extent_start;
+            owners.push(self.owner(extent, value_offset, Some(value_offset / self.cluster_size)));
+
+            let attribute = &self.attributes[extent.attribute];
+            if attribute.file.record != MFT_RECORD || attribute.type_code != ATTRIBUTE_TYPE_CODE::DATA as u32 || !attribute.name.is_empty() {
+                continue;
+            }
+            let stop = cmp::min(end, extent_end);
+            let mft_end = value_offset + stop - start;
+            for value in overlapping(&self.resident, self.record_size, value_offset, mft_end) {
+                let from = cmp::max(value_offset, value.start);
+                owners.push(self.owner(value, from - value.start, None));
+            }
+        }
+        owners
+    }
+
+    /// Find the attributes holding a cluster.
+    pub fn cluster_owners(&self, lcn: u64) -> Vec<ClusterOwner> {
+        match lcn.checked_mul(self.cluster_size) {
+            Some(offset) => self.owners(offset, self.cluster_size),
+            None => Vec::new(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::record::tests::{file_record, non_resident, resident};
+    use super::super::record::ATTRIBUTE_TYPE_CODE::DATA;
+
+    fn parse(number: u64, attributes: &[Vec<u8>]) -> FileRecord {
+        FileRecord::parse(&file_record(1, 1, 0, attributes), number, 4096, 0x100).unwrap()
+    }
+
+    fn owned(owners: &[ClusterOwner]) -> Vec<(u64, &str, u64, Option<u64>)> {
+        owners.iter().map(|o| (o.file.record, o.name.as_str(), o.offset, o.vcn)).collect()
+    }
+
+    #[test]
+    fn owners() {
+        // the $MFT holds 8 records in clusters 2 and 3, record 5 is in the second
+        let records = vec![
+            parse(MFT_RECORD, &[non_resident(DATA, "", 0, 1, &[0x11, 0x02, 0x02], 8192)]),
+            parse(5, &[resident(DATA, "", b"hello"), non_resident(DATA, "big", 0, 3, &[0x11, 0x04, 0x10], 16384)]),
+            parse(6, &[non_resident(DATA, "", 0, 0, &[0x11, 0x01, 0x12], 4096)]),
+        ];
+        let map = ClusterMap::new(records, 4096, 1024);
+
+        assert_eq!(owned(&map.owners(0x11 * 4096 + 100, 1)), vec![(5, "big", 4096 + 100, Some(1))]);
+        assert_eq!(owned(&map.cluster_owners(0x12)), vec![(5, "big", 8192, Some(2)), (6, "", 0, Some(0))]);
+        assert_eq!(owned(&map.cluster_owners(0x14)), vec![]);
+
+        let owners = map.cluster_owners(3);
+        assert_eq!(owners.len(), 2);
+        assert_eq!(owned(&owners[..1]), vec![(MFT_RECORD, "", 4096, Some(1))]);
+        assert_eq!((owners[1].file.record, owners[1].offset, owners[1].vcn), (5, 0, None));
+        assert_eq!(owners[1].segment, 5);
+
+        assert_eq!(owned(&map.cluster_owners(u64::MAX)), vec![]);
+    }
+}
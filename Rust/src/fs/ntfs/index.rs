@@ -0,0 +1,432 @@
+use core::cmp::Ordering;
+use core::mem;
+use std::io;
+use std::io::{Read, Seek};
+
+use super::reader::AttributeReader;
+use super::record::{read_multi_sector, FileName, FileReference, INDEX_SIGNATURE, MULTI_SECTOR_HEADER};
+
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct INDEX_HEADER {
+    FirstIndexEntry: u32,       // relative to this header
+    FirstFreeByte: u32,         // relative to this header
+    BytesAvailable: u32,
+    Flags: u8,                  // 0x01 - entries have children in the index allocation
+    Reserved: [u8; 3],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct INDEX_ROOT {
+    IndexedAttributeType: u32,  // FILE_NAME, or 0 for view indexes
+    CollationRule: u32,         // 0x01 - file names, 0x10 - ULONG, 0x13 - ULONGs, ...
+    BytesPerIndexBuffer: u32,
+    BlocksPerIndexBuffer: u8,
+    Reserved: [u8; 3],
+    IndexHeader: INDEX_HEADER,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct INDEX_ALLOCATION_BUFFER {
+    MultiSectorHeader: MULTI_SECTOR_HEADER,     // 'INDX'
+    Lsn: u64,
+    ThisBlock: u64,             // VCN of this buffer
+    IndexHeader: INDEX_HEADER,
+}
+
+const INDEX_ENTRY_NODE: u16 = 0x0001;   // followed by the VCN of the child node
+const INDEX_ENTRY_END: u16 = 0x0002;    // last entry of a node, has no key
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct INDEX_ENTRY {
+    // union {
+    //     FileReference: FILE_REFERENCE,
+    //     struct {
+    DataOffset: u16,
+    DataLength: u16,
+    Reserved: u32,
+    //     },
+    // },
+    Length: u16,
+    AttributeLength: u16,       // length of the key
+    Flags: u16,                 // INDEX_ENTRY_*
+    Reserved2: u16,
+}
+
+// deeper trees would need more entries than fit in an MFT
+const MAXIMUM_DEPTH: usize = 32;
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+
+/// An entry of an index.
+#[derive(Clone, Debug)]
+pub struct IndexEntry {
+    reference: u64,
+    flags: u16,
+    key: Vec<u8>,
+    data: Vec<u8>,
+    child: Option<u64>,
+}
+
+impl IndexEntry {
+
+    /// Get the file referenced by an entry of a file name index.
+    pub fn file_reference(&self) -> FileReference {
+        FileReference::from_u64(self.reference)
+    }
+
+    pub fn key(&self) -> &[u8] { &self.key }
+
+    /// Get the data of an entry of a view index.
+    pub fn data(&self) -> &[u8] { &self.data }
+
+    /// Parse the key of an entry of a file name index.
+    pub fn file_name(&self) -> io::Result<FileName> {
+        FileName::parse(&self.key)
+    }
+
+    fn is_end(&self) -> bool {
+        self.flags & INDEX_ENTRY_END != 0
+    }
+}
+
+
+/// Parse the entries of an index node, up to and including the end entry.
+fn parse_entries(data: &[u8], view: bool) -> io::Result<Vec<IndexEntry>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    loop {
+        if pos + mem::size_of::<INDEX_ENTRY>() > data.len() {
+            return invalid(&format!("Index Entry at {} is truncated", pos));
+        }
+        let entry: INDEX_ENTRY = unsafe{ *(data[pos..].as_ptr() as *const INDEX_ENTRY) };
+        let length = entry.Length as usize;
+        let key_end = mem::size_of::<INDEX_ENTRY>() + entry.AttributeLength as usize;
+        let has_child = entry.Flags & INDEX_ENTRY_NODE != 0;
+        if length < key_end || !length.is_multiple_of(8) || pos + length > data.len() || (has_child && length < key_end + 8) {
+            return invalid(&format!("Invalid Index Entry Length: {}", length));
+        }
+        let raw = &data[pos..pos + length];
+
+        let child = if has_child {
+            let mut vcn = [0; 8];
+            vcn.copy_from_slice(&raw[length - 8..]);
+            Some(u64::from_le_bytes(vcn))
+        } else {
+            None
+        };
+        let mut reference = [0; 8];
+        reference.copy_from_slice(&raw[..8]);
+        let (key, value) = if entry.Flags & INDEX_ENTRY_END != 0 {
+            (Vec::new(), Vec::new())
+        } else if view {
+            let start = entry.DataOffset as usize;
+            let end = start + entry.DataLength as usize;
+            if end > length {
+                return invalid(&format!("Invalid Index Entry Data Length: {}", { entry.DataLength }));
+            }
+            (raw[mem::size_of::<INDEX_ENTRY>()..key_end].to_vec(), raw[start..end].to_vec())
+        } else {
+            (raw[mem::size_of::<INDEX_ENTRY>()..key_end].to_vec(), Vec::new())
+        };
+
+        entries.push(IndexEntry {
+            reference: u64::from_le_bytes(reference),
+            flags: entry.Flags,
+            key: key,
+            data: value,
+            child: child,
+        });
+        if entry.Flags & INDEX_ENTRY_END != 0 {
+            return Ok(entries);
+        }
+        pos += length;
+    }
+}
+
+/// Get the entries of a node described by an `INDEX_HEADER` at the start of `data`.
+fn node_entries(data: &[u8], view: bool) -> io::Result<Vec<IndexEntry>> {
+    if data.len() < mem::size_of::<INDEX_HEADER>() {
+        return invalid("Index Header is truncated");
+    }
+    let header: INDEX_HEADER = unsafe{ *(data.as_ptr() as *const INDEX_HEADER) };
+    let start = header.FirstIndexEntry as usize;
+    let end = header.FirstFreeByte as usize;
+    if start < mem::size_of::<INDEX_HEADER>() || start > end || end > data.len() {
+        return invalid(&format!("Invalid Index Header: {} - {}", start, end));
+    }
+    parse_entries(&data[start..end], view)
+}
+
+
+/// Compare names as NTFS collates them, by code units converted with an upcase table.
+pub fn collate_names(upcase: &[u16], a: &[u16], b: &[u16]) -> Ordering {
+    let up = |c: &u16| *upcase.get(*c as usize).unwrap_or(c);
+    a.iter().map(up).cmp(b.iter().map(up))
+}
+
+/// An upcase table for ASCII, for use until the volume's `$UpCase` is loaded.
+pub fn ascii_upcase() -> Vec<u16> {
+    (0..128).map(|c| if c >= b'a' as u16 && c <= b'z' as u16 { c - 32 } else { c }).collect()
+}
+
+
+struct Node {
+    entries: Vec<IndexEntry>,
+    pos: usize,
+    descended: bool,    // has the child of the current entry been visited
+}
+
+/// An iterator over the entries of an index, in collation order.
+pub struct IndexEntries<'a, R> {
+    allocation: Option<AttributeReader<'a, R>>,
+    bitmap: Vec<u8>,
+    buffer_size: u64,
+    block_size: u64,    // unit of the VCNs in child pointers
+    view: bool,
+    stack: Vec<Node>,
+}
+
+impl<'a, R> IndexEntries<'a, R>
+where R: Read + Seek {
+
+    /// Creates a new iterator over an index.
+    ///
+    /// `root` is the value of the `$INDEX_ROOT`, `allocation` the reader for the
+    /// `$INDEX_ALLOCATION` and `bitmap` the value of the `$BITMAP` of the index.
+    pub fn new(root: &[u8], allocation: Option<AttributeReader<'a, R>>, bitmap: Vec<u8>, buffer_size: u64, cluster_size: u64) -> io::Result<Self> {
+        if root.len() < mem::size_of::<INDEX_ROOT>() {
+            return invalid("Index Root is truncated");
+        }
+        let header: INDEX_ROOT = unsafe{ *(root.as_ptr() as *const INDEX_ROOT) };
+        debug!("{:#?}", header);
+        if header.BytesPerIndexBuffer as u64 != buffer_size {
+            eprintln!("WARNING: Index Buffer Size {} differs from the volume's {}", { header.BytesPerIndexBuffer }, buffer_size);
+        }
+        let buffer_size = header.BytesPerIndexBuffer as u64;
+        if buffer_size < mem::size_of::<INDEX_ALLOCATION_BUFFER>() as u64 || buffer_size > 1 << 20 {
+            return invalid(&format!("Invalid Index Buffer Size: {}", buffer_size));
+        }
+
+        // small buffers are addressed in 512 byte blocks rather than clusters
+        let block_size = if buffer_size < cluster_size { 512 } else { cluster_size };
+        let view = header.IndexedAttributeType == 0;
+        let entries = node_entries(&root[mem::size_of::<INDEX_ROOT>() - mem::size_of::<INDEX_HEADER>()..], view)?;
+
+        Ok(Self {
+            allocation: allocation,
+            bitmap: bitmap,
+            buffer_size: buffer_size,
+            block_size: block_size,
+            view: view,
+            stack: vec![Node { entries: entries, pos: 0, descended: false }],
+        })
+    }
+
+    /// Read the node at a VCN of the index allocation.
+    fn read_node(&mut self, vcn: u64) -> io::Result<Vec<IndexEntry>> {
+        if self.stack.len() >= MAXIMUM_DEPTH {
+            return invalid("Index is too deep");
+        }
+        let offset = vcn * self.block_size;
+        let buffer = offset / self.buffer_size;
+        let allocated = self.bitmap.get((buffer / 8) as usize).is_some_and(|b| b & (1 << (buffer % 8)) != 0);
+        if !allocated {
+            eprintln!("WARNING: Index Buffer {} is not allocated", vcn);
+            return Ok(Vec::new());
+        }
+
+        let reader = match &mut self.allocation {
+            Some(reader) => reader,
+            None => return invalid("Index has no allocation"),
+        };
+        let data = read_multi_sector(reader, offset, self.buffer_size, INDEX_SIGNATURE)?;
+        if data.len() < mem::size_of::<INDEX_ALLOCATION_BUFFER>() {
+            return invalid(&format!("Index Buffer {} is truncated", vcn));
+        }
+        let header: INDEX_ALLOCATION_BUFFER = unsafe{ *(data.as_ptr() as *const INDEX_ALLOCATION_BUFFER) };
+        if header.ThisBlock != vcn {
+            return invalid(&format!("Index Buffer {} claims to be {}", vcn, { header.ThisBlock }));
+        }
+        let start = mem::size_of::<INDEX_ALLOCATION_BUFFER>() - mem::size_of::<INDEX_HEADER>();
+        node_entries(&data[start..], self.view)
+    }
+
+    /// Position the iterator before the first entry which does not compare less than the target.
+    pub fn seek<F>(&mut self, compare: F) -> io::Result<()>
+    where F: Fn(&IndexEntry) -> Ordering {
+        self.stack.truncate(1);
+        loop {
+            let child = match self.stack.last_mut() {
+                Some(node) => {
+                    let pos = node.entries.iter().position(|entry| entry.is_end() || compare(entry) != Ordering::Less);
+                    node.pos = pos.unwrap_or(node.entries.len());
+                    // entries before the bound may be in the child, so visit it first
+                    let child = node.entries.get(node.pos).and_then(|entry| entry.child);
+                    node.descended = child.is_some();
+                    child
+                }
+                None => return Ok(()),
+            };
+            match child {
+                Some(vcn) => {
+                    let entries = self.read_node(vcn)?;
+                    self.stack.push(Node { entries: entries, pos: 0, descended: false });
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Find an entry by descending the tree, given how each entry compares to the target.
+    pub fn find<F>(mut self, compare: F) -> io::Result<Option<IndexEntry>>
+    where F: Fn(&IndexEntry) -> Ordering {
+        let mut entries = self.stack.pop().map(|n| n.entries).unwrap_or_default();
+        loop {
+            let mut child = None;
+            for entry in entries {
+                let order = if entry.is_end() { Ordering::Greater } else { compare(&entry) };
+                match order {
+                    Ordering::Less => continue,
+                    Ordering::Equal => return Ok(Some(entry)),
+                    Ordering::Greater => {
+                        child = entry.child;
+                        break;
+                    }
+                }
+            }
+            match child {
+                Some(vcn) => {
+                    // count the depth as the iterator would
+                    self.stack.push(Node { entries: Vec::new(), pos: 0, descended: false });
+                    entries = self.read_node(vcn)?;
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+impl<'a, R> Iterator for IndexEntries<'a, R>
+where R: Read + Seek {
+    type Item = io::Result<IndexEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (child, entry) = {
+                let node = self.stack.last_mut()?;
+                if node.pos >= node.entries.len() {
+                    self.stack.pop();
+                    continue;
+                }
+                let entry = &node.entries[node.pos];
+                if !node.descended && entry.child.is_some() {
+                    node.descended = true;
+                    (entry.child, None)
+                } else {
+                    node.pos += 1;
+                    node.descended = false;
+                    (None, if entry.is_end() { None } else { Some(entry.clone()) })
+                }
+            };
+
+            if let Some(vcn) = child {
+                match self.read_node(vcn) {
+                    Ok(entries) => self.stack.push(Node { entries: entries, pos: 0, descended: false }),
+                    Err(err) => {
+                        self.stack.clear();
+                        return Some(Err(err));
+                    }
+                }
+            } else if let Some(entry) = entry {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// The root of an index of an attribute type, or a view index for 0, with no allocation.
+    ///
+    /// Each entry is a file reference and a key, in collation order.
+    pub fn index_root(attribute_type: u32, entries: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut root = vec![0u8; 32];
+        root[..4].copy_from_slice(&attribute_type.to_le_bytes());
+        root[8..12].copy_from_slice(&4096u32.to_le_bytes());
+        for (reference, key) in entries {
+            let mut entry = vec![0u8; 16];
+            entry[..8].copy_from_slice(&reference.to_le_bytes());
+            entry.extend_from_slice(key);
+            entry.resize(entry.len().next_multiple_of(8), 0);
+            let length = entry.len() as u16;
+            entry[8..10].copy_from_slice(&length.to_le_bytes());
+            entry[10..12].copy_from_slice(&(key.len() as u16).to_le_bytes());
+            root.extend_from_slice(&entry);
+        }
+        let mut end = [0u8; 16];
+        end[8..10].copy_from_slice(&16u16.to_le_bytes());
+        end[12..14].copy_from_slice(&INDEX_ENTRY_END.to_le_bytes());
+        root.extend_from_slice(&end);
+        let free = (root.len() - 16) as u32;
+        root[16..20].copy_from_slice(&16u32.to_le_bytes());
+        root[20..24].copy_from_slice(&free.to_le_bytes());
+        root
+    }
+
+    /// The root of a view index holding the keys, with no allocation.
+    fn view_root(keys: &[u64]) -> Vec<u8> {
+        let entries: Vec<(u64, Vec<u8>)> = keys.iter().map(|key| (0, key.to_le_bytes().to_vec())).collect();
+        index_root(0, &entries)
+    }
+
+    fn keys_from(root: &[u8], target: u64) -> Vec<u64> {
+        let mut entries = IndexEntries::<Cursor<Vec<u8>>>::new(root, None, Vec::new(), 4096, 4096).unwrap();
+        entries.seek(|entry| {
+            let mut key = [0; 8];
+            key.copy_from_slice(entry.key());
+            u64::from_le_bytes(key).cmp(&target)
+        }).unwrap();
+        entries.map(|entry| {
+            let mut key = [0; 8];
+            key.copy_from_slice(entry.unwrap().key());
+            u64::from_le_bytes(key)
+        }).collect()
+    }
+
+    #[test]
+    fn seek() {
+        let root = view_root(&[1, 3, 3, 5]);
+        assert_eq!(keys_from(&root, 0), vec![1, 3, 3, 5]);
+        assert_eq!(keys_from(&root, 3), vec![3, 3, 5]);
+        assert_eq!(keys_from(&root, 4), vec![5]);
+        assert_eq!(keys_from(&root, 9), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn invalid_buffer_size() {
+        let mut root = [0u8; 32];
+        for &size in [0u32, 8, 39, 2 << 20].iter() {
+            root[8..12].copy_from_slice(&size.to_le_bytes());
+            let entries = IndexEntries::<Cursor<Vec<u8>>>::new(&root, None, Vec::new(), size as u64, 4096);
+            assert_eq!(entries.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,698 @@
+use core::cmp;
+use core::mem;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::record::{apply_fixups, read_multi_sector, MULTI_SECTOR_HEADER};
+
+
+pub const RESTART_SIGNATURE: &[u8; 4] = b"RSTR";
+pub const RECORD_SIGNATURE: &[u8; 4] = b"RCRD";
+
+/// The first pages after the restart pages hold copies of the tail of the log.
+const LOG_TAIL_PAGES_V1: u64 = 2;
+const LOG_TAIL_PAGES_V2: u64 = 32;
+
+const RESTART_VOLUME_IS_CLEAN: u16 = 0x0002;
+const LOGFILE_NO_CLIENT: u16 = 0xFFFF;
+
+const LOG_RECORD_MULTI_PAGE: u16 = 0x0001;
+
+pub const LOG_RECORD_CLIENT: u32 = 1;
+pub const LOG_RECORD_CHECKPOINT: u32 = 2;
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct RESTART_PAGE_HEADER {
+    MultiSectorHeader: MULTI_SECTOR_HEADER,     // 'RSTR', 'CHKD'
+    ChkDskLsn: u64,
+    SystemPageSize: u32,
+    LogPageSize: u32,
+    RestartAreaOffset: u16,
+    MinorVersion: i16,
+    MajorVersion: i16,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct RESTART_AREA {
+    CurrentLsn: u64,
+    LogClients: u16,
+    ClientFreeList: u16,
+    ClientInUseList: u16,       // LOGFILE_NO_CLIENT if none
+    Flags: u16,                 // RESTART_*
+    SeqNumberBits: u32,
+    RestartAreaLength: u16,
+    ClientArrayOffset: u16,     // from the start of the restart area
+    FileSize: u64,
+    LastLsnDataLength: u32,
+    LogRecordHeaderLength: u16,
+    LogPageDataOffset: u16,
+    RestartLogOpenCount: u32,
+    Reserved: u32,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct LOG_CLIENT_RECORD {
+    OldestLsn: u64,
+    ClientRestartLsn: u64,
+    PrevClient: u16,
+    NextClient: u16,
+    SeqNumber: u16,
+    Reserved: [u8; 6],
+    ClientNameLength: u32,      // in bytes
+    ClientName: [u16; 64],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct RECORD_PAGE_HEADER {
+    MultiSectorHeader: MULTI_SECTOR_HEADER,     // 'RCRD'
+    LastLsn: u64,               // or the file offset in a tail copy
+    Flags: u32,
+    PageCount: u16,
+    PagePosition: u16,
+    NextRecordOffset: u16,
+    Reserved: [u8; 6],
+    LastEndLsn: u64,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct LOG_RECORD_HEADER {
+    ThisLsn: u64,
+    ClientPreviousLsn: u64,
+    ClientUndoNextLsn: u64,
+    ClientDataLength: u32,
+    SeqNumber: u16,             // of the client
+    ClientIndex: u16,
+    RecordType: u32,            // LOG_RECORD_*
+    TransactionId: u32,
+    Flags: u16,                 // LOG_RECORD_*
+    Reserved: [u16; 3],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct NTFS_LOG_RECORD_HEADER {
+    RedoOperation: u16,         // LogOperation
+    UndoOperation: u16,
+    RedoOffset: u16,            // from the start of this header
+    RedoLength: u16,
+    UndoOffset: u16,
+    UndoLength: u16,
+    TargetAttribute: u16,       // index in the open attribute table
+    LcnsToFollow: u16,
+    RecordOffset: u16,
+    AttributeOffset: u16,
+    ClusterBlockOffset: u16,    // in 512 byte blocks from the target VCN
+    Reserved: u16,
+    TargetVcn: u64,
+    // LcnsForPage: [u64; LcnsToFollow],
+}
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+
+/// An operation in an NTFS log record, to redo or undo an update.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogOperation {
+    Noop,
+    CompensationLogRecord,
+    InitializeFileRecordSegment,
+    DeallocateFileRecordSegment,
+    WriteEndOfFileRecordSegment,
+    CreateAttribute,
+    DeleteAttribute,
+    UpdateResidentValue,
+    UpdateNonresidentValue,
+    UpdateMappingPairs,
+    DeleteDirtyClusters,
+    SetNewAttributeSizes,
+    AddIndexEntryRoot,
+    DeleteIndexEntryRoot,
+    AddIndexEntryAllocation,
+    DeleteIndexEntryAllocation,
+    WriteEndOfIndexBuffer,
+    SetIndexEntryVcnRoot,
+    SetIndexEntryVcnAllocation,
+    UpdateFileNameRoot,
+    UpdateFileNameAllocation,
+    SetBitsInNonresidentBitMap,
+    ClearBitsInNonresidentBitMap,
+    HotFix,
+    EndTopLevelAction,
+    PrepareTransaction,
+    CommitTransaction,
+    ForgetTransaction,
+    OpenNonresidentAttribute,
+    OpenAttributeTableDump,
+    AttributeNamesDump,
+    DirtyPageTableDump,
+    TransactionTableDump,
+    UpdateRecordDataRoot,
+    UpdateRecordDataAllocation,
+    Unknown(u16),
+}
+
+impl From<u16> for LogOperation {
+    fn from(value: u16) -> Self {
+        match value {
+            0x00 => LogOperation::Noop,
+            0x01 => LogOperation::CompensationLogRecord,
+            0x02 => LogOperation::InitializeFileRecordSegment,
+            0x03 => LogOperation::DeallocateFileRecordSegment,
+            0x04 => LogOperation::WriteEndOfFileRecordSegment,
+            0x05 => LogOperation::CreateAttribute,
+            0x06 => LogOperation::DeleteAttribute,
+            0x07 => LogOperation::UpdateResidentValue,
+            0x08 => LogOperation::UpdateNonresidentValue,
+            0x09 => LogOperation::UpdateMappingPairs,
+            0x0A => LogOperation::DeleteDirtyClusters,
+            0x0B => LogOperation::SetNewAttributeSizes,
+            0x0C => LogOperation::AddIndexEntryRoot,
+            0x0D => LogOperation::DeleteIndexEntryRoot,
+            0x0E => LogOperation::AddIndexEntryAllocation,
+            0x0F => LogOperation::DeleteIndexEntryAllocation,
+            0x10 => LogOperation::WriteEndOfIndexBuffer,
+            0x11 => LogOperation::SetIndexEntryVcnRoot,
+            0x12 => LogOperation::SetIndexEntryVcnAllocation,
+            0x13 => LogOperation::UpdateFileNameRoot,
+            0x14 => LogOperation::UpdateFileNameAllocation,
+            0x15 => LogOperation::SetBitsInNonresidentBitMap,
+            0x16 => LogOperation::ClearBitsInNonresidentBitMap,
+            0x17 => LogOperation::HotFix,
+            0x18 => LogOperation::EndTopLevelAction,
+            0x19 => LogOperation::PrepareTransaction,
+            0x1A => LogOperation::CommitTransaction,
+            0x1B => LogOperation::ForgetTransaction,
+            0x1C => LogOperation::OpenNonresidentAttribute,
+            0x1D => LogOperation::OpenAttributeTableDump,
+            0x1E => LogOperation::AttributeNamesDump,
+            0x1F => LogOperation::DirtyPageTableDump,
+            0x20 => LogOperation::TransactionTableDump,
+            0x21 => LogOperation::UpdateRecordDataRoot,
+            0x22 => LogOperation::UpdateRecordDataAllocation,
+            value => LogOperation::Unknown(value),
+        }
+    }
+}
+
+
+/// A client of the log, NTFS itself being the only one in practice.
+#[derive(Clone, Debug)]
+pub struct LogClient {
+    /// Records before this are no longer needed.
+    pub oldest_lsn: u64,
+    /// The checkpoint record to restart from.
+    pub client_restart_lsn: u64,
+    pub sequence_number: u16,
+    pub name: String,
+}
+
+/// The restart area of `$LogFile`, describing the log and where to restart from.
+#[derive(Clone, Debug)]
+pub struct RestartArea {
+    pub major_version: i16,
+    pub minor_version: i16,
+    pub system_page_size: u32,
+    pub log_page_size: u32,
+    pub chkdsk_lsn: u64,
+    pub current_lsn: u64,
+    pub flags: u16,             // RESTART_*
+    pub sequence_number_bits: u32,
+    pub file_size: u64,
+    pub log_record_header_length: u16,
+    pub log_page_data_offset: u16,
+    pub restart_log_open_count: u32,
+    /// The clients in use.
+    pub clients: Vec<LogClient>,
+}
+
+impl RestartArea {
+
+    /// Parse a restart page that has had its fixups applied.
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < mem::size_of::<RESTART_PAGE_HEADER>() {
+            return invalid(&format!("Invalid Restart Page Length: {}", data.len()));
+        }
+        let page: RESTART_PAGE_HEADER = unsafe{ *(data.as_ptr() as *const RESTART_PAGE_HEADER) };
+        debug!("{:#?}", page);
+        let start = page.RestartAreaOffset as usize;
+        if start + mem::size_of::<RESTART_AREA>() > data.len() {
+            return invalid(&format!("Invalid Restart Area Offset: {}", start));
+        }
+        let area: RESTART_AREA = unsafe{ *(data[start..].as_ptr() as *const RESTART_AREA) };
+        debug!("{:#?}", area);
+        if area.SeqNumberBits < 3 || area.SeqNumberBits > 61 || !page.LogPageSize.is_power_of_two()
+            || area.LogPageDataOffset as u32 >= page.LogPageSize {
+            return invalid(&format!("Invalid Restart Area: {} sequence bits, {} byte pages", { area.SeqNumberBits }, { page.LogPageSize }));
+        }
+
+        // the clients in use are a linked list through the client array
+        let mut clients = Vec::new();
+        let mut index = area.ClientInUseList;
+        while index != LOGFILE_NO_CLIENT {
+            if index >= area.LogClients || clients.len() >= area.LogClients as usize {
+                return invalid(&format!("Invalid Log Client: {}", index));
+            }
+            let offset = start + area.ClientArrayOffset as usize + index as usize * mem::size_of::<LOG_CLIENT_RECORD>();
+            if offset + mem::size_of::<LOG_CLIENT_RECORD>() > data.len() {
+                return invalid(&format!("Invalid Log Client Offset: {}", offset));
+            }
+            let client: LOG_CLIENT_RECORD = unsafe{ *(data[offset..].as_ptr() as *const LOG_CLIENT_RECORD) };
+            let name = client.ClientName;
+            let length = cmp::min(client.ClientNameLength as usize / 2, name.len());
+            clients.push(LogClient {
+                oldest_lsn: client.OldestLsn,
+                client_restart_lsn: client.ClientRestartLsn,
+                sequence_number: client.SeqNumber,
+                name: String::from_utf16_lossy(&name[..length]),
+            });
+            index = client.NextClient;
+        }
+
+        Ok(Self {
+            major_version: page.MajorVersion,
+            minor_version: page.MinorVersion,
+            system_page_size: page.SystemPageSize,
+            log_page_size: page.LogPageSize,
+            chkdsk_lsn: page.ChkDskLsn,
+            current_lsn: area.CurrentLsn,
+            flags: area.Flags,
+            sequence_number_bits: area.SeqNumberBits,
+            file_size: area.FileSize,
+            log_record_header_length: area.LogRecordHeaderLength,
+            log_page_data_offset: area.LogPageDataOffset,
+            restart_log_open_count: area.RestartLogOpenCount,
+            clients: clients,
+        })
+    }
+
+    /// Was the volume unmounted cleanly, with nothing in the log to replay?
+    pub fn is_clean(&self) -> bool {
+        self.clients.is_empty() || self.flags & RESTART_VOLUME_IS_CLEAN != 0
+    }
+}
+
+
+/// An update to redo or undo, the data of an NTFS client record.
+#[derive(Clone, Debug)]
+pub struct LogUpdate {
+    pub redo_operation: LogOperation,
+    pub undo_operation: LogOperation,
+    pub redo_data: Vec<u8>,
+    pub undo_data: Vec<u8>,
+    /// The index of the attribute in the open attribute table.
+    pub target_attribute: u16,
+    pub record_offset: u16,
+    pub attribute_offset: u16,
+    /// The offset of the update in 512 byte blocks from the target VCN.
+    pub cluster_block_offset: u16,
+    pub target_vcn: u64,
+    /// The clusters of the page being updated, starting at the target VCN.
+    pub lcns: Vec<u64>,
+}
+
+impl LogUpdate {
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let size = mem::size_of::<NTFS_LOG_RECORD_HEADER>();
+        if data.len() < size {
+            return invalid(&format!("Invalid NTFS Log Record Length: {}", data.len()));
+        }
+        let header: NTFS_LOG_RECORD_HEADER = unsafe{ *(data.as_ptr() as *const NTFS_LOG_RECORD_HEADER) };
+        let lcns_end = size + header.LcnsToFollow as usize * 8;
+        if lcns_end > data.len() {
+            return invalid(&format!("Invalid NTFS Log Record LCN Count: {}", { header.LcnsToFollow }));
+        }
+        let lcns = data[size..lcns_end].chunks(8).map(|c| {
+            u64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]])
+        }).collect();
+        let slice = |offset: u16, length: u16| -> io::Result<Vec<u8>> {
+            let (start, end) = (offset as usize, offset as usize + length as usize);
+            match data.get(start..end) {
+                Some(slice) => Ok(slice.to_vec()),
+                None if length == 0 => Ok(Vec::new()),
+                None => invalid(&format!("Invalid NTFS Log Record Data: {} + {}", offset, length)),
+            }
+        };
+
+        Ok(Self {
+            redo_operation: LogOperation::from(header.RedoOperation),
+            undo_operation: LogOperation::from(header.UndoOperation),
+            redo_data: slice(header.RedoOffset, header.RedoLength)?,
+            undo_data: slice(header.UndoOffset, header.UndoLength)?,
+            target_attribute: header.TargetAttribute,
+            record_offset: header.RecordOffset,
+            attribute_offset: header.AttributeOffset,
+            cluster_block_offset: header.ClusterBlockOffset,
+            target_vcn: header.TargetVcn,
+            lcns: lcns,
+        })
+    }
+}
+
+
+/// A record from the log.
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub lsn: u64,
+    /// The previous record of the same client, in any transaction.
+    pub previous_lsn: u64,
+    /// The next record to undo when rolling back the transaction.
+    pub undo_next_lsn: u64,
+    pub client_index: u16,
+    pub record_type: u32,       // LOG_RECORD_*
+    pub transaction_id: u32,
+    pub flags: u16,
+    pub data: Vec<u8>,
+    /// The update described by a client record.
+    pub update: Option<LogUpdate>,
+}
+
+impl LogRecord {
+
+    /// Is this a checkpoint, whose data is the client's restart area?
+    pub fn is_checkpoint(&self) -> bool {
+        self.record_type == LOG_RECORD_CHECKPOINT
+    }
+}
+
+
+/// A reader for the records of `$LogFile`.
+pub struct LogFile<R> {
+    inner: R,
+    restart: RestartArea,
+    size: u64,
+    first_page: u64,            // the first page of the circular log
+    page: Option<(u64, Vec<u8>)>,   // the last record page read
+}
+
+impl<R> LogFile<R> {
+
+    /// Get the restart area.
+    pub fn restart_area(&self) -> &RestartArea { &self.restart }
+
+    /// Does the log hold updates which must be replayed for the volume to be consistent?
+    pub fn needs_replay(&self) -> bool {
+        !self.restart.is_clean()
+    }
+
+    /// Get the byte offset in the file of a log sequence number.
+    pub fn lsn_offset(&self, lsn: u64) -> u64 {
+        (lsn << self.restart.sequence_number_bits) >> (self.restart.sequence_number_bits - 3)
+    }
+
+    /// Get the log sequence number of a byte offset, in the same pass over the log as `lsn`.
+    fn offset_lsn(&self, offset: u64, lsn: u64) -> u64 {
+        let bits = 64 - self.restart.sequence_number_bits;
+        let sequence = lsn >> bits;
+        let sequence = if offset < self.lsn_offset(lsn) { sequence + 1 } else { sequence };
+        sequence << bits | offset >> 3
+    }
+
+    /// Get the offset of the next record page, wrapping to the start of the circular log.
+    fn next_page(&self, offset: u64) -> u64 {
+        let page_size = self.restart.log_page_size as u64;
+        let next = (offset / page_size + 1) * page_size;
+        if next + page_size > self.size { self.first_page } else { next }
+    }
+}
+
+impl<R> LogFile<R>
+where R: Read + Seek {
+
+    /// Creates a new `LogFile` from a reader for its data, using the newest restart page.
+    pub fn new(mut inner: R, size: u64) -> io::Result<Self> {
+        let mut header = [0; mem::size_of::<RESTART_PAGE_HEADER>()];
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(&mut header)?;
+        let page: RESTART_PAGE_HEADER = unsafe{ *(header.as_ptr() as *const RESTART_PAGE_HEADER) };
+        let page_size = page.SystemPageSize as u64;
+        if !page.SystemPageSize.is_power_of_two() || page_size < 512 || page_size * 2 > size {
+            return invalid(&format!("Invalid Log System Page Size: {}", page_size));
+        }
+
+        let mut restart: Option<RestartArea> = None;
+        for offset in &[0, page_size] {
+            let area = match read_multi_sector(&mut inner, *offset, page_size, RESTART_SIGNATURE).and_then(|data| RestartArea::parse(&data)) {
+                Ok(area) => area,
+                Err(_) => {
+                    eprintln!("WARNING: Skipping the restart page at {}", offset);
+                    continue;
+                }
+            };
+            if restart.as_ref().is_none_or(|r| area.current_lsn > r.current_lsn) {
+                restart = Some(area);
+            }
+        }
+        let restart = match restart {
+            Some(restart) => restart,
+            None => return invalid("$LogFile has no valid restart page"),
+        };
+
+        let tail_pages = if restart.major_version >= 2 { LOG_TAIL_PAGES_V2 } else { LOG_TAIL_PAGES_V1 };
+        let first_page = page_size * 2 + restart.log_page_size as u64 * tail_pages;
+        let size = cmp::min(size, restart.file_size);
+        if first_page + restart.log_page_size as u64 > size {
+            return invalid(&format!("Invalid Log File Size: {}", size));
+        }
+        Ok(Self {
+            inner: inner,
+            restart: restart,
+            size: size,
+            first_page: first_page,
+            page: None,
+        })
+    }
+
+    /// Read a record page and apply its fixups.
+    fn read_page(&mut self, offset: u64) -> io::Result<&[u8]> {
+        if self.page.as_ref().is_none_or(|(o, _)| *o != offset) {
+            let mut data = vec![0; self.restart.log_page_size as usize];
+            self.inner.seek(SeekFrom::Start(offset))?;
+            self.inner.read_exact(&mut data)?;
+            apply_fixups(&mut data, RECORD_SIGNATURE)?;
+            let page: RECORD_PAGE_HEADER = unsafe{ *(data.as_ptr() as *const RECORD_PAGE_HEADER) };
+            debug!("{:#?}", page);
+            self.page = Some((offset, data));
+        }
+        Ok(&self.page.as_ref().unwrap().1)
+    }
+
+    /// Is there a record with a sequence number in the log, rather than one from an earlier pass?
+    fn has_record(&mut self, lsn: u64) -> bool {
+        let page_size = self.restart.log_page_size as u64;
+        let offset = self.lsn_offset(lsn);
+        let pos = (offset % page_size) as usize;
+        if offset < self.first_page || offset >= self.size || pos + mem::size_of::<LOG_RECORD_HEADER>() > page_size as usize {
+            return false;
+        }
+        match self.read_page(offset - pos as u64) {
+            Ok(data) => u64::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3],
+                                            data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) == lsn,
+            Err(_) => false,
+        }
+    }
+
+    /// Read the log record with a sequence number, and get the sequence number following it.
+    ///
+    /// Records continue past the end of a page into the data of the next page.
+    pub fn read_record(&mut self, lsn: u64) -> io::Result<(LogRecord, u64)> {
+        let page_size = self.restart.log_page_size as u64;
+        let data_offset = self.restart.log_page_data_offset as u64;
+        let header_size = mem::size_of::<LOG_RECORD_HEADER>() as u64;
+        let offset = self.lsn_offset(lsn);
+        let (mut page, mut pos) = (offset / page_size * page_size, offset % page_size);
+        if offset < self.first_page || offset >= self.size || pos < data_offset || pos + header_size > page_size {
+            return invalid(&format!("Invalid LSN: {:#x}", lsn));
+        }
+
+        let data = self.read_page(page).map_err(|err| {
+            eprintln!("ERROR: Log page at {}: {}", page, err);
+            err
+        })?;
+        let header: LOG_RECORD_HEADER = unsafe{ *(data[pos as usize..].as_ptr() as *const LOG_RECORD_HEADER) };
+        if header.ThisLsn != lsn {
+            return invalid(&format!("LSN {:#x} has a record for {:#x}", lsn, { header.ThisLsn }));
+        }
+        let length = header_size + header.ClientDataLength as u64;
+        if length > self.size {
+            return invalid(&format!("Invalid Log Record Length: {}", length));
+        }
+
+        let mut record = Vec::with_capacity(length as usize);
+        let first = page;
+        loop {
+            let count = cmp::min(length - record.len() as u64, page_size - pos);
+            let data = self.read_page(page).map_err(|err| {
+                eprintln!("ERROR: Log page at {}: {}", page, err);
+                err
+            })?;
+            record.extend_from_slice(&data[pos as usize..(pos + count) as usize]);
+            pos += count;
+            if record.len() as u64 == length {
+                break;
+            }
+            page = self.next_page(page);
+            pos = data_offset;
+            if page == first {
+                return invalid(&format!("Log Record {:#x} is longer than the log", lsn));
+            }
+        }
+
+        // the next record starts aligned, in the next page if its header would not fit
+        pos = (pos + 7) & !7;
+        if pos + header_size > page_size {
+            page = self.next_page(page);
+            pos = data_offset;
+        }
+        let next = self.offset_lsn(page + pos, lsn);
+
+        let data = record.split_off(header_size as usize);
+        let update = if header.RecordType == LOG_RECORD_CLIENT { Some(LogUpdate::parse(&data)?) } else { None };
+        debug!("{:#?}", header);
+        Ok((LogRecord {
+            lsn: lsn,
+            previous_lsn: header.ClientPreviousLsn,
+            undo_next_lsn: header.ClientUndoNextLsn,
+            client_index: header.ClientIndex,
+            record_type: header.RecordType,
+            transaction_id: header.TransactionId,
+            flags: header.Flags & !LOG_RECORD_MULTI_PAGE,
+            data: data,
+            update: update,
+        }, next))
+    }
+
+    /// Iterate over the records from a sequence number to the end of the log.
+    ///
+    /// The end is found where the next record is missing or from an earlier pass over the log,
+    /// so records written after the last checkpoint are included.
+    pub fn records_from(&mut self, lsn: u64) -> LogRecords<'_, R> {
+        LogRecords {
+            log: self,
+            next: Some(lsn),
+            first: true,
+        }
+    }
+
+    /// Iterate over the records from the oldest still needed by a client.
+    pub fn records(&mut self) -> LogRecords<'_, R> {
+        let oldest = self.restart.clients.iter().map(|c| c.oldest_lsn).filter(|&lsn| lsn != 0).min();
+        let lsn = oldest.unwrap_or(self.restart.current_lsn);
+        self.records_from(lsn)
+    }
+}
+
+
+/// An iterator over the records of `$LogFile`.
+pub struct LogRecords<'a, R> {
+    log: &'a mut LogFile<R>,
+    next: Option<u64>,
+    first: bool,                // the first record must exist
+}
+
+impl<'a, R> Iterator for LogRecords<'a, R>
+where R: Read + Seek {
+    type Item = io::Result<LogRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lsn = self.next.take()?;
+        if !self.first && !self.log.has_record(lsn) {
+            debug!("Log ends before LSN {:#x}", lsn);
+            return None;
+        }
+        self.first = false;
+        match self.log.read_record(lsn) {
+            Ok((record, next)) => {
+                self.next = Some(next);
+                Some(Ok(record))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restart_page(sequence_number_bits: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 4096];
+        data[0..4].copy_from_slice(b"RSTR");
+        data[20..24].copy_from_slice(&4096u32.to_le_bytes());
+        data[24..26].copy_from_slice(&0x30u16.to_le_bytes());
+        data[0x30 + 12..0x30 + 14].copy_from_slice(&LOGFILE_NO_CLIENT.to_le_bytes());
+        data[0x30 + 16..0x30 + 20].copy_from_slice(&sequence_number_bits.to_le_bytes());
+        data[0x30 + 38..0x30 + 40].copy_from_slice(&0x40u16.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn sequence_number_bits() {
+        for &bits in [0, 1, 2, 62].iter() {
+            assert!(RestartArea::parse(&restart_page(bits)).is_err());
+        }
+        for &bits in [3, 44, 61].iter() {
+            assert_eq!(RestartArea::parse(&restart_page(bits)).unwrap().sequence_number_bits, bits);
+        }
+    }
+
+    #[test]
+    fn log_update() {
+        // an update of a resident value, with one LCN and the redo and undo data after it
+        let mut data = vec![0u8; 0x20];
+        data[0..2].copy_from_slice(&0x07u16.to_le_bytes());
+        data[2..4].copy_from_slice(&0x07u16.to_le_bytes());
+        data[4..6].copy_from_slice(&0x28u16.to_le_bytes());
+        data[6..8].copy_from_slice(&5u16.to_le_bytes());
+        data[8..10].copy_from_slice(&0x30u16.to_le_bytes());
+        data[10..12].copy_from_slice(&3u16.to_le_bytes());
+        data[12..14].copy_from_slice(&4u16.to_le_bytes());
+        data[14..16].copy_from_slice(&1u16.to_le_bytes());
+        data[16..18].copy_from_slice(&0x38u16.to_le_bytes());
+        data[18..20].copy_from_slice(&0x18u16.to_le_bytes());
+        data[20..22].copy_from_slice(&2u16.to_le_bytes());
+        data[24..32].copy_from_slice(&5u64.to_le_bytes());
+        data.extend_from_slice(&0x1234u64.to_le_bytes());
+        data.extend_from_slice(b"HELLO\0\0\0hel");
+
+        let update = LogUpdate::parse(&data).unwrap();
+        assert_eq!(update.redo_operation, LogOperation::UpdateResidentValue);
+        assert_eq!(update.undo_operation, LogOperation::UpdateResidentValue);
+        assert_eq!(update.redo_data, b"HELLO");
+        assert_eq!(update.undo_data, b"hel");
+        assert_eq!(update.target_attribute, 4);
+        assert_eq!((update.record_offset, update.attribute_offset), (0x38, 0x18));
+        assert_eq!(update.cluster_block_offset, 2);
+        assert_eq!(update.target_vcn, 5);
+        assert_eq!(update.lcns, vec![0x1234]);
+        assert_eq!(LogOperation::from(0x99), LogOperation::Unknown(0x99));
+
+        // empty data may point anywhere, but data must not run past the end
+        let mut empty = data.clone();
+        empty[8..12].copy_from_slice(&[0xFF, 0xFF, 0, 0]);
+        assert!(LogUpdate::parse(&empty).unwrap().undo_data.is_empty());
+        let mut overrun = data.clone();
+        overrun[10..12].copy_from_slice(&4u16.to_le_bytes());
+        assert!(LogUpdate::parse(&overrun).is_err());
+        let mut lcns = data.clone();
+        lcns[14..16].copy_from_slice(&3u16.to_le_bytes());
+        assert!(LogUpdate::parse(&lcns).is_err());
+        assert!(LogUpdate::parse(&data[..0x1F]).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,1390 @@
+use core::cmp::Ordering;
+use core::mem;
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::rc::Rc;
+
+use super::fat::BIOS_PARAMETER_BLOCK;
+use super::super::device::{Device, Overlay, Volume};
+use index::{ascii_upcase, collate_names};
+use record::{apply_fixups, is_unused, parse_attribute_list, FILE_SIGNATURE};
+use security::{SECURITY_DESCRIPTOR_HEADER, SDH_INDEX, SDS_STREAM, SII_INDEX};
+use usn::{USN_JOURNAL_MAX_STREAM, USN_JOURNAL_STREAM};
+use wof::WOF_STREAM;
+
+mod bitmap;
+mod clusters;
+mod index;
+mod logfile;
+mod reader;
+mod record;
+mod recover;
+mod replay;
+mod reparse;
+mod runs;
+mod security;
+mod usn;
+mod volume;
+mod wof;
+
+pub use bitmap::{AllocationRuns, ClusterBitmap, UnallocatedReader};
+pub use clusters::{ClusterMap, ClusterOwner};
+pub use index::{IndexEntries, IndexEntry};
+pub use logfile::{LogClient, LogFile, LogOperation, LogRecord, LogRecords, LogUpdate, RestartArea};
+pub use reader::{AttributeReader, StreamReader};
+pub use recover::{DeletedFile, DeletedStream, RecoveredRun};
+pub use record::{Attribute, AttributeValue, FileName, FileRecord, FileReference, RecordError, StandardInformation, ATTRIBUTE_TYPE_CODE, FILE_NAME_TYPE};
+pub use replay::Replay;
+pub use reparse::{ReparseData, ReparsePoint};
+pub use runs::Run;
+pub use security::{security_hash, Ace, Acl, SecurityDescriptor, Sid};
+pub use usn::{UsnExtent, UsnJournal, UsnRecord, UsnRecords};
+pub use volume::VolumeInfo;
+pub use wof::{WofReader, FILE_PROVIDER_COMPRESSION};
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct NTFS_EXTENDED_BIOS_PARAMETER_BLOCK {
+    // BIOS_PARAMETER_BLOCK
+    SectorsPerFAT32: u32,       // 0 for NTFS
+    TotalSectors64: u64,
+    MFTLocation: u64,           // Logical Cluster Number
+    BackupMFTLocation: u64,     // Logical Cluster Number
+    ClustersPerMFTRecord: i8,   // Positive: Number of Clusters, Negative: 2^|x| Bytes
+    Unused_0: [u8; 3],
+    ClustersPerIndexBuffer: i8, // Positive: Number of Clusters, Negative: 2^|x| Bytes
+    Unused_1: [u8; 3],
+    VolumeSerialNumber: u64,
+    Unused_2: [u8; 4],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct NTFS_BOOT_SECTOR {
+    Jump: [u8; 3],
+    OemID: [u8; 8],             // 'NTFS    '
+    BiosParameterBlock: BIOS_PARAMETER_BLOCK,
+    ExtendedBiosParameterBlock: NTFS_EXTENDED_BIOS_PARAMETER_BLOCK,
+    BootstrapCode: [u8; 426],
+    EndOfSector: [u8; 2],       // 55 aa
+}
+
+/// Well known file record numbers.
+pub const MFT_RECORD: u64 = 0;
+pub const LOGFILE_RECORD: u64 = 2;
+pub const VOLUME_RECORD: u64 = 3;
+pub const ROOT_RECORD: u64 = 5;
+pub const BITMAP_RECORD: u64 = 6;
+pub const SECURE_RECORD: u64 = 9;
+pub const UPCASE_RECORD: u64 = 10;
+
+/// Maximum number of links followed resolving a path.
+const MAXIMUM_LINKS: usize = 63;
+
+/// Name of the index of file names in a directory.
+pub const FILE_NAME_INDEX: &str = "$I30";
+
+/// Path of the change journal.
+pub const USN_JOURNAL_PATH: &str = "/$Extend/$UsnJrnl";
+
+
+pub struct Ntfs<R> {
+    inner: Device<Overlay<R>>,
+    mft: FileRecord,
+    mft_data: Attribute,
+    mft_offset: u64,
+    record_size: u64,
+    sector_size: u64,
+    total_sectors: u64,
+    serial_number: u64,
+    cluster_size: u64,
+    cluster_count: u64,
+    index_buffer_size: u64,
+    upcase: Rc<[u16]>,
+}
+
+impl<R> Ntfs<R> {
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R { self.inner.get_ref().get_ref() }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R { self.inner.get_mut().get_mut() }
+
+    /// Unwraps this `BlockDevice`, returning the underlying reader.
+    pub fn into_inner(self) -> R { self.inner.into_inner().into_inner() }
+
+    /// Get the file record of the `$MFT` itself.
+    pub fn mft(&self) -> &FileRecord { &self.mft }
+
+    /// Get the size of a cluster in bytes.
+    pub fn cluster_size(&self) -> u64 { self.cluster_size }
+
+    /// Get the number of clusters on the volume.
+    pub fn cluster_count(&self) -> u64 { self.cluster_count }
+
+    /// Open the value of an attribute for reading.
+    pub fn open_attribute(&mut self, attribute: &Attribute) -> AttributeReader<'_, Device<Overlay<R>>> {
+        AttributeReader::new(&mut self.inner, attribute, self.cluster_size)
+    }
+
+    /// Get the number of file record segments in the MFT.
+    pub fn record_count(&self) -> u64 {
+        self.mft_data.size() / self.record_size
+    }
+}
+
+impl<R> Ntfs<R>
+where R: Read + Seek {
+
+    /// Read a file record segment by number.
+    pub fn read_record(&mut self, number: u64) -> io::Result<FileRecord> {
+        let mut reader = AttributeReader::new(&mut self.inner, &self.mft_data, self.cluster_size);
+        FileRecord::new(&mut reader, number, number * self.record_size, self.record_size, self.cluster_size, self.cluster_count)
+    }
+
+    /// Read a file, merging the attributes of its extension records.
+    pub fn read_file(&mut self, number: u64) -> io::Result<FileRecord> {
+        let mut record = self.read_record(number)?;
+        self.resolve_attribute_list(&mut record, false)?;
+        Ok(record)
+    }
+
+    /// Replace the attributes of a base record with those named by its `$ATTRIBUTE_LIST`.
+    ///
+    /// The extension records of a `deleted` base may be deleted too, and refer to it by the
+    /// sequence number it had before.
+    fn resolve_attribute_list(&mut self, record: &mut FileRecord, deleted: bool) -> io::Result<()> {
+        let list = match record.attribute(ATTRIBUTE_TYPE_CODE::ATTRIBUTE_LIST, "") {
+            Some(list) => list.clone(),
+            None => return Ok(()),
+        };
+        let mut data = Vec::new();
+        self.open_attribute(&list).read_to_end(&mut data)?;
+        let entries = parse_attribute_list(&data)?;
+
+        let base = record.reference();
+        let mut segments: Vec<FileRecord> = Vec::new();
+        let mut attributes: Vec<Attribute> = Vec::new();
+        for entry in entries {
+            if entry.segment.record != base.record && !segments.iter().any(|s| s.number() == entry.segment.record) {
+                let segment = self.read_record(entry.segment.record)?;
+                let valid = if deleted {
+                    refers_to(entry.segment, &segment, true) &&
+                        segment.base_record().is_some_and(|b| b.record == base.record && refers_to(b, record, true))
+                } else {
+                    segment.sequence_number() == entry.segment.sequence && segment.base_record() == Some(base)
+                };
+                if !valid {
+                    eprintln!("ERROR: MFT record {} is not an extension of {}", entry.segment.record, base.record);
+                    return Err(io::Error::from(io::ErrorKind::InvalidData));
+                }
+                segments.push(segment);
+            }
+            let segment = if entry.segment.record == base.record {
+                &*record
+            } else {
+                segments.iter().find(|s| s.number() == entry.segment.record).unwrap()
+            };
+
+            let attribute = match segment.attributes().iter().find(|a| {
+                a.type_code() == entry.type_code && a.instance() == entry.instance && a.name() == entry.name
+            }) {
+                Some(attribute) => attribute,
+                None => {
+                    eprintln!("ERROR: Attribute {:#x} {} not found in MFT record {}", entry.type_code, entry.instance, entry.segment.record);
+                    return Err(io::Error::from(io::ErrorKind::InvalidData));
+                }
+            };
+
+            // later extents of a non-resident attribute continue the previous one
+            match attributes.last_mut() {
+                Some(last) if entry.lowest_vcn != 0 && last.type_code() == entry.type_code && last.name() == entry.name => {
+                    last.extend(attribute, self.cluster_size)?;
+                }
+                _ => attributes.push(attribute.clone()),
+            }
+        }
+
+        record.set_attributes(attributes);
+        Ok(())
+    }
+
+    /// Load the `$DATA` of the `$MFT`, which may itself be split across extension records.
+    fn load_mft(&mut self) -> io::Result<()> {
+        self.mft_data = mft_data(&self.mft)?;
+        let mut mft = self.mft.clone();
+        self.resolve_attribute_list(&mut mft, false)?;
+        self.mft_data = mft_data(&mft)?;
+        self.mft = mft;
+        Ok(())
+    }
+
+    /// Iterate over every file record segment in the MFT.
+    ///
+    /// Records which were never written are skipped, records which fail their fixups are
+    /// returned as errors.
+    pub fn records(&mut self) -> Records<'_, R> {
+        let count = self.record_count();
+        Records {
+            reader: AttributeReader::new(&mut self.inner, &self.mft_data, self.cluster_size),
+            record_size: self.record_size,
+            cluster_size: self.cluster_size,
+            cluster_count: self.cluster_count,
+            number: 0,
+            count: count,
+        }
+    }
+
+    /// Iterate over the entries of a named index of a file, in collation order.
+    pub fn index_entries(&mut self, record: &FileRecord, name: &str) -> io::Result<IndexEntries<'_, Device<Overlay<R>>>> {
+        let root = match record.attribute(ATTRIBUTE_TYPE_CODE::INDEX_ROOT, name) {
+            Some(root) => root.clone(),
+            None => {
+                eprintln!("ERROR: MFT record {} has no index {}", record.number(), name);
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+        };
+        let mut root_data = Vec::new();
+        self.open_attribute(&root).read_to_end(&mut root_data)?;
+        let mut bitmap = Vec::new();
+        if let Some(attribute) = record.attribute(ATTRIBUTE_TYPE_CODE::BITMAP, name) {
+            self.open_attribute(attribute).read_to_end(&mut bitmap)?;
+        }
+
+        let (buffer_size, cluster_size) = (self.index_buffer_size, self.cluster_size);
+        let inner = &mut self.inner;
+        let allocation = record.attribute(ATTRIBUTE_TYPE_CODE::INDEX_ALLOCATION, name)
+            .map(move |a| AttributeReader::new(inner, a, cluster_size));
+        IndexEntries::new(&root_data, allocation, bitmap, buffer_size, cluster_size)
+    }
+
+    /// Iterate over the names in a directory, in collation order.
+    ///
+    /// Files with both a long and a short name are listed under each of them.
+    pub fn read_dir(&mut self, directory: &FileRecord) -> io::Result<IndexEntries<'_, Device<Overlay<R>>>> {
+        self.index_entries(directory, FILE_NAME_INDEX)
+    }
+
+    /// Look up a name in a directory.
+    ///
+    /// An exact match is preferred.  Otherwise names are compared ignoring case, except for
+    /// names in the POSIX namespace which are case sensitive.
+    pub fn find_file_name(&mut self, directory: &FileRecord, name: &str) -> io::Result<Option<IndexEntry>> {
+        let target: Vec<u16> = name.encode_utf16().collect();
+        let upcase = Rc::clone(&self.upcase);
+
+        // names equal ignoring case are adjacent, ordered by their code units
+        let mut entries = self.read_dir(directory)?;
+        entries.seek(|entry| match entry.file_name() {
+            Ok(file_name) => collate_names(&upcase, &file_name.units, &target),
+            Err(_) => Ordering::Less,
+        })?;
+
+        let mut found = None;
+        for entry in entries {
+            let entry = entry?;
+            let file_name = match entry.file_name() {
+                Ok(file_name) => file_name,
+                Err(_) => continue,
+            };
+            if collate_names(&upcase, &file_name.units, &target) != Ordering::Equal {
+                break;
+            }
+            if file_name.units == target {
+                return Ok(Some(entry));
+            }
+            if found.is_none() && file_name.namespace != FILE_NAME_TYPE::POSIX {
+                found = Some(entry);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Open a file by its path from the root directory.
+    ///
+    /// Components are separated by `/` or `\`, and may be long or short (8.3) names.  Reparse
+    /// points are not followed.
+    pub fn open(&mut self, path: &str) -> io::Result<FileRecord> {
+        let mut record = self.read_file(ROOT_RECORD)?;
+        for component in split_path(path) {
+            record = self.child(&record, &component)?;
+        }
+        Ok(record)
+    }
+
+    /// Open a file by its path from the root directory, following links.
+    ///
+    /// Symbolic links and junctions are followed when their target is on this volume, ie. is
+    /// relative or has a drive letter.  `..` goes to the parent of the last directory opened.
+    pub fn resolve(&mut self, path: &str) -> io::Result<FileRecord> {
+        let mut stack = vec![self.read_file(ROOT_RECORD)?];
+        let mut components: VecDeque<String> = split_path(path).collect();
+        let mut links = 0;
+        while let Some(component) = components.pop_front() {
+            if component == ".." {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+                continue;
+            }
+            let record = self.child(stack.last().unwrap(), &component)?;
+
+            let (target, relative) = match self.reparse_point(&record)?.map(|r| r.decode()).transpose()? {
+                Some(ReparseData::SymbolicLink { target, relative, .. }) => (target, relative),
+                Some(ReparseData::MountPoint { target, .. }) => (target, false),
+                Some(ReparseData::LxSymbolicLink { target }) => {
+                    let relative = !target.starts_with('/');
+                    (target, relative)
+                }
+                _ => {
+                    stack.push(record);
+                    continue;
+                }
+            };
+            links += 1;
+            if links > MAXIMUM_LINKS {
+                eprintln!("ERROR: Too many links resolving {}", path);
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+            debug!("{} links to {}", component, target);
+
+            let target = if relative {
+                target.as_str()
+            } else {
+                match volume_path(&target) {
+                    Some(target) => {
+                        stack.truncate(1);
+                        target
+                    }
+                    None => {
+                        eprintln!("ERROR: {} links to another volume: {}", component, target);
+                        return Err(io::Error::from(io::ErrorKind::NotFound));
+                    }
+                }
+            };
+            for (i, c) in split_path(target).enumerate() {
+                components.insert(i, c);
+            }
+        }
+        Ok(stack.pop().unwrap())
+    }
+
+    /// Open a file in a directory by name.
+    fn child(&mut self, directory: &FileRecord, name: &str) -> io::Result<FileRecord> {
+        if !directory.is_directory() {
+            eprintln!("ERROR: MFT record {} is not a directory", directory.number());
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        }
+        let reference = match self.find_file_name(directory, name)? {
+            Some(entry) => entry.file_reference(),
+            None => {
+                debug!("{} not found in MFT record {}", name, directory.number());
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+        };
+        let record = self.read_file(reference.record)?;
+        if !record.is_in_use() || (reference.sequence != 0 && record.sequence_number() != reference.sequence) {
+            eprintln!("ERROR: {} refers to stale MFT record {}", name, reference.record);
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        }
+        Ok(record)
+    }
+
+    /// Find a data stream by `path[:stream[:$DATA]]`, the unnamed stream if none is given.
+    ///
+    /// Stream names are compared ignoring case.
+    pub fn stream(&mut self, path: &str) -> io::Result<Attribute> {
+        self.find_stream(path).map(|(_, stream)| stream)
+    }
+
+    /// Open a data stream by `path[:stream[:$DATA]]` for reading.
+    pub fn open_stream(&mut self, path: &str) -> io::Result<StreamReader<'_, Device<Overlay<R>>>> {
+        let (record, stream) = self.find_stream(path)?;
+        self.open_data(&record, &stream)
+    }
+
+    /// Open a data stream of a file for reading.
+    ///
+    /// The unnamed stream of a file compressed by the Windows Overlay Filter is read from its
+    /// `WofCompressedData` stream instead.
+    pub fn open_data(&mut self, record: &FileRecord, stream: &Attribute) -> io::Result<StreamReader<'_, Device<Overlay<R>>>> {
+        let compressed = match record.attribute(ATTRIBUTE_TYPE_CODE::DATA, WOF_STREAM) {
+            Some(compressed) if stream.name().is_empty() => compressed.clone(),
+            _ => return Ok(StreamReader::Attribute(self.open_attribute(stream))),
+        };
+        let algorithm = match self.reparse_point(record)?.map(|r| r.decode()).transpose()? {
+            Some(ReparseData::Wof(algorithm)) => algorithm,
+            _ => return Ok(StreamReader::Attribute(self.open_attribute(stream))),
+        };
+
+        // the unnamed stream is left sparse with the uncompressed size
+        let size = stream.size();
+        let reader = self.open_attribute(&compressed);
+        Ok(StreamReader::Wof(WofReader::new(reader, algorithm, size)?))
+    }
+
+    /// Read the reparse point of a file, if it has one.
+    pub fn reparse_point(&mut self, record: &FileRecord) -> io::Result<Option<ReparsePoint>> {
+        let attribute = match record.attribute(ATTRIBUTE_TYPE_CODE::REPARSE_POINT, "") {
+            Some(attribute) => attribute.clone(),
+            None => return Ok(None),
+        };
+        let mut data = Vec::new();
+        self.open_attribute(&attribute).read_to_end(&mut data)?;
+        ReparsePoint::parse(&data).map(Some)
+    }
+
+    /// Find a file and one of its data streams by `path[:stream[:$DATA]]`.
+    fn find_stream(&mut self, path: &str) -> io::Result<(FileRecord, Attribute)> {
+        let start = path.rfind(['/', '\\']).map_or(0, |i| i + 1);
+        let (path, name) = match path[start..].find(':') {
+            Some(i) => (&path[..start + i], &path[start + i + 1..]),
+            None => (path, ""),
+        };
+        let name = match name.rfind(':') {
+            Some(i) if &name[i + 1..] == "$DATA" => &name[..i],
+            Some(_) => {
+                eprintln!("ERROR: Only $DATA streams can be opened: {}", name);
+                return Err(io::Error::from(io::ErrorKind::InvalidInput));
+            }
+            None => name,
+        };
+
+        let record = self.open(path)?;
+        let target: Vec<u16> = name.encode_utf16().collect();
+        let stream = record.streams().into_iter().find(|a| {
+            let units: Vec<u16> = a.name().encode_utf16().collect();
+            collate_names(&self.upcase, &units, &target) == Ordering::Equal
+        }).cloned();
+        match stream {
+            Some(stream) => Ok((record, stream)),
+            None => {
+                debug!("Stream {:?} not found in MFT record {}", name, record.number());
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            }
+        }
+    }
+
+    /// Get the path of a file from the root directory by following the parents of its names.
+    ///
+    /// The long name is used where a file has a short one too.  Returns `None` if the file or
+    /// one of its parents has been deleted or its record reused.
+    pub fn path(&mut self, reference: FileReference) -> io::Result<Option<String>> {
+        self.ancestry(reference, false)
+    }
+
+    /// Get every path of a file, one for each of its hard links.
+    ///
+    /// A short name is only used for a link without a long one.  Links from directories which
+    /// have been deleted are left out.
+    pub fn paths(&mut self, record: &FileRecord) -> io::Result<Vec<String>> {
+        if record.number() == ROOT_RECORD {
+            return Ok(vec![String::from("/")]);
+        }
+        let names = record.file_names()?;
+        let mut paths = Vec::new();
+        for name in &names {
+            if name.namespace == FILE_NAME_TYPE::DOS &&
+                names.iter().any(|n| n.namespace != FILE_NAME_TYPE::DOS && n.parent == name.parent) {
+                continue;
+            }
+            if let Some(parent) = self.path(name.parent)? {
+                paths.push(join_path(&parent, &name.name));
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Walk the tree below a directory, listing each file once.
+    ///
+    /// The tree is walked a level at a time.  Short names are skipped, and a file with several
+    /// hard links is only listed under the one found first, `paths` gets the others.
+    pub fn walk(&mut self, path: &str) -> io::Result<Walk<'_, R>> {
+        let record = self.open(path)?;
+        let path = self.path(record.reference())?.unwrap_or_else(|| String::from(path));
+        let mut seen = HashSet::new();
+        seen.insert(record.number());
+        Ok(Walk {
+            ntfs: self,
+            directories: VecDeque::from(vec![(path, record.reference())]),
+            entries: VecDeque::new(),
+            seen: seen,
+        })
+    }
+
+    /// Follow the parents of a file to the root directory, through `deleted` directories too.
+    fn ancestry(&mut self, reference: FileReference, deleted: bool) -> io::Result<Option<String>> {
+        let mut components = Vec::new();
+        let mut reference = reference;
+        while reference.record != ROOT_RECORD {
+            if components.len() as u64 > self.record_count() {
+                eprintln!("ERROR: MFT record {} is its own ancestor", reference.record);
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+            let mut record = self.read_record(reference.record)?;
+            if !refers_to(reference, &record, deleted) {
+                debug!("MFT record {} is stale", reference.record);
+                return Ok(None);
+            }
+            let in_use = record.is_in_use();
+            self.resolve_attribute_list(&mut record, !in_use)?;
+            let name = match primary_name(&record)? {
+                Some(name) => name,
+                None => return Ok(None),
+            };
+            components.push(name.name);
+            reference = name.parent;
+        }
+        components.reverse();
+        Ok(Some(format!("/{}", components.join("/"))))
+    }
+
+    /// Find the files whose records are no longer in use.
+    ///
+    /// Each has the path of its directory where that remains, even if it was deleted too, or
+    /// is an orphan where the directory's record has been reused.  The runs of its streams are
+    /// checked against `$Bitmap` for clusters since allocated to other files.  The data can be
+    /// read with `open_data`.
+    pub fn deleted_files(&mut self) -> io::Result<Vec<DeletedFile>> {
+        let bitmap = self.cluster_bitmap()?;
+
+        // records which fail their fixups have already been reported
+        let records: Vec<FileRecord> = self.records().filter_map(|r| r.ok())
+            .filter(|r| !r.is_in_use() && r.base_record().is_none())
+            .collect();
+        debug!("{} deleted records", records.len());
+
+        let mut files = Vec::new();
+        for mut record in records {
+            if self.resolve_attribute_list(&mut record, true).is_err() {
+                eprintln!("WARNING: Attribute list of deleted MFT record {} is lost", record.number());
+            }
+            let name = primary_name(&record).unwrap_or(None);
+            let parent = match &name {
+                Some(name) => self.ancestry(name.parent, true).unwrap_or(None),
+                None => None,
+            };
+            let streams = record.streams().into_iter().map(|a| DeletedStream::new(a.clone(), &bitmap)).collect();
+            files.push(DeletedFile {
+                record: record,
+                name: name,
+                parent: parent,
+                streams: streams,
+            });
+        }
+        Ok(files)
+    }
+
+    /// Read the cluster allocation bitmap, `$Bitmap`.
+    pub fn cluster_bitmap(&mut self) -> io::Result<ClusterBitmap> {
+        let record = self.read_file(BITMAP_RECORD)?;
+        let attribute = match record.attribute(ATTRIBUTE_TYPE_CODE::DATA, "") {
+            Some(attribute) => attribute.clone(),
+            None => {
+                eprintln!("ERROR: $Bitmap has no $DATA");
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+        };
+        let mut bitmap = Vec::new();
+        self.open_attribute(&attribute).read_to_end(&mut bitmap)?;
+        if (bitmap.len() as u64) * 8 < self.cluster_count {
+            eprintln!("WARNING: $Bitmap only covers {} of {} clusters", bitmap.len() * 8, self.cluster_count);
+        }
+        Ok(ClusterBitmap::new(bitmap, self.cluster_count))
+    }
+
+    /// Map the clusters of the volume to the attributes of the files in use which hold them, eg.
+    /// to find which file a byte at some offset belongs to.
+    pub fn cluster_map(&mut self) -> ClusterMap {
+        let (cluster_size, record_size) = (self.cluster_size, self.record_size);
+
+        // records which fail their fixups have already been reported
+        let records = self.records().filter_map(|r| r.ok()).filter(|r| r.is_in_use());
+        ClusterMap::new(records, cluster_size, record_size)
+    }
+
+    /// Open the free clusters of the volume for reading, one after another.
+    ///
+    /// `volume_offset` maps an offset in them back to the volume, eg. to find where carved data
+    /// was.
+    pub fn unallocated_clusters(&mut self) -> io::Result<UnallocatedReader<'_, Device<Overlay<R>>>> {
+        let bitmap = self.cluster_bitmap()?;
+        Ok(UnallocatedReader::new(&mut self.inner, &bitmap, self.cluster_size))
+    }
+
+    /// Read the parameters of the change journal, `$Extend\$UsnJrnl:$Max`.
+    pub fn usn_journal(&mut self) -> io::Result<UsnJournal> {
+        let journal = self.open(USN_JOURNAL_PATH)?;
+        let attribute = match journal.attribute(ATTRIBUTE_TYPE_CODE::DATA, USN_JOURNAL_MAX_STREAM) {
+            Some(attribute) => attribute.clone(),
+            None => {
+                eprintln!("ERROR: $UsnJrnl has no {} stream", USN_JOURNAL_MAX_STREAM);
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+        };
+        let mut data = Vec::new();
+        self.open_attribute(&attribute).read_to_end(&mut data)?;
+        UsnJournal::parse(&data)
+    }
+
+    /// Iterate over the records of the change journal, `$Extend\$UsnJrnl:$J`, oldest first.
+    pub fn usn_records(&mut self) -> io::Result<UsnRecords<'_, Device<Overlay<R>>>> {
+        let journal = self.open(USN_JOURNAL_PATH)?;
+        let attribute = match journal.attribute(ATTRIBUTE_TYPE_CODE::DATA, USN_JOURNAL_STREAM) {
+            Some(attribute) => attribute.clone(),
+            None => {
+                eprintln!("ERROR: $UsnJrnl has no {} stream", USN_JOURNAL_STREAM);
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+        };
+        let cluster_size = self.cluster_size;
+        Ok(UsnRecords::new(self.open_attribute(&attribute), &attribute, cluster_size))
+    }
+
+    /// Get the current path of the file a change journal record refers to.
+    ///
+    /// The parent is used with the name in the record, so a file which has since been deleted or
+    /// renamed still gets the path it had, as long as its directory remains.
+    pub fn usn_path(&mut self, record: &UsnRecord) -> io::Result<Option<String>> {
+        let name = match &record.name {
+            Some(name) => name,
+            None => return self.path(record.file_reference),
+        };
+        Ok(self.path(record.parent_reference)?.map(|parent| join_path(&parent, name)))
+    }
+
+    /// Open `$LogFile` to read its restart area and records.
+    pub fn log_file(&mut self) -> io::Result<LogFile<AttributeReader<'_, Device<Overlay<R>>>>> {
+        let record = self.read_file(LOGFILE_RECORD)?;
+        let attribute = match record.attribute(ATTRIBUTE_TYPE_CODE::DATA, "") {
+            Some(attribute) => attribute.clone(),
+            None => {
+                eprintln!("ERROR: $LogFile has no $DATA");
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+        };
+        let size = attribute.size();
+        LogFile::new(self.open_attribute(&attribute), size)
+    }
+
+    /// Replay `$LogFile` into memory, so the volume is read as it was after the last transaction
+    /// which completed.
+    ///
+    /// Committed updates which never reached the disk are redone and updates of incomplete
+    /// transactions are undone.  The device itself is never written.  Nothing is done when the
+    /// log shows the volume was unmounted cleanly.
+    pub fn replay_log(&mut self) -> io::Result<Replay> {
+        let records = {
+            let mut log = self.log_file()?;
+            if !log.needs_replay() {
+                debug!("$LogFile is clean");
+                return Ok(Replay::default());
+            }
+            log.records().collect::<io::Result<Vec<LogRecord>>>()?
+        };
+        let result = replay::replay(self.inner.get_mut(), &records, self.cluster_size, self.record_size, self.index_buffer_size)?;
+        debug!("{:#?}", result);
+        self.inner.discard_buffer();
+        self.refresh()?;
+        Ok(result)
+    }
+
+    /// Discard the changes made by `replay_log`, reading the volume as it is on disk.
+    pub fn discard_replay(&mut self) -> io::Result<()> {
+        self.inner.get_mut().clear();
+        self.inner.discard_buffer();
+        self.refresh()
+    }
+
+    /// Get the label, NTFS version and flags from `$Volume`, with facts from the boot sector.
+    pub fn volume_info(&mut self) -> io::Result<VolumeInfo> {
+        let record = self.read_file(VOLUME_RECORD)?;
+        let name = match record.attribute(ATTRIBUTE_TYPE_CODE::VOLUME_NAME, "").map(|a| a.value()) {
+            Some(AttributeValue::Resident(data)) => Some(data.as_slice()),
+            _ => None,
+        };
+        let information = match record.attribute(ATTRIBUTE_TYPE_CODE::VOLUME_INFORMATION, "").map(|a| a.value()) {
+            Some(AttributeValue::Resident(data)) => data,
+            _ => {
+                eprintln!("ERROR: $Volume has no resident $VOLUME_INFORMATION");
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+        };
+
+        let mut info = VolumeInfo::parse(name, information)?;
+        info.serial_number = self.serial_number;
+        info.total_sectors = self.total_sectors;
+        info.sector_size = self.sector_size;
+        info.cluster_size = self.cluster_size;
+        Ok(info)
+    }
+
+    /// Get the security descriptor of a file.
+    ///
+    /// Files from NTFS 3.0 refer to a shared descriptor in `$Secure` by id, older ones have
+    /// their own `$SECURITY_DESCRIPTOR`.
+    pub fn file_security(&mut self, record: &FileRecord) -> io::Result<Option<SecurityDescriptor>> {
+        if let Some(attribute) = record.attribute(ATTRIBUTE_TYPE_CODE::SECURITY_DESCRIPTOR, "") {
+            let attribute = attribute.clone();
+            let mut data = Vec::new();
+            self.open_attribute(&attribute).read_to_end(&mut data)?;
+            return SecurityDescriptor::parse(&data).map(Some);
+        }
+        match record.standard_information()?.security_id {
+            Some(id) if id != 0 => self.security_descriptor(id).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Get a security descriptor from `$Secure` by id, using the `$SII` index.
+    pub fn security_descriptor(&mut self, security_id: u32) -> io::Result<SecurityDescriptor> {
+        let secure = self.read_file(SECURE_RECORD)?;
+        let entry = self.index_entries(&secure, SII_INDEX)?.find(|entry| {
+            let key = entry.key();
+            if key.len() < 4 {
+                return Ordering::Less;
+            }
+            u32::from_le_bytes([key[0], key[1], key[2], key[3]]).cmp(&security_id)
+        })?;
+        let header = match entry {
+            Some(entry) if entry.data().len() >= mem::size_of::<SECURITY_DESCRIPTOR_HEADER>() => {
+                unsafe{ *(entry.data().as_ptr() as *const SECURITY_DESCRIPTOR_HEADER) }
+            }
+            _ => {
+                eprintln!("ERROR: Security Id {} not found", security_id);
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+        };
+        let data = self.read_sds(&secure, &header)?;
+        SecurityDescriptor::parse(&data)
+    }
+
+    /// Find the id of a self-relative security descriptor in `$Secure`, using the `$SDH` index.
+    pub fn security_id(&mut self, descriptor: &[u8]) -> io::Result<Option<u32>> {
+        let hash = security_hash(descriptor);
+        let secure = self.read_file(SECURE_RECORD)?;
+
+        // collisions are ordered by id, so check every entry with the hash
+        let mut candidates = Vec::new();
+        for entry in self.index_entries(&secure, SDH_INDEX)? {
+            let entry = entry?;
+            if entry.data().len() < mem::size_of::<SECURITY_DESCRIPTOR_HEADER>() {
+                continue;
+            }
+            let header: SECURITY_DESCRIPTOR_HEADER = unsafe{ *(entry.data().as_ptr() as *const SECURITY_DESCRIPTOR_HEADER) };
+            if header.Hash > hash {
+                break;
+            } else if header.Hash == hash {
+                candidates.push(header);
+            }
+        }
+        for header in candidates {
+            if self.read_sds(&secure, &header)? == descriptor {
+                return Ok(Some(header.SecurityId));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the descriptor of an entry in the `$SDS` stream, checking its header.
+    fn read_sds(&mut self, secure: &FileRecord, header: &SECURITY_DESCRIPTOR_HEADER) -> io::Result<Vec<u8>> {
+        let stream = match secure.attribute(ATTRIBUTE_TYPE_CODE::DATA, SDS_STREAM) {
+            Some(stream) => stream.clone(),
+            None => {
+                eprintln!("ERROR: $Secure has no $SDS");
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+        };
+        let size = mem::size_of::<SECURITY_DESCRIPTOR_HEADER>();
+        if (header.Length as usize) < size {
+            eprintln!("ERROR: Invalid Security Descriptor Length: {}", { header.Length });
+            return Err(io::Error::from(io::ErrorKind::InvalidData));
+        }
+        let mut data = vec![0; header.Length as usize];
+        let mut reader = self.open_attribute(&stream);
+        reader.seek(SeekFrom::Start(header.Offset))?;
+        reader.read_exact(&mut data)?;
+
+        let entry: SECURITY_DESCRIPTOR_HEADER = unsafe{ *(data.as_ptr() as *const SECURITY_DESCRIPTOR_HEADER) };
+        debug!("{:#?}", entry);
+        let descriptor = data.split_off(size);
+        if entry.SecurityId != header.SecurityId || entry.Offset != header.Offset || entry.Hash != security_hash(&descriptor) {
+            eprintln!("ERROR: $SDS entry at {} does not match Security Id {}", { header.Offset }, { header.SecurityId });
+            return Err(io::Error::from(io::ErrorKind::InvalidData));
+        }
+        Ok(descriptor)
+    }
+
+    /// Load the `$UpCase` table used to collate file names.
+    fn load_upcase(&mut self) -> io::Result<()> {
+        let record = self.read_file(UPCASE_RECORD)?;
+        let data = match record.attribute(ATTRIBUTE_TYPE_CODE::DATA, "") {
+            Some(data) => data.clone(),
+            None => {
+                eprintln!("ERROR: $UpCase has no $DATA");
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+        };
+        let mut table = Vec::new();
+        self.open_attribute(&data).read_to_end(&mut table)?;
+        if table.len() != 0x10000 * 2 {
+            eprintln!("ERROR: Invalid $UpCase Length: {}", table.len());
+            return Err(io::Error::from(io::ErrorKind::InvalidData));
+        }
+        self.upcase = table.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        Ok(())
+    }
+}
+
+impl<R> Volume<R> for Ntfs<R>
+where R: Read + Seek {
+
+    fn is_supported(header: &[u8; 512]) -> bool {
+        &header[3..7] == b"NTFS"
+    }
+
+    fn with_header(inner: R, header: &[u8; 512]) -> io::Result<Self> {
+        let boot_sector: &NTFS_BOOT_SECTOR = unsafe{ & *(header.as_ptr() as *const NTFS_BOOT_SECTOR) };
+
+        debug!("{:#?}", boot_sector.BiosParameterBlock);
+        debug!("{:#?}", boot_sector.ExtendedBiosParameterBlock);
+
+        let sector_size = boot_sector.BiosParameterBlock.BytesPerSector as u64;
+        let sectors_per_cluster = boot_sector.BiosParameterBlock.SectorsPerCluster as u64;
+        if sector_size < 512 || !sector_size.is_power_of_two() || sectors_per_cluster == 0 {
+            eprintln!("ERROR: Invalid Geometry: {} Bytes Per Sector, {} Sectors Per Cluster", sector_size, sectors_per_cluster);
+            return Err(io::Error::from(io::ErrorKind::InvalidData));
+        }
+        let cluster_size = sector_size * sectors_per_cluster;
+        let total_sectors = boot_sector.ExtendedBiosParameterBlock.TotalSectors64;
+        let cluster_count = match total_sectors.checked_mul(sector_size) {
+            Some(size) => size / cluster_size,
+            None => {
+                eprintln!("ERROR: Invalid Total Sectors: {}", total_sectors);
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+        };
+        let (mft_offset, backup_offset, mft_record_size, index_buffer_size) = match (
+            boot_sector.ExtendedBiosParameterBlock.MFTLocation.checked_mul(cluster_size),
+            boot_sector.ExtendedBiosParameterBlock.BackupMFTLocation.checked_mul(cluster_size),
+            structure_size(boot_sector.ExtendedBiosParameterBlock.ClustersPerMFTRecord, cluster_size),
+            structure_size(boot_sector.ExtendedBiosParameterBlock.ClustersPerIndexBuffer, cluster_size),
+        ) {
+            (Some(mft), Some(backup), Some(record), Some(index)) => (mft, backup, record, index),
+            _ => {
+                eprintln!("ERROR: Invalid MFT Location or Record Sizes");
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+        };
+
+        debug!(
+            "Cluster Size: {}\nPrimary MFT - Offset: {}\nBackup  MFT - Offset: {}\nMFT Record Size: {}\nIndex Buffer Size: {}",
+            cluster_size, mft_offset, backup_offset, mft_record_size, index_buffer_size
+        );
+
+        let overlay = Overlay::with_block_size(inner, cluster_size as usize);
+        let mut wrapper = Device::with_block_size(overlay, cluster_size as usize);
+
+        let (mft_record, mft_offset) = {
+            FileRecord::new(&mut wrapper, MFT_RECORD, mft_offset, mft_record_size, cluster_size, cluster_count).map(|r| (r, mft_offset)).or_else(|_| {
+                eprintln!("WARNING: Primart MFT is bad. Parsing backup...");
+                FileRecord::new(&mut wrapper, MFT_RECORD, backup_offset, mft_record_size, cluster_size, cluster_count).map(|r| (r, backup_offset))
+            })
+        }?;
+        let mft_data = mft_data(&mft_record)?;
+
+        let mut ntfs = Self {
+            inner: wrapper,
+            mft: mft_record,
+            mft_data: mft_data,
+            mft_offset: mft_offset,
+            record_size: mft_record_size,
+            sector_size: sector_size,
+            total_sectors: total_sectors,
+            serial_number: boot_sector.ExtendedBiosParameterBlock.VolumeSerialNumber,
+            cluster_size: cluster_size,
+            cluster_count: cluster_count,
+            index_buffer_size: index_buffer_size,
+            upcase: ascii_upcase().into(),
+        };
+        ntfs.load_mft()?;
+        if ntfs.load_upcase().is_err() {
+            eprintln!("WARNING: $UpCase is bad. Names will be compared as ASCII...");
+        }
+        Ok(ntfs)
+    }
+
+    fn refresh(&mut self) -> io::Result<()> {
+        self.mft = FileRecord::new(&mut self.inner, MFT_RECORD, self.mft_offset, self.record_size, self.cluster_size, self.cluster_count)?;
+        self.load_mft()?;
+        if self.load_upcase().is_err() {
+            eprintln!("WARNING: $UpCase is bad. Names will be compared as ASCII...");
+            self.upcase = ascii_upcase().into();
+        }
+        Ok(())
+    }
+}
+
+
+/// Split a path into its components, ignoring empty ones and `.`.
+fn split_path(path: &str) -> impl Iterator<Item = String> + '_ {
+    path.split(['/', '\\']).filter(|c| !c.is_empty() && *c != ".").map(String::from)
+}
+
+/// Get the path within the volume of an absolute link target, eg. `\??\C:\Windows`.
+///
+/// Targets naming a volume by GUID or a network share can not be followed.
+fn volume_path(target: &str) -> Option<&str> {
+    let path = target.trim_start_matches("\\??\\").trim_start_matches("\\\\?\\");
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        Some(&path[2..])
+    } else if target.starts_with('/') {
+        Some(target)
+    } else {
+        None
+    }
+}
+
+/// Does a reference still refer to a record?
+///
+/// The sequence number of a record is incremented when it is freed, so a `deleted` record is
+/// also referred to by the one it had before.
+fn refers_to(reference: FileReference, record: &FileRecord, deleted: bool) -> bool {
+    if reference.sequence == 0 || record.sequence_number() == reference.sequence {
+        record.is_in_use() || deleted
+    } else {
+        deleted && !record.is_in_use() && record.sequence_number() == reference.sequence.wrapping_add(1)
+    }
+}
+
+/// Get the name of a file to use in its path, preferring the long name to the short one.
+fn primary_name(record: &FileRecord) -> io::Result<Option<FileName>> {
+    let mut names = record.file_names()?;
+    if names.is_empty() {
+        return Ok(None);
+    }
+    let index = names.iter().position(|n| n.namespace != FILE_NAME_TYPE::DOS).unwrap_or(0);
+    Ok(Some(names.swap_remove(index)))
+}
+
+/// Append a name to the path of a directory.
+fn join_path(parent: &str, name: &str) -> String {
+    if parent == "/" { format!("/{}", name) } else { format!("{}/{}", parent, name) }
+}
+
+/// Get the size of an MFT record or index buffer from its encoding in the boot sector.
+fn structure_size(clusters: i8, cluster_size: u64) -> Option<u64> {
+    if clusters > 0 {
+        return (clusters as u64).checked_mul(cluster_size);
+    }
+    match -(clusters as i32) {
+        exponent @ 1..=31 => Some(1 << exponent),
+        _ => None,
+    }
+}
+
+/// Get the unnamed `$DATA` attribute of the `$MFT`.
+fn mft_data(mft: &FileRecord) -> io::Result<Attribute> {
+    match mft.attribute(ATTRIBUTE_TYPE_CODE::DATA, "") {
+        Some(data) if !data.is_resident() => Ok(data.clone()),
+        _ => {
+            eprintln!("ERROR: $MFT has no $DATA");
+            Err(io::Error::from(io::ErrorKind::InvalidData))
+        }
+    }
+}
+
+
+/// An iterator over the file record segments of the MFT.
+pub struct Records<'a, R> {
+    reader: AttributeReader<'a, Device<Overlay<R>>>,
+    record_size: u64,
+    cluster_size: u64,
+    cluster_count: u64,
+    number: u64,
+    count: u64,
+}
+
+impl<'a, R> Iterator for Records<'a, R>
+where R: Read + Seek {
+    type Item = io::Result<FileRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut data = vec![0; self.record_size as usize];
+        while self.number < self.count {
+            let number = self.number;
+            self.number += 1;
+
+            let result = self.reader.seek(SeekFrom::Start(number * self.record_size))
+                .and_then(|_| self.reader.read_exact(&mut data));
+            if let Err(err) = result {
+                eprintln!("ERROR: Failed to read MFT record {}: {}", number, err);
+                return Some(Err(err));
+            }
+            if is_unused(&data) {
+                continue;
+            }
+            if let Err(err) = apply_fixups(&mut data, FILE_SIGNATURE) {
+                eprintln!("ERROR: MFT record {}: {}", number, err);
+                return Some(Err(err.into()));
+            }
+            return Some(FileRecord::parse(&data, number, self.cluster_size, self.cluster_count));
+        }
+        None
+    }
+}
+
+
+/// A file found walking a directory tree.
+#[derive(Clone, Debug)]
+pub struct WalkEntry {
+    /// The path from the root directory.
+    pub path: String,
+    /// The name in the directory index.
+    pub name: FileName,
+    pub reference: FileReference,
+}
+
+/// An iterator over the files below a directory.
+pub struct Walk<'a, R> {
+    ntfs: &'a mut Ntfs<R>,
+    directories: VecDeque<(String, FileReference)>,
+    entries: VecDeque<WalkEntry>,
+    seen: HashSet<u64>,     // record numbers already listed
+}
+
+impl<'a, R> Walk<'a, R>
+where R: Read + Seek {
+
+    /// Queue the entries of a directory which have not been seen.
+    fn list(&mut self, path: &str, reference: FileReference) -> io::Result<()> {
+        let directory = self.ntfs.read_file(reference.record)?;
+        if !refers_to(reference, &directory, false) || !directory.is_directory() {
+            debug!("{} is not a directory in MFT record {}", path, reference.record);
+            return Ok(());
+        }
+        for entry in self.ntfs.read_dir(&directory)? {
+            let entry = entry?;
+            let name = entry.file_name()?;
+            if name.namespace == FILE_NAME_TYPE::DOS || !self.seen.insert(entry.file_reference().record) {
+                continue;
+            }
+            self.entries.push_back(WalkEntry {
+                path: join_path(path, &name.name),
+                name: name,
+                reference: entry.file_reference(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R> Iterator for Walk<'a, R>
+where R: Read + Seek {
+    type Item = io::Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.pop_front() {
+                if entry.name.is_directory() {
+                    self.directories.push_back((entry.path.clone(), entry.reference));
+                }
+                return Some(Ok(entry));
+            }
+            let (path, reference) = self.directories.pop_front()?;
+            if let Err(err) = self.list(&path, reference) {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use index::tests::index_root;
+    use record::tests::{file_name, file_record, non_resident, resident};
+    use reparse::tests::link;
+    use reparse::{IO_REPARSE_TAG_MOUNT_POINT, IO_REPARSE_TAG_SYMLINK};
+    use record::write_fixups;
+    use ATTRIBUTE_TYPE_CODE::DATA;
+
+    fn boot_sector(bytes_per_sector: u16, sectors_per_cluster: u8, total_sectors: u64) -> [u8; 512] {
+        let mut header = [0u8; 512];
+        header[3..11].copy_from_slice(b"NTFS    ");
+        header[0x0b..0x0d].copy_from_slice(&bytes_per_sector.to_le_bytes());
+        header[0x0d] = sectors_per_cluster;
+        header[0x28..0x30].copy_from_slice(&total_sectors.to_le_bytes());
+        header
+    }
+
+    /// A volume of 64 clusters of 4 KiB and 1 KiB records, with an `$MFT` of 16 records in
+    /// clusters 4-5 and 8-9.
+    ///
+    /// Records are written with their fixups after `damage` has been applied.
+    fn volume(records: &[(u64, Vec<u8>)], clusters: &[(u64, &[u8])], damage: fn(u64, &mut Vec<u8>)) -> Ntfs<Cursor<Vec<u8>>> {
+        let mut header = boot_sector(512, 8, 64 * 8);
+        header[0x30..0x38].copy_from_slice(&4u64.to_le_bytes());
+        header[0x38..0x40].copy_from_slice(&4u64.to_le_bytes());
+        header[0x40] = -10i8 as u8;
+        header[0x44] = 1;
+        header[0x48..0x50].copy_from_slice(&0x1122_3344_5566_7788u64.to_le_bytes());
+        let mut image = vec![0u8; 64 * 4096];
+        image[..512].copy_from_slice(&header);
+
+        let mft = file_record(1, 1, 0, &[non_resident(DATA, "", 0, 3, &[0x11, 0x02, 0x04, 0x11, 0x02, 0x04], 16384)]);
+        for (number, record) in Some((MFT_RECORD, mft)).iter().chain(records) {
+            let mut data = record.clone();
+            write_fixups(&mut data).unwrap();
+            damage(*number, &mut data);
+            let lcn = [4, 5, 8, 9][*number as usize / 4];
+            let offset = lcn * 4096 + (*number as usize % 4) * 1024;
+            image[offset..offset + 1024].copy_from_slice(&data);
+        }
+        for (lcn, data) in clusters {
+            let offset = *lcn as usize * 4096;
+            image[offset..offset + data.len()].copy_from_slice(data);
+        }
+        Ntfs::with_header(Cursor::new(image), &header).unwrap()
+    }
+
+    /// The root directory, record 5, holding file names in collation order.
+    fn root(names: &[(u64, Vec<u8>)]) -> (u64, Vec<u8>) {
+        let index = index_root(ATTRIBUTE_TYPE_CODE::FILE_NAME as u32, names);
+        (ROOT_RECORD, file_record(5, 3, 0, &[
+            resident(ATTRIBUTE_TYPE_CODE::FILE_NAME, "", &file_name(5 << 48 | 5, ".", FILE_NAME_TYPE::DOS_WINDOWS, true)),
+            resident(ATTRIBUTE_TYPE_CODE::INDEX_ROOT, FILE_NAME_INDEX, &index),
+        ]))
+    }
+
+    /// An `$ATTRIBUTE_LIST` entry for an unnamed attribute.
+    fn list_entry(type_code: ATTRIBUTE_TYPE_CODE, lowest_vcn: u64, segment: u64, instance: u16) -> Vec<u8> {
+        let mut entry = vec![0u8; 32];
+        entry[..4].copy_from_slice(&(type_code as u32).to_le_bytes());
+        entry[4..6].copy_from_slice(&32u16.to_le_bytes());
+        entry[7] = 26;
+        entry[8..16].copy_from_slice(&lowest_vcn.to_le_bytes());
+        entry[16..24].copy_from_slice(&segment.to_le_bytes());
+        entry[24..26].copy_from_slice(&instance.to_le_bytes());
+        entry
+    }
+
+    /// Record 14 with a `$DATA` of 2 clusters, the second in its extension record 15.
+    fn split_data(base: u64) -> Vec<(u64, Vec<u8>)> {
+        let mut list = list_entry(DATA, 0, 1 << 48 | 14, 1);
+        list.extend_from_slice(&list_entry(DATA, 1, 1 << 48 | 15, 0));
+        let mut first = non_resident(DATA, "", 0, 0, &[0x11, 0x01, 0x10], 8192);
+        first[40..48].copy_from_slice(&8192u64.to_le_bytes());
+        vec![
+            (14, file_record(1, 1, 0, &[resident(ATTRIBUTE_TYPE_CODE::ATTRIBUTE_LIST, "", &list), first])),
+            (15, file_record(1, 1, base, &[non_resident(DATA, "", 1, 1, &[0x11, 0x01, 0x12], 0)])),
+        ]
+    }
+
+    #[test]
+    fn invalid_geometry() {
+        for header in [
+            boot_sector(0, 8, 1024),
+            boot_sector(500, 8, 1024),
+            boot_sector(512, 0, 1024),
+            boot_sector(512, 8, u64::MAX),
+        ].iter() {
+            assert!(Ntfs::<Cursor<Vec<u8>>>::is_supported(header));
+            let ntfs = Ntfs::with_header(Cursor::new(vec![0u8; 4096]), header);
+            assert_eq!(ntfs.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        }
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        let valid = {
+            let mut header = boot_sector(512, 8, 1024);
+            header[0x40] = -10i8 as u8;
+            header[0x44] = 1;
+            header
+        };
+        let mut headers = Vec::new();
+        for offset in [0x30, 0x38].iter() {
+            let mut header = valid;
+            header[*offset..*offset + 8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+            headers.push(header);
+        }
+        for (offset, clusters) in [(0x40, -128i8), (0x40, -32), (0x40, 0), (0x44, -64)].iter() {
+            let mut header = valid;
+            header[*offset] = *clusters as u8;
+            headers.push(header);
+        }
+        for header in headers.iter() {
+            let ntfs = Ntfs::with_header(Cursor::new(vec![0u8; 4096]), header);
+            assert_eq!(ntfs.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        }
+        assert_eq!(structure_size(-10, 4096), Some(1024));
+        assert_eq!(structure_size(1, 4096), Some(4096));
+    }
+
+    #[test]
+    fn records() {
+        // a record in each fragment of the $MFT, a deleted one, one marked BAAD and a torn write
+        let records: Vec<(u64, Vec<u8>)> = [(5, 3), (7, 0), (9, 1), (11, 1), (12, 1), (13, 1)].iter()
+            .map(|&(number, flags)| (number, file_record(number as u16, flags, 0, &[])))
+            .collect();
+        let mut ntfs = volume(&records, &[], |number, data| match number {
+            11 => data[..4].copy_from_slice(b"BAAD"),
+            12 => data[1022] ^= 1,
+            _ => {}
+        });
+        assert_eq!(ntfs.record_count(), 16);
+
+        let found: Vec<Result<(u64, u16, bool), io::ErrorKind>> = ntfs.records()
+            .map(|r| r.map(|r| (r.number(), r.sequence_number(), r.is_in_use())).map_err(|e| e.kind()))
+            .collect();
+        assert_eq!(found, vec![
+            Ok((0, 1, true)), Ok((5, 5, true)), Ok((7, 7, false)), Ok((9, 9, true)),
+            Err(io::ErrorKind::InvalidData), Err(io::ErrorKind::InvalidData), Ok((13, 13, true)),
+        ]);
+        assert_eq!(ntfs.read_record(13).unwrap().sequence_number(), 13);
+    }
+
+    #[test]
+    fn attribute_list() {
+        let clusters: [(u64, &[u8]); 2] = [(0x10, &[b'a'; 4096]), (0x12, &[b'b'; 4096])];
+        let mut ntfs = volume(&split_data(1 << 48 | 14), &clusters, |_, _| {});
+        assert_eq!(ntfs.read_record(14).unwrap().attributes().len(), 2);
+
+        let file = ntfs.read_file(14).unwrap();
+        assert_eq!(file.attributes().len(), 1);
+        let data = file.attribute(DATA, "").unwrap();
+        assert_eq!((data.size(), data.runs().len()), (8192, 2));
+        let mut value = Vec::new();
+        ntfs.open_attribute(data).read_to_end(&mut value).unwrap();
+        assert_eq!((value.len(), value[4095], value[4096]), (8192, b'a', b'b'));
+
+        // an extension must refer back to its base record
+        let mut ntfs = volume(&split_data(1 << 48 | 13), &clusters, |_, _| {});
+        assert_eq!(ntfs.read_file(14).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn streams() {
+        let name = file_name(5 << 48 | 5, "file.txt", FILE_NAME_TYPE::DOS_WINDOWS, false);
+        let records = [
+            root(&[(1 << 48 | 13, name.clone())]),
+            (13, file_record(1, 1, 0, &[
+                resident(ATTRIBUTE_TYPE_CODE::FILE_NAME, "", &name),
+                resident(DATA, "", b"hello"),
+                resident(DATA, "Zone.Identifier", b"[ZoneTransfer]"),
+            ])),
+        ];
+        let mut ntfs = volume(&records, &[], |_, _| {});
+        let file = ntfs.open("/FILE.TXT").unwrap();
+        let names: Vec<&str> = file.streams().iter().map(|s| s.name()).collect();
+        assert_eq!(names, vec!["", "Zone.Identifier"]);
+
+        let read = |ntfs: &mut Ntfs<Cursor<Vec<u8>>>, path| {
+            let mut data = Vec::new();
+            ntfs.open_stream(path).and_then(|mut s| s.read_to_end(&mut data)).map(|_| data)
+        };
+        assert_eq!(read(&mut ntfs, "/file.txt").unwrap(), b"hello");
+        assert_eq!(read(&mut ntfs, "\\file.txt:zone.identifier").unwrap(), b"[ZoneTransfer]");
+        assert_eq!(ntfs.stream("file.txt:Zone.Identifier:$DATA").unwrap().size(), 14);
+
+        for (path, kind) in [
+            ("file.txt:Zone.Identifier:$INDEX_ALLOCATION", io::ErrorKind::InvalidInput),
+            ("file.txt:missing", io::ErrorKind::NotFound),
+            ("missing.txt", io::ErrorKind::NotFound),
+        ].iter() {
+            assert_eq!(ntfs.stream(path).err().map(|e| e.kind()), Some(*kind));
+        }
+    }
+
+    #[test]
+    fn resolve_links() {
+        // a junction to the root, a relative symbolic link and one to itself
+        let names = [
+            (13, "file.txt", None),
+            (10, "jump", Some(link(IO_REPARSE_TAG_MOUNT_POINT, "\\??\\C:\\", None))),
+            (12, "link", Some(link(IO_REPARSE_TAG_SYMLINK, "file.txt", Some(1)))),
+            (11, "loop", Some(link(IO_REPARSE_TAG_SYMLINK, "loop", Some(1)))),
+        ];
+        let key = |name: &str| file_name(5 << 48 | 5, name, FILE_NAME_TYPE::DOS_WINDOWS, name == "jump");
+        let mut records = vec![root(&names.iter().map(|(number, name, _)| (1 << 48 | number, key(name))).collect::<Vec<_>>())];
+        for (number, name, reparse) in names.iter() {
+            let mut attributes = vec![resident(ATTRIBUTE_TYPE_CODE::FILE_NAME, "", &key(name))];
+            if let Some(reparse) = reparse {
+                attributes.push(resident(ATTRIBUTE_TYPE_CODE::REPARSE_POINT, "", reparse));
+            }
+            records.push((*number, file_record(1, if *name == "jump" { 3 } else { 1 }, 0, &attributes)));
+        }
+        let mut ntfs = volume(&records, &[], |_, _| {});
+
+        assert_eq!(ntfs.open("/link").unwrap().number(), 12);
+        assert_eq!(ntfs.resolve("/link").unwrap().number(), 13);
+        assert_eq!(ntfs.resolve("/jump/jump/link").unwrap().number(), 13);
+        assert_eq!(ntfs.resolve("/loop").err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn hard_links() {
+        // a long name and its short name in the root, and another link in a directory
+        let long = file_name(5 << 48 | 5, "Long Name.txt", FILE_NAME_TYPE::WINDOWS, false);
+        let short = file_name(5 << 48 | 5, "LONGNA~1.TXT", FILE_NAME_TYPE::DOS, false);
+        let alias = file_name(1 << 48 | 10, "alias", FILE_NAME_TYPE::POSIX, false);
+        let docs = file_name(5 << 48 | 5, "docs", FILE_NAME_TYPE::DOS_WINDOWS, true);
+        let records = [
+            root(&[(1 << 48 | 10, docs.clone()), (1 << 48 | 13, long.clone()), (1 << 48 | 13, short.clone())]),
+            (10, file_record(1, 3, 0, &[
+                resident(ATTRIBUTE_TYPE_CODE::FILE_NAME, "", &docs),
+                resident(ATTRIBUTE_TYPE_CODE::INDEX_ROOT, FILE_NAME_INDEX,
+                    &index_root(ATTRIBUTE_TYPE_CODE::FILE_NAME as u32, &[(1 << 48 | 13, alias.clone())])),
+            ])),
+            (13, file_record(1, 1, 0, &[
+                resident(ATTRIBUTE_TYPE_CODE::FILE_NAME, "", &long),
+                resident(ATTRIBUTE_TYPE_CODE::FILE_NAME, "", &short),
+                resident(ATTRIBUTE_TYPE_CODE::FILE_NAME, "", &alias),
+            ])),
+        ];
+        let mut ntfs = volume(&records, &[], |_, _| {});
+
+        let file = ntfs.read_file(13).unwrap();
+        assert_eq!(file.file_names().unwrap().len(), 3);
+        assert_eq!(ntfs.paths(&file).unwrap(), vec!["/Long Name.txt", "/docs/alias"]);
+        assert_eq!(ntfs.open("/longna~1.txt").unwrap().number(), 13);
+        assert_eq!(ntfs.open("/docs/alias").unwrap().number(), 13);
+
+        // each file is listed once, under the first name found
+        let walk = |ntfs: &mut Ntfs<Cursor<Vec<u8>>>, path| -> Vec<(String, u64)> {
+            ntfs.walk(path).unwrap().map(|e| e.map(|e| (e.path, e.reference.record)).unwrap()).collect()
+        };
+        assert_eq!(walk(&mut ntfs, "/"), vec![(String::from("/docs"), 10), (String::from("/Long Name.txt"), 13)]);
+        assert_eq!(walk(&mut ntfs, "/docs"), vec![(String::from("/docs/alias"), 13)]);
+    }
+
+    #[test]
+    fn volume_info() {
+        let label: Vec<u8> = "DATA".encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+        let mut information = vec![0u8; 12];
+        information[8..12].copy_from_slice(&[3, 1, 0x01, 0x00]);
+        let records = [(VOLUME_RECORD, file_record(3, 1, 0, &[
+            resident(ATTRIBUTE_TYPE_CODE::VOLUME_NAME, "", &label),
+            resident(ATTRIBUTE_TYPE_CODE::VOLUME_INFORMATION, "", &information),
+        ]))];
+        let mut ntfs = volume(&records, &[], |_, _| {});
+        let info = ntfs.volume_info().unwrap();
+        assert_eq!(info.label, "DATA");
+        assert_eq!((info.major_version, info.minor_version, info.is_dirty()), (3, 1, true));
+        assert_eq!(info.short_serial_number(), "5566-7788");
+        assert_eq!((info.total_sectors, info.sector_size, info.cluster_size), (512, 512, 4096));
+
+        // $VOLUME_INFORMATION is required
+        let records = [(VOLUME_RECORD, file_record(3, 1, 0, &[resident(ATTRIBUTE_TYPE_CODE::VOLUME_NAME, "", &label)]))];
+        let mut ntfs = volume(&records, &[], |_, _| {});
+        assert_eq!(ntfs.volume_info().err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+}
\ No newline at end of file
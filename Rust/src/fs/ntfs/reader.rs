@@ -0,0 +1,256 @@
+use core::cmp;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::record::{Attribute, AttributeValue};
+use super::runs::Run;
+use super::wof::WofReader;
+use super::super::super::compress::lznt1_decompress;
+use super::super::super::utils::iadd;
+
+
+const COMPRESSION_FORMAT_LZNT1: u16 = 0x0002;
+
+
+/// A reader for the value of an attribute.
+///
+/// Resident values are read from memory, non-resident values from their runs.  Sparse runs and
+/// anything between the valid data length and the file size read as zeros.  Compressed values are
+/// decompressed a unit at a time.
+pub struct AttributeReader<'a, R> {
+    inner: &'a mut R,
+    resident: Option<Vec<u8>>,
+    runs: Vec<Run>,
+    cluster_size: u64,
+    size: u64,
+    valid: u64,
+    pos: u64,
+    run: usize,     // index of the last run used, reads are usually sequential
+    compression: Option<u16>,
+    unit_size: u64,
+    unit: Option<(u64, Vec<u8>)>,   // the last compression unit read
+}
+
+impl<'a, R> AttributeReader<'a, R> {
+
+    /// Creates a new `AttributeReader` for the value of an attribute.
+    pub fn new(inner: &'a mut R, attribute: &Attribute, cluster_size: u64) -> Self {
+        let (resident, runs, size, valid, unit_size) = match attribute.value() {
+            AttributeValue::Resident(data) => (Some(data.clone()), Vec::new(), data.len() as u64, data.len() as u64, 0),
+            AttributeValue::NonResident { file_size, valid_data_length, runs, compression_unit, .. } => {
+                let unit_size = if *compression_unit < 32 { cluster_size << compression_unit } else { 0 };
+                (None, runs.clone(), *file_size, cmp::min(*valid_data_length, *file_size), unit_size)
+            }
+        };
+        let compression = if resident.is_none() { attribute.compression_format() } else { None };
+        Self {
+            inner: inner,
+            resident: resident,
+            runs: runs,
+            cluster_size: cluster_size,
+            size: size,
+            valid: valid,
+            pos: 0,
+            run: 0,
+            compression: compression,
+            unit_size: unit_size,
+            unit: None,
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R { self.inner }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R { self.inner }
+
+    /// Get the size of the value in bytes.
+    pub fn size(&self) -> u64 { self.size }
+
+    /// Find the run containing a VCN.
+    fn find_run(&mut self, vcn: u64) -> Option<Run> {
+        let contains = |run: &Run| run.vcn <= vcn && vcn - run.vcn < run.length;
+        if !self.runs.get(self.run).is_some_and(contains) {
+            self.run = self.runs.iter().position(contains)?;
+        }
+        Some(self.runs[self.run])
+    }
+}
+
+impl<'a, R> AttributeReader<'a, R>
+where R: Read + Seek {
+
+    /// Get the byte offset of a cluster number taken from the runs.
+    fn cluster_offset(&self, cluster: u64) -> io::Result<u64> {
+        match cluster.checked_mul(self.cluster_size) {
+            Some(offset) => Ok(offset),
+            None => {
+                eprintln!("ERROR: Cluster {} is out of range", cluster);
+                Err(io::Error::from(io::ErrorKind::InvalidData))
+            }
+        }
+    }
+
+    /// Read and decompress a compression unit.
+    ///
+    /// A unit which is fully allocated is stored as is, one which is fully sparse is zeros, and
+    /// anything else holds compressed data in its allocated clusters.
+    fn read_unit(&mut self, index: u64) -> io::Result<Vec<u8>> {
+        let clusters = self.unit_size / self.cluster_size;
+        let mut data = Vec::with_capacity(self.unit_size as usize);
+        let mut vcn = index * clusters;
+        while vcn < (index + 1) * clusters {
+            let run = match self.find_run(vcn) {
+                Some(run) => run,
+                None => {
+                    eprintln!("ERROR: VCN {} is not mapped", vcn);
+                    return Err(io::Error::from(io::ErrorKind::InvalidData));
+                }
+            };
+            let count = cmp::min(run.vcn + run.length, (index + 1) * clusters) - vcn;
+            let lcn = match run.lcn {
+                Some(lcn) => lcn + (vcn - run.vcn),
+                None => break,
+            };
+            let start = data.len();
+            data.resize(start + self.cluster_offset(count)? as usize, 0);
+            self.inner.seek(SeekFrom::Start(self.cluster_offset(lcn)?))?;
+            self.inner.read_exact(&mut data[start..])?;
+            vcn += count;
+        }
+
+        if data.len() as u64 == self.unit_size {
+            return Ok(data);
+        }
+        let mut unit = vec![0; self.unit_size as usize];
+        if !data.is_empty() {
+            match self.compression {
+                Some(COMPRESSION_FORMAT_LZNT1) => {
+                    lznt1_decompress(&data, &mut unit)?;
+                }
+                format => {
+                    eprintln!("ERROR: Unsupported Compression Format: {:?}", format);
+                    return Err(io::Error::from(io::ErrorKind::InvalidData));
+                }
+            }
+        }
+        Ok(unit)
+    }
+}
+
+impl<'a, R> Read for AttributeReader<'a, R>
+where R: Read + Seek {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let remaining = cmp::min(buf.len() as u64, self.size - self.pos) as usize;
+
+        if let Some(resident) = &self.resident {
+            let start = self.pos as usize;
+            buf[..remaining].copy_from_slice(&resident[start..start + remaining]);
+            self.pos += remaining as u64;
+            return Ok(remaining);
+        }
+
+        if self.pos >= self.valid {
+            for b in &mut buf[..remaining] {
+                *b = 0;
+            }
+            self.pos += remaining as u64;
+            return Ok(remaining);
+        }
+        let remaining = cmp::min(remaining as u64, self.valid - self.pos);
+
+        if self.compression.is_some() {
+            if self.unit_size == 0 {
+                eprintln!("ERROR: Compressed attribute has no compression unit");
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+            let index = self.pos / self.unit_size;
+            if self.unit.as_ref().is_none_or(|(i, _)| *i != index) {
+                self.unit = Some((index, self.read_unit(index)?));
+            }
+            let unit = &self.unit.as_ref().unwrap().1;
+            let offset = (self.pos - index * self.unit_size) as usize;
+            let count = cmp::min(remaining, self.unit_size - offset as u64) as usize;
+            buf[..count].copy_from_slice(&unit[offset..offset + count]);
+            self.pos += count as u64;
+            return Ok(count);
+        }
+
+        let vcn = self.pos / self.cluster_size;
+        let run = match self.find_run(vcn) {
+            Some(run) => run,
+            None => {
+                eprintln!("ERROR: VCN {} is not mapped", vcn);
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+        };
+        let offset = self.pos - self.cluster_offset(run.vcn)?;
+        let count = cmp::min(remaining, self.cluster_offset(run.length)? - offset) as usize;
+
+        let nread = match run.lcn {
+            Some(lcn) => {
+                self.inner.seek(SeekFrom::Start(self.cluster_offset(lcn)? + offset))?;
+                self.inner.read(&mut buf[..count])?
+            }
+            None => {
+                for b in &mut buf[..count] {
+                    *b = 0;
+                }
+                count
+            }
+        };
+        self.pos += nread as u64;
+        Ok(nread)
+    }
+}
+
+impl<'a, R> Seek for AttributeReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(n) => n,
+            SeekFrom::Current(n) => iadd(self.pos, n)?,
+            SeekFrom::End(n) => iadd(self.size, n)?,
+        };
+        Ok(self.pos)
+    }
+}
+
+
+/// A reader for a data stream, decompressing it if it was compressed by WOF.
+pub enum StreamReader<'a, R> {
+    Attribute(AttributeReader<'a, R>),
+    Wof(WofReader<AttributeReader<'a, R>>),
+}
+
+impl<'a, R> StreamReader<'a, R> {
+
+    /// Get the size of the stream in bytes.
+    pub fn size(&self) -> u64 {
+        match self {
+            StreamReader::Attribute(reader) => reader.size(),
+            StreamReader::Wof(reader) => reader.size(),
+        }
+    }
+}
+
+impl<'a, R> Read for StreamReader<'a, R>
+where R: Read + Seek {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            StreamReader::Attribute(reader) => reader.read(buf),
+            StreamReader::Wof(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<'a, R> Seek for StreamReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            StreamReader::Attribute(reader) => reader.seek(pos),
+            StreamReader::Wof(reader) => reader.seek(pos),
+        }
+    }
+}
@@ -0,0 +1,959 @@
+use core::cmp;
+use core::fmt;
+use core::mem;
+use std::error;
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use super::runs;
+use super::runs::Run;
+
+
+pub const FILE_SIGNATURE: &[u8; 4] = b"FILE";
+pub const INDEX_SIGNATURE: &[u8; 4] = b"INDX";
+const BAD_SIGNATURE: &[u8; 4] = b"BAAD";    // chkdsk found an incomplete multi-sector write
+
+// the last two bytes of every 512 byte stride are replaced with the update sequence number
+const SEQUENCE_NUMBER_STRIDE: usize = 512;
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct MULTI_SECTOR_HEADER {
+    Signature: [u8; 4],         // 'FILE', 'INDX'
+    UpdateSequenceArrayOffset: u16,
+    UpdateSequenceArraySize: u16,   // 1 + number of strides
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct FILE_REFERENCE {
+    SegmentNumberLowPart: u32,
+    SegmentNumberHighPart: u16,
+    SequenceNumber: u16,
+}
+
+impl FILE_REFERENCE {
+    fn reference(&self) -> FileReference {
+        FileReference {
+            record: self.SegmentNumberLowPart as u64 | (self.SegmentNumberHighPart as u64) << 32,
+            sequence: self.SequenceNumber,
+        }
+    }
+}
+
+/// A reference to a file record segment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileReference {
+    /// The record number in the MFT.
+    pub record: u64,
+    /// The sequence number the record must have for the reference to be current.
+    pub sequence: u16,
+}
+
+impl FileReference {
+
+    /// Unpack a 48 bit record number and 16 bit sequence number.
+    pub fn from_u64(value: u64) -> Self {
+        Self {
+            record: value & 0x0000_FFFF_FFFF_FFFF,
+            sequence: (value >> 48) as u16,
+        }
+    }
+}
+
+const FILE_RECORD_SEGMENT_IN_USE: u16 = 0x0001;
+const FILE_FILE_NAME_INDEX_PRESENT: u16 = 0x0002;
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct FILE_RECORD_SEGMENT_HEADER {
+    MultiSectorHeader: MULTI_SECTOR_HEADER,
+    LogSequenceNumber: u64,     // Reserved1
+    SequenceNumber: u16,        // 0 => record is unused
+    ReferenceCount: u16,        // Reserved2
+    FirstAttributeOffset: u16,
+    Flags: u16,                 // FILE_*
+    RealSize: u32,              // Reserved3[0]
+    AllocatedSize: u32,         // Reserved3[1]
+    BaseFileRecordSegment: FILE_REFERENCE,
+    NextAttribute: u16,
+}
+
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u32)]
+pub enum ATTRIBUTE_TYPE_CODE {
+    STANDARD_INFORMATION = 0x10,
+    ATTRIBUTE_LIST = 0x20,
+    FILE_NAME = 0x30,
+    OBJECT_ID = 0x40,
+    SECURITY_DESCRIPTOR = 0x50,
+    VOLUME_NAME = 0x60,
+    VOLUME_INFORMATION = 0x70,
+    DATA = 0x80,
+    INDEX_ROOT = 0x90,
+    INDEX_ALLOCATION = 0xA0,
+    BITMAP = 0xB0,
+    REPARSE_POINT = 0xC0,
+    EA_INFORMATION = 0xD0,
+    EA = 0xE0,
+    LOGGED_UTILITY_STREAM = 0x100,
+    END = 0xFFFFFFFF,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct ATTRIBUTE_LIST_ENTRY {
+    TypeCode: u32,              // ATTRIBUTE_TYPE_CODE
+    RecordLength: u16,
+    NameLength: u8,
+    NameOffset: u8,
+    LowestVcn: u64,
+    SegmentReference: FILE_REFERENCE,
+    Instance: u16,
+}
+
+const RESIDENT_FORM: u8 = 0x00;
+const NONRESIDENT_FORM: u8 = 0x01;
+
+const ATTRIBUTE_FLAG_COMPRESSION_MASK: u16 = 0x00FF;
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct ATTRIBUTE_RECORD_HEADER {
+    TypeCode: u32,              // ATTRIBUTE_TYPE_CODE
+    RecordLength: u32,
+    FormCode: u8,               // *_FORM
+    NameLength: u8,
+    NameOffset: u16,
+    Flags: u16,
+    Instance: u16,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct ATTRIBUTE_RECORD_HEADER_RESIDENT {
+    ValueLength: u32,
+    ValueOffset: u16,
+    Indexed: u8,
+    Padding: u8,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct ATTRIBUTE_RECORD_HEADER_NON_RESIDENT {
+    LowestVcn: u64,
+    HighestVcn: u64,
+    MappingPairOffset: u16,
+    CompressionUnit: u8,    // log2 of clusters per compression unit
+    Reserved: [u8; 5],
+    AllocatedLength: u64,   // only valid when LowestVcn == 0
+    FileSize: u64,          // only valid when LowestVcn == 0
+    ValidDataLength: u64,   // only valid when LowestVcn == 0
+}
+
+
+const PERMISSION_FLAG_REPARSE_POINT: u32 = 0x00000400;
+const PERMISSION_FLAG_DIRECTORY:     u32 = 0x10000000;
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct STANDARD_INFORMATION {
+	CreationTime: u64,
+	ModifiedTime: u64,
+	ChangeTime: u64,
+	AccessTime: u64,
+	Permissions: u32,
+	MaxVersion: u32,
+	Version: u32,
+    ClassID: u32,
+    // --- NTFS 3.0 ---
+    OwnerId: u32,
+    SecurityId: u32,
+	QuotaCharged: u64,
+	UpdateSequenceNumber: u64,
+}
+
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum FILE_NAME_TYPE {
+    POSIX = 0,
+    WINDOWS = 1,
+    DOS = 2,
+    DOS_WINDOWS = 3,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct FILE_NAME {
+    ParentDirectory: FILE_REFERENCE,
+	CreationTime: u64,
+	ModifiedTime: u64,
+	ChangeTime: u64,
+	AccessTime: u64,
+	AllocatedSize: u64,
+	RealSize: u64,
+	Permissions: u32,
+	ReparseTag: u32,
+    FileNameLength: u8,
+    Type: u8,                   // FILE_NAME_TYPE
+}
+
+
+/// A parsed `$FILE_NAME` attribute or directory index key.
+#[derive(Clone, Debug)]
+pub struct FileName {
+    pub parent: FileReference,
+    pub creation_time: u64,
+    pub modified_time: u64,
+    pub change_time: u64,
+    pub access_time: u64,
+    pub allocated_size: u64,
+    pub real_size: u64,
+    pub permissions: u32,       // PERMISSION_FLAG_*
+    pub reparse_tag: u32,
+    pub namespace: FILE_NAME_TYPE,
+    pub name: String,
+    /// The name as stored, which may not be valid UTF-16.
+    pub units: Vec<u16>,
+}
+
+impl FileName {
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < mem::size_of::<FILE_NAME>() {
+            return invalid(&format!("Invalid File Name Length: {}", data.len()));
+        }
+        let info: FILE_NAME = unsafe{ *(data.as_ptr() as *const FILE_NAME) };
+        let start = mem::size_of::<FILE_NAME>();
+        let end = start + info.FileNameLength as usize * 2;
+        if end > data.len() {
+            return invalid(&format!("Invalid File Name Length: {}", info.FileNameLength));
+        }
+        let namespace = match info.Type {
+            0 => FILE_NAME_TYPE::POSIX,
+            1 => FILE_NAME_TYPE::WINDOWS,
+            2 => FILE_NAME_TYPE::DOS,
+            3 => FILE_NAME_TYPE::DOS_WINDOWS,
+            _ => return invalid(&format!("Invalid File Name Type: {}", info.Type)),
+        };
+        let units: Vec<u16> = data[start..end].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+
+        Ok(Self {
+            parent: info.ParentDirectory.reference(),
+            creation_time: info.CreationTime,
+            modified_time: info.ModifiedTime,
+            change_time: info.ChangeTime,
+            access_time: info.AccessTime,
+            allocated_size: info.AllocatedSize,
+            real_size: info.RealSize,
+            permissions: info.Permissions,
+            reparse_tag: info.ReparseTag,
+            namespace: namespace,
+            name: String::from_utf16_lossy(&units),
+            units: units,
+        })
+    }
+
+    /// Is this a directory?
+    pub fn is_directory(&self) -> bool {
+        self.permissions & PERMISSION_FLAG_DIRECTORY != 0
+    }
+
+    /// Is this a reparse point, with its tag in `reparse_tag`?
+    pub fn is_reparse_point(&self) -> bool {
+        self.permissions & PERMISSION_FLAG_REPARSE_POINT != 0
+    }
+}
+
+
+/// A parsed `$STANDARD_INFORMATION` attribute.
+#[derive(Clone, Debug)]
+pub struct StandardInformation {
+    pub creation_time: u64,
+    pub modified_time: u64,
+    pub change_time: u64,
+    pub access_time: u64,
+    pub permissions: u32,       // PERMISSION_FLAG_*
+    pub max_version: u32,
+    pub version: u32,
+    pub class_id: u32,
+    /// Only present from NTFS 3.0.
+    pub owner_id: Option<u32>,
+    pub security_id: Option<u32>,
+    pub quota_charged: Option<u64>,
+    pub update_sequence_number: Option<u64>,
+}
+
+impl StandardInformation {
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        // NTFS 1.2 stops before the owner
+        let legacy = mem::size_of::<STANDARD_INFORMATION>() - 24;
+        if data.len() < legacy {
+            return invalid(&format!("Invalid Standard Information Length: {}", data.len()));
+        }
+        let mut buffer = [0; mem::size_of::<STANDARD_INFORMATION>()];
+        let length = cmp::min(data.len(), buffer.len());
+        buffer[..length].copy_from_slice(&data[..length]);
+        let info: STANDARD_INFORMATION = unsafe{ *(buffer.as_ptr() as *const STANDARD_INFORMATION) };
+        let extended = data.len() >= buffer.len();
+
+        Ok(Self {
+            creation_time: info.CreationTime,
+            modified_time: info.ModifiedTime,
+            change_time: info.ChangeTime,
+            access_time: info.AccessTime,
+            permissions: info.Permissions,
+            max_version: info.MaxVersion,
+            version: info.Version,
+            class_id: info.ClassID,
+            owner_id: if extended { Some(info.OwnerId) } else { None },
+            security_id: if extended { Some(info.SecurityId) } else { None },
+            quota_charged: if extended { Some(info.QuotaCharged) } else { None },
+            update_sequence_number: if extended { Some(info.UpdateSequenceNumber) } else { None },
+        })
+    }
+}
+
+
+/// Errors for structures protected by an update sequence array.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordError {
+    /// The structure does not have the expected signature.
+    Signature([u8; 4]),
+    /// The structure was marked bad by chkdsk.
+    Bad,
+    /// The update sequence array is malformed.
+    UpdateSequenceArray,
+    /// A stride does not end with the update sequence number, ie. a torn write.
+    UpdateSequenceNumber(usize),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::Signature(signature) => write!(f, "Invalid Signature: {:?}", String::from_utf8_lossy(signature)),
+            RecordError::Bad => write!(f, "Record marked BAAD"),
+            RecordError::UpdateSequenceArray => write!(f, "Invalid Update Sequence Array"),
+            RecordError::UpdateSequenceNumber(stride) => write!(f, "Update Sequence Number mismatch in sector {}", stride),
+        }
+    }
+}
+
+impl error::Error for RecordError {}
+
+impl From<RecordError> for io::Error {
+    fn from(err: RecordError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+
+/// Check the signature of a multi-sector structure and apply its update sequence array.
+pub fn apply_fixups(data: &mut [u8], signature: &[u8; 4]) -> Result<(), RecordError> {
+    if data.len() < mem::size_of::<MULTI_SECTOR_HEADER>() {
+        return Err(RecordError::UpdateSequenceArray);
+    }
+    let header: MULTI_SECTOR_HEADER = unsafe{ *(data.as_ptr() as *const MULTI_SECTOR_HEADER) };
+    if &header.Signature == BAD_SIGNATURE {
+        return Err(RecordError::Bad);
+    }
+    if &header.Signature != signature {
+        return Err(RecordError::Signature(header.Signature));
+    }
+
+    let strides = data.len() / SEQUENCE_NUMBER_STRIDE;
+    let offset = header.UpdateSequenceArrayOffset as usize;
+    let count = header.UpdateSequenceArraySize as usize;
+    if count != strides + 1 || !offset.is_multiple_of(2) || offset + count * 2 > cmp::min(data.len(), SEQUENCE_NUMBER_STRIDE - 2) {
+        return Err(RecordError::UpdateSequenceArray);
+    }
+
+    let usn = [data[offset], data[offset + 1]];
+    for i in 0..strides {
+        let end = (i + 1) * SEQUENCE_NUMBER_STRIDE;
+        if data[end - 2..end] != usn {
+            return Err(RecordError::UpdateSequenceNumber(i));
+        }
+        let entry = offset + (i + 1) * 2;
+        data[end - 2] = data[entry];
+        data[end - 1] = data[entry + 1];
+    }
+    Ok(())
+}
+
+/// Protect a multi-sector structure with its update sequence array before writing it.
+///
+/// The last two bytes of each sector are saved in the array and replaced by the update sequence
+/// number, which is incremented.
+pub fn write_fixups(data: &mut [u8]) -> Result<(), RecordError> {
+    if data.len() < mem::size_of::<MULTI_SECTOR_HEADER>() {
+        return Err(RecordError::UpdateSequenceArray);
+    }
+    let header: MULTI_SECTOR_HEADER = unsafe{ *(data.as_ptr() as *const MULTI_SECTOR_HEADER) };
+    let strides = data.len() / SEQUENCE_NUMBER_STRIDE;
+    let offset = header.UpdateSequenceArrayOffset as usize;
+    let count = header.UpdateSequenceArraySize as usize;
+    if count != strides + 1 || !offset.is_multiple_of(2) || offset + count * 2 > cmp::min(data.len(), SEQUENCE_NUMBER_STRIDE - 2) {
+        return Err(RecordError::UpdateSequenceArray);
+    }
+
+    // 0 and 0xFFFF are never used
+    let usn = match u16::from_le_bytes([data[offset], data[offset + 1]]).wrapping_add(1) {
+        0 | 0xFFFF => 1,
+        usn => usn,
+    };
+    data[offset..offset + 2].copy_from_slice(&usn.to_le_bytes());
+    for i in 0..strides {
+        let end = (i + 1) * SEQUENCE_NUMBER_STRIDE;
+        let entry = offset + (i + 1) * 2;
+        data[entry] = data[end - 2];
+        data[entry + 1] = data[end - 1];
+        data[end - 2..end].copy_from_slice(&usn.to_le_bytes());
+    }
+    Ok(())
+}
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Does a buffer hold a multi-sector structure that was never written?
+pub fn is_unused(data: &[u8]) -> bool {
+    data.len() >= 4 && data[..4] == [0; 4]
+}
+
+/// Read a multi-sector structure into memory and apply its fixups.
+pub fn read_multi_sector<R>(device: &mut R, offset: u64, size: u64, signature: &[u8; 4]) -> io::Result<Vec<u8>>
+where R: Read + Seek {
+    let mut data = vec![0; size as usize];
+    device.seek(SeekFrom::Start(offset)).and_then(|_| device.read_exact(&mut data)).map_err(|err| {
+        eprintln!("ERROR: Read Failed: {}", err);
+        err
+    })?;
+    debug_xxd!(&data, offset);
+
+    apply_fixups(&mut data, signature).inspect_err(|err| {
+        eprintln!("ERROR: {} at offset {}", err, offset);
+    })?;
+    Ok(data)
+}
+
+
+/// A parsed file record segment.
+#[derive(Clone, Debug)]
+pub struct FileRecord {
+    number: u64,
+    header: FILE_RECORD_SEGMENT_HEADER,
+    attributes: Vec<Attribute>,
+}
+
+impl FileRecord {
+
+    /// Read a file record segment and apply its fixups.
+    pub fn new<R>(device: &mut R, number: u64, offset: u64, size: u64, cluster_size: u64, cluster_count: u64) -> io::Result<Self>
+    where R: Read + Seek {
+        let data = read_multi_sector(device, offset, size, FILE_SIGNATURE)?;
+        Self::parse(&data, number, cluster_size, cluster_count)
+    }
+
+    /// Parse a file record segment that has already had its fixups applied.
+    pub fn parse(data: &[u8], number: u64, cluster_size: u64, cluster_count: u64) -> io::Result<Self> {
+        let size = data.len() as u64;
+        let mut cursor = Cursor::new(data);
+        let header = read_struct!(FILE_RECORD_SEGMENT_HEADER, cursor, 0, size)?;
+
+        let max = cmp::min(size, header.RealSize as u64);
+        let mut attributes = Vec::new();
+        let mut pos = header.FirstAttributeOffset as u64;
+        while pos + 4 <= max {
+            let type_code = read_struct!(u32, cursor, pos, max - pos)?;
+            if type_code == ATTRIBUTE_TYPE_CODE::END as u32 {
+                break;
+            }
+            let attribute = read_struct!(ATTRIBUTE_RECORD_HEADER, cursor, pos, max - pos)?;
+            let length = attribute.RecordLength as u64;
+            if length < mem::size_of::<ATTRIBUTE_RECORD_HEADER>() as u64 || !length.is_multiple_of(8) || length > max - pos {
+                return invalid(&format!("Invalid Attribute Length: {}", length));
+            }
+            attributes.push(Attribute::parse(&data[pos as usize..(pos + length) as usize], pos, cluster_size, cluster_count)?);
+            pos += length;
+        }
+
+        Ok(Self {
+            number: number,
+            header: header,
+            attributes: attributes,
+        })
+    }
+
+    /// Get the record number in the MFT.
+    pub fn number(&self) -> u64 {
+        self.number
+    }
+
+    /// Get the sequence number, which is incremented each time the record is reused.
+    pub fn sequence_number(&self) -> u16 {
+        self.header.SequenceNumber
+    }
+
+    /// Get a reference to this record at its current sequence number.
+    pub fn reference(&self) -> FileReference {
+        FileReference { record: self.number, sequence: self.header.SequenceNumber }
+    }
+
+    /// Get the sequence number of the last `$LogFile` record that modified this record.
+    pub fn log_sequence_number(&self) -> u64 {
+        self.header.LogSequenceNumber
+    }
+
+    /// Get the number of directory entries which reference this record.
+    pub fn link_count(&self) -> u16 {
+        self.header.ReferenceCount
+    }
+
+    pub fn is_in_use(&self) -> bool {
+        self.header.Flags & FILE_RECORD_SEGMENT_IN_USE != 0
+    }
+
+    /// Does this record have a file name index, ie. is it a directory?
+    pub fn is_directory(&self) -> bool {
+        self.header.Flags & FILE_FILE_NAME_INDEX_PRESENT != 0
+    }
+
+    /// Get the base record if this is an extension record.
+    pub fn base_record(&self) -> Option<FileReference> {
+        let base = self.header.BaseFileRecordSegment.reference();
+        if base.record == 0 && base.sequence == 0 {
+            None
+        } else {
+            Some(base)
+        }
+    }
+
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    /// Replace the attributes, eg. with those merged from an attribute list.
+    pub fn set_attributes(&mut self, attributes: Vec<Attribute>) {
+        self.attributes = attributes;
+    }
+
+    /// Find the first attribute with a type and name.
+    pub fn attribute(&self, type_code: ATTRIBUTE_TYPE_CODE, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|a| a.type_code == type_code as u32 && a.name == name)
+    }
+
+    /// Parse the `$STANDARD_INFORMATION`.
+    pub fn standard_information(&self) -> io::Result<StandardInformation> {
+        match self.attribute(ATTRIBUTE_TYPE_CODE::STANDARD_INFORMATION, "").map(|a| &a.value) {
+            Some(AttributeValue::Resident(data)) => StandardInformation::parse(data),
+            _ => invalid(&format!("MFT record {} has no resident $STANDARD_INFORMATION", self.number)),
+        }
+    }
+
+    /// Parse the `$FILE_NAME`s, one for each hard link and another for a short name.
+    pub fn file_names(&self) -> io::Result<Vec<FileName>> {
+        self.attributes.iter().filter(|a| a.type_code == ATTRIBUTE_TYPE_CODE::FILE_NAME as u32).filter_map(|a| match &a.value {
+            AttributeValue::Resident(data) => Some(FileName::parse(data)),
+            AttributeValue::NonResident { .. } => None,
+        }).collect()
+    }
+
+    /// Get the `$DATA` streams, the unnamed default stream having an empty name.
+    pub fn streams(&self) -> Vec<&Attribute> {
+        self.attributes.iter().filter(|a| a.type_code == ATTRIBUTE_TYPE_CODE::DATA as u32).collect()
+    }
+}
+
+
+/// The value of an attribute.
+#[derive(Clone, Debug)]
+pub enum AttributeValue {
+    /// The value is stored in the file record.
+    Resident(Vec<u8>),
+    /// The value is stored in clusters.  Sizes are only valid when `lowest_vcn` is 0.
+    NonResident {
+        lowest_vcn: u64,
+        highest_vcn: u64,
+        allocated_length: u64,
+        file_size: u64,
+        valid_data_length: u64,
+        compression_unit: u8,   // log2 of clusters per compression unit
+        runs: Vec<Run>,
+    },
+}
+
+/// A parsed attribute.
+#[derive(Clone, Debug)]
+pub struct Attribute {
+    type_code: u32,
+    name: String,
+    flags: u16,
+    instance: u16,
+    value: AttributeValue,
+    value_offset: Option<u64>,  // of a resident value in its file record segment
+}
+
+impl Attribute {
+
+    /// Parse an attribute record found at `offset` in its file record segment.
+    fn parse(data: &[u8], offset: u64, cluster_size: u64, cluster_count: u64) -> io::Result<Self> {
+        let length = data.len() as u64;
+        let mut cursor = Cursor::new(data);
+        let header = read_struct!(ATTRIBUTE_RECORD_HEADER, cursor, 0, length)?;
+        let read = mem::size_of::<ATTRIBUTE_RECORD_HEADER>() as u64;
+
+        let name_start = header.NameOffset as usize;
+        let name_end = name_start + header.NameLength as usize * 2;
+        if name_end > data.len() {
+            return invalid(&format!("Invalid Attribute Name Offset: {}", name_start));
+        }
+        let name: Vec<u16> = data[name_start..name_end].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        let name = String::from_utf16_lossy(&name);
+        debug!("Attribute Name: {}", name);
+
+        let mut value_offset = None;
+        let value = if header.FormCode == RESIDENT_FORM {
+            let resident = read_struct!(ATTRIBUTE_RECORD_HEADER_RESIDENT, cursor, read, length - read)?;
+            let start = resident.ValueOffset as usize;
+            let end = start + resident.ValueLength as usize;
+            if end > data.len() {
+                return invalid(&format!("Invalid Attribute Value Length: {}", { resident.ValueLength }));
+            }
+
+            if header.TypeCode == ATTRIBUTE_TYPE_CODE::FILE_NAME as u32 {
+                let name = FileName::parse(&data[start..end])?;
+                debug!("File Name: {}", name.name);
+            }
+
+            value_offset = Some(offset + start as u64);
+            AttributeValue::Resident(data[start..end].to_vec())
+        } else if header.FormCode == NONRESIDENT_FORM {
+            let loc = read_struct!(ATTRIBUTE_RECORD_HEADER_NON_RESIDENT, cursor, read, length - read)?;
+            let mapping_pairs = match data.get(loc.MappingPairOffset as usize..) {
+                Some(mapping_pairs) => mapping_pairs,
+                None => return invalid(&format!("Invalid Mapping Pair Offset: {}", { loc.MappingPairOffset })),
+            };
+            let runs = runs::decode(mapping_pairs, loc.LowestVcn, loc.HighestVcn, cluster_count)?;
+            debug!("{:#?}", runs);
+
+            // the remaining clusters of a split attribute are described by extension records
+            let clusters = loc.HighestVcn.wrapping_add(1);
+            if loc.LowestVcn == 0 && clusters.checked_mul(cluster_size).is_none_or(|size| size > loc.AllocatedLength) {
+                return invalid(&format!("Runs exceed the Allocated Length: {} clusters vs {}", clusters, { loc.AllocatedLength }));
+            }
+
+            AttributeValue::NonResident {
+                lowest_vcn: loc.LowestVcn,
+                highest_vcn: loc.HighestVcn,
+                allocated_length: loc.AllocatedLength,
+                file_size: loc.FileSize,
+                valid_data_length: loc.ValidDataLength,
+                compression_unit: loc.CompressionUnit,
+                runs: runs,
+            }
+        } else {
+            return invalid(&format!("Invalid Attribute Form Code: {}", header.FormCode));
+        };
+
+        Ok(Self {
+            type_code: header.TypeCode,
+            name: name,
+            flags: header.Flags,
+            instance: header.Instance,
+            value: value,
+            value_offset: value_offset,
+        })
+    }
+
+    /// Get the type, one of `ATTRIBUTE_TYPE_CODE`.
+    pub fn type_code(&self) -> u32 { self.type_code }
+
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Get the flags, `ATTRIBUTE_FLAG_*`.
+    pub fn flags(&self) -> u16 { self.flags }
+
+    /// Get the identifier, unique within the file record.
+    pub fn instance(&self) -> u16 { self.instance }
+
+    pub fn value(&self) -> &AttributeValue { &self.value }
+
+    /// Get the offset of a resident value in the file record segment which holds it.
+    pub fn value_offset(&self) -> Option<u64> { self.value_offset }
+
+    pub fn is_resident(&self) -> bool {
+        matches!(self.value, AttributeValue::Resident(_))
+    }
+
+    /// Is the value stored in compression units?
+    pub fn is_compressed(&self) -> bool {
+        self.flags & ATTRIBUTE_FLAG_COMPRESSION_MASK != 0
+    }
+
+    /// Get the `COMPRESSION_FORMAT_*` of a compressed value, which the flags hold minus one.
+    pub fn compression_format(&self) -> Option<u16> {
+        if self.is_compressed() {
+            Some((self.flags & ATTRIBUTE_FLAG_COMPRESSION_MASK) + 1)
+        } else {
+            None
+        }
+    }
+
+    /// Get the size of the value in bytes.
+    pub fn size(&self) -> u64 {
+        match &self.value {
+            AttributeValue::Resident(data) => data.len() as u64,
+            AttributeValue::NonResident { file_size, .. } => *file_size,
+        }
+    }
+
+    /// Get the run list of a non-resident value.
+    pub fn runs(&self) -> &[Run] {
+        match &self.value {
+            AttributeValue::Resident(_) => &[],
+            AttributeValue::NonResident { runs, .. } => runs,
+        }
+    }
+
+    /// Get the first VCN described by this extent, 0 for resident values.
+    pub fn lowest_vcn(&self) -> u64 {
+        match &self.value {
+            AttributeValue::Resident(_) => 0,
+            AttributeValue::NonResident { lowest_vcn, .. } => *lowest_vcn,
+        }
+    }
+
+    /// Append the next extent of a non-resident attribute that was split across file records.
+    pub fn extend(&mut self, extent: &Attribute, cluster_size: u64) -> io::Result<()> {
+        let (next, last, more) = match &extent.value {
+            AttributeValue::NonResident { lowest_vcn, highest_vcn, runs, .. } => (*lowest_vcn, *highest_vcn, runs),
+            _ => return invalid("Resident Attribute extent"),
+        };
+        match &mut self.value {
+            AttributeValue::NonResident { highest_vcn, allocated_length, runs, .. } if next == highest_vcn.wrapping_add(1) => {
+                if last.wrapping_add(1).checked_mul(cluster_size).is_none_or(|size| size > *allocated_length) {
+                    return invalid(&format!("Runs exceed the Allocated Length: {} clusters vs {}", last.wrapping_add(1), allocated_length));
+                }
+                runs.extend_from_slice(more);
+                *highest_vcn = last;
+                Ok(())
+            }
+            _ => invalid(&format!("Attribute extent at VCN {} is not contiguous", next)),
+        }
+    }
+}
+
+
+/// An entry of an `$ATTRIBUTE_LIST`, locating one attribute of a file.
+#[derive(Clone, Debug)]
+pub struct AttributeListEntry {
+    pub type_code: u32,
+    pub name: String,
+    pub lowest_vcn: u64,
+    pub segment: FileReference,
+    pub instance: u16,
+}
+
+/// Parse the value of an `$ATTRIBUTE_LIST`.
+pub fn parse_attribute_list(data: &[u8]) -> io::Result<Vec<AttributeListEntry>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos + mem::size_of::<ATTRIBUTE_LIST_ENTRY>() <= data.len() {
+        let entry: ATTRIBUTE_LIST_ENTRY = unsafe{ *(data[pos..].as_ptr() as *const ATTRIBUTE_LIST_ENTRY) };
+        debug!("{:#?}", entry);
+
+        let length = entry.RecordLength as usize;
+        let name_start = pos + entry.NameOffset as usize;
+        let name_end = name_start + entry.NameLength as usize * 2;
+        if length < mem::size_of::<ATTRIBUTE_LIST_ENTRY>() || pos + length > data.len() || name_end > pos + length {
+            return invalid(&format!("Invalid Attribute List Entry at {}", pos));
+        }
+        let name: Vec<u16> = data[name_start..name_end].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+
+        entries.push(AttributeListEntry {
+            type_code: entry.TypeCode,
+            name: String::from_utf16_lossy(&name),
+            lowest_vcn: entry.LowestVcn,
+            segment: entry.SegmentReference.reference(),
+            instance: entry.Instance,
+        });
+        pos += length;
+    }
+    Ok(entries)
+}
+
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Build a file record segment, whose fixups have been applied, holding attribute records.
+    pub fn file_record(sequence: u16, flags: u16, base: u64, attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0u8; 1024];
+        data[..4].copy_from_slice(FILE_SIGNATURE);
+        data[4..6].copy_from_slice(&0x30u16.to_le_bytes());
+        data[6..8].copy_from_slice(&3u16.to_le_bytes());
+        data[16..18].copy_from_slice(&sequence.to_le_bytes());
+        data[18..20].copy_from_slice(&1u16.to_le_bytes());
+        data[20..22].copy_from_slice(&0x38u16.to_le_bytes());
+        data[22..24].copy_from_slice(&flags.to_le_bytes());
+        data[28..32].copy_from_slice(&1024u32.to_le_bytes());
+        data[32..40].copy_from_slice(&base.to_le_bytes());
+
+        let mut pos = 0x38;
+        for (instance, attribute) in attributes.iter().enumerate() {
+            data[pos..pos + attribute.len()].copy_from_slice(attribute);
+            data[pos + 14..pos + 16].copy_from_slice(&(instance as u16).to_le_bytes());
+            pos += attribute.len();
+        }
+        data[pos..pos + 4].copy_from_slice(&(ATTRIBUTE_TYPE_CODE::END as u32).to_le_bytes());
+        data[24..28].copy_from_slice(&(pos as u32 + 8).to_le_bytes());
+        data[40..42].copy_from_slice(&(attributes.len() as u16).to_le_bytes());
+        data
+    }
+
+    /// The attribute header and name, padded to where its value or mapping pairs start.
+    fn attribute_header(type_code: ATTRIBUTE_TYPE_CODE, form: u8, name: &str, size: usize) -> Vec<u8> {
+        let name: Vec<u8> = name.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+        let mut data = vec![0u8; size];
+        data[..4].copy_from_slice(&(type_code as u32).to_le_bytes());
+        data[8] = form;
+        data[9] = (name.len() / 2) as u8;
+        data[10..12].copy_from_slice(&(size as u16).to_le_bytes());
+        data.extend_from_slice(&name);
+        data.resize(data.len().next_multiple_of(8), 0);
+        data
+    }
+
+    /// Build a resident attribute record.
+    pub fn resident(type_code: ATTRIBUTE_TYPE_CODE, name: &str, value: &[u8]) -> Vec<u8> {
+        let mut data = attribute_header(type_code, RESIDENT_FORM, name, 24);
+        let offset = data.len();
+        data[16..20].copy_from_slice(&(value.len() as u32).to_le_bytes());
+        data[20..22].copy_from_slice(&(offset as u16).to_le_bytes());
+        data.extend_from_slice(value);
+        data.resize(data.len().next_multiple_of(8), 0);
+        let length = data.len() as u32;
+        data[4..8].copy_from_slice(&length.to_le_bytes());
+        data
+    }
+
+    /// Build a non-resident attribute record with its mapping pairs, for 4 KiB clusters.
+    pub fn non_resident(type_code: ATTRIBUTE_TYPE_CODE, name: &str, lowest_vcn: u64, highest_vcn: u64, pairs: &[u8], size: u64) -> Vec<u8> {
+        let mut data = attribute_header(type_code, NONRESIDENT_FORM, name, 64);
+        let offset = data.len();
+        data[16..24].copy_from_slice(&lowest_vcn.to_le_bytes());
+        data[24..32].copy_from_slice(&highest_vcn.to_le_bytes());
+        data[32..34].copy_from_slice(&(offset as u16).to_le_bytes());
+        data[40..48].copy_from_slice(&(highest_vcn.wrapping_add(1) * 4096).to_le_bytes());
+        data[48..56].copy_from_slice(&size.to_le_bytes());
+        data[56..64].copy_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(pairs);
+        data.push(0);
+        data.resize(data.len().next_multiple_of(8), 0);
+        let length = data.len() as u32;
+        data[4..8].copy_from_slice(&length.to_le_bytes());
+        data
+    }
+
+    /// Build the value of a `$FILE_NAME`, also used as the key of a directory index entry.
+    pub fn file_name(parent: u64, name: &str, namespace: FILE_NAME_TYPE, directory: bool) -> Vec<u8> {
+        let name: Vec<u8> = name.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+        let mut data = vec![0u8; mem::size_of::<FILE_NAME>()];
+        data[..8].copy_from_slice(&parent.to_le_bytes());
+        if directory {
+            data[56..60].copy_from_slice(&PERMISSION_FLAG_DIRECTORY.to_le_bytes());
+        }
+        data[64] = (name.len() / 2) as u8;
+        data[65] = namespace as u8;
+        data.extend_from_slice(&name);
+        data
+    }
+
+    /// A two sector record with its update sequence array at 0x30 and recognisable sector tails.
+    fn record() -> Vec<u8> {
+        let mut data: Vec<u8> = (0..1024).map(|i| (i % 251) as u8).collect();
+        data[..4].copy_from_slice(FILE_SIGNATURE);
+        data[4..6].copy_from_slice(&0x30u16.to_le_bytes());
+        data[6..8].copy_from_slice(&3u16.to_le_bytes());
+        data[0x30..0x36].copy_from_slice(&[0x41, 0x00, 0, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn write_fixups_round_trip() {
+        let original = record();
+        let mut data = original.clone();
+        write_fixups(&mut data).unwrap();
+        assert_eq!(&data[0x30..0x32], &[0x42, 0x00]);
+        assert_eq!(&data[510..512], &[0x42, 0x00]);
+        assert_eq!(&data[1022..1024], &[0x42, 0x00]);
+        assert_eq!(&data[0x32..0x36], &[original[510], original[511], original[1022], original[1023]]);
+
+        apply_fixups(&mut data, FILE_SIGNATURE).unwrap();
+        assert_eq!(&data[..0x30], &original[..0x30]);
+        assert_eq!(&data[0x36..], &original[0x36..]);
+    }
+
+    #[test]
+    fn torn_write() {
+        let mut data = record();
+        write_fixups(&mut data).unwrap();
+        data[1022] ^= 0xFF;
+        assert_eq!(apply_fixups(&mut data, FILE_SIGNATURE), Err(RecordError::UpdateSequenceNumber(1)));
+    }
+
+    #[test]
+    fn signatures() {
+        let mut data = record();
+        write_fixups(&mut data).unwrap();
+        assert_eq!(apply_fixups(&mut data.clone(), INDEX_SIGNATURE), Err(RecordError::Signature(*FILE_SIGNATURE)));
+
+        let mut bad = data.clone();
+        bad[..4].copy_from_slice(BAD_SIGNATURE);
+        assert_eq!(apply_fixups(&mut bad, FILE_SIGNATURE), Err(RecordError::Bad));
+
+        // the array must cover every sector and fit in the first
+        let mut short = data.clone();
+        short[6..8].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(apply_fixups(&mut short, FILE_SIGNATURE), Err(RecordError::UpdateSequenceArray));
+        assert_eq!(apply_fixups(&mut data[..512], FILE_SIGNATURE), Err(RecordError::UpdateSequenceArray));
+    }
+
+    #[test]
+    fn read_from_device() {
+        let mut data = file_record(7, FILE_RECORD_SEGMENT_IN_USE, 0, &[resident(ATTRIBUTE_TYPE_CODE::DATA, "", b"hello")]);
+        write_fixups(&mut data).unwrap();
+        let mut image = vec![0u8; 1024];
+        image.extend_from_slice(&data);
+        let mut device = Cursor::new(image);
+
+        let record = FileRecord::new(&mut device, 1, 1024, 1024, 4096, 0x100).unwrap();
+        assert_eq!(record.reference(), FileReference { record: 1, sequence: 7 });
+        assert!(record.is_in_use());
+        assert_eq!(record.attributes().len(), 1);
+
+        let err = read_multi_sector(&mut device, 0, 1024, FILE_SIGNATURE).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref()), Some(&RecordError::Signature([0; 4])));
+        assert!(read_multi_sector(&mut device, 1024, 2048, FILE_SIGNATURE).is_err());
+    }
+}
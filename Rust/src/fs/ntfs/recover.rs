@@ -0,0 +1,76 @@
+use super::bitmap::ClusterBitmap;
+use super::record::{Attribute, FileName, FileRecord};
+use super::runs::Run;
+use super::join_path;
+
+
+/// A run of clusters of a deleted stream.
+#[derive(Clone, Copy, Debug)]
+pub struct RecoveredRun {
+    pub run: Run,
+    /// Number of the clusters which `$Bitmap` shows have since been allocated again.
+    pub reallocated: u64,
+}
+
+/// A data stream of a deleted file.
+#[derive(Clone, Debug)]
+pub struct DeletedStream {
+    pub attribute: Attribute,
+    pub runs: Vec<RecoveredRun>,
+}
+
+impl DeletedStream {
+
+    /// Check the runs of a stream against the cluster bitmap.
+    pub fn new(attribute: Attribute, bitmap: &ClusterBitmap) -> Self {
+        let runs = attribute.runs().iter().map(|run| RecoveredRun {
+            run: *run,
+            reallocated: run.lcn.map_or(0, |lcn| bitmap.count_allocated(lcn, run.length)),
+        }).collect();
+        Self {
+            attribute: attribute,
+            runs: runs,
+        }
+    }
+
+    /// Get the number of clusters which have been allocated again.
+    pub fn reallocated(&self) -> u64 {
+        self.runs.iter().map(|r| r.reallocated).sum()
+    }
+
+    /// Can the data still be read as it was, ie. is it resident or are none of its clusters
+    /// allocated again?
+    pub fn is_intact(&self) -> bool {
+        self.reallocated() == 0
+    }
+}
+
+/// A file whose record is no longer in use.
+#[derive(Clone, Debug)]
+pub struct DeletedFile {
+    /// The record as it was left when the file was deleted.
+    pub record: FileRecord,
+    /// The long name of the file, if the record still has a name.
+    pub name: Option<FileName>,
+    /// The path of the directory which held the file, `None` if it has been reused or the file
+    /// has no name.
+    pub parent: Option<String>,
+    pub streams: Vec<DeletedStream>,
+}
+
+impl DeletedFile {
+
+    /// Has the directory which held the file been reused, so its path is lost?
+    pub fn is_orphan(&self) -> bool {
+        self.name.is_some() && self.parent.is_none()
+    }
+
+    /// Get the path the file had, if both its name and directory are known.
+    pub fn path(&self) -> Option<String> {
+        match (&self.parent, &self.name) {
+            (Some(parent), Some(name)) => Some(join_path(parent, &name.name)),
+            _ => None,
+        }
+    }
+}
+
@@ -0,0 +1,280 @@
+use core::mem;
+use std::io;
+
+use super::wof::FILE_PROVIDER_COMPRESSION;
+
+
+pub const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA0000003;
+pub const IO_REPARSE_TAG_SYMLINK: u32 = 0xA000000C;
+pub const IO_REPARSE_TAG_DEDUP: u32 = 0x80000013;
+pub const IO_REPARSE_TAG_WOF: u32 = 0x80000017;
+pub const IO_REPARSE_TAG_CLOUD: u32 = 0x9000001A;          // 0x9000X01A for X in 0..=F
+pub const IO_REPARSE_TAG_APPEXECLINK: u32 = 0x8000001B;
+pub const IO_REPARSE_TAG_LX_SYMLINK: u32 = 0xA000001D;
+pub const IO_REPARSE_TAG_AF_UNIX: u32 = 0x80000023;
+
+const IO_REPARSE_TAG_CLOUD_MASK: u32 = 0x0000F000;
+const SYMLINK_FLAG_RELATIVE: u32 = 0x00000001;
+const LX_SYMLINK_VERSION: u32 = 2;
+const APPEXECLINK_VERSION: u32 = 3;
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct REPARSE_DATA_BUFFER {
+    ReparseTag: u32,
+    ReparseDataLength: u16,
+    Reserved: u16,
+    // DataBuffer: [u8; ReparseDataLength],
+}
+
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct MOUNT_POINT_REPARSE_BUFFER {
+    SubstituteNameOffset: u16,  // relative to the path buffer
+    SubstituteNameLength: u16,
+    PrintNameOffset: u16,
+    PrintNameLength: u16,
+    // PathBuffer: [u16],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct SYMBOLIC_LINK_REPARSE_BUFFER {
+    SubstituteNameOffset: u16,  // relative to the path buffer
+    SubstituteNameLength: u16,
+    PrintNameOffset: u16,
+    PrintNameLength: u16,
+    Flags: u32,                 // SYMLINK_FLAG_*
+    // PathBuffer: [u16],
+}
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Get a UTF-16 string at a byte offset and length within a path buffer.
+fn utf16(buffer: &[u8], offset: u16, length: u16) -> io::Result<String> {
+    let start = offset as usize;
+    let end = start + length as usize;
+    if end > buffer.len() || !length.is_multiple_of(2) {
+        return invalid(&format!("Invalid Reparse Path: {} + {}", offset, length));
+    }
+    let units: Vec<u16> = buffer[start..end].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    Ok(String::from_utf16_lossy(&units))
+}
+
+
+/// The decoded data of a reparse point.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReparseData {
+    /// A symbolic link, relative to its directory or absolute, eg. `\??\C:\Windows`.
+    SymbolicLink { target: String, print_name: String, relative: bool },
+    /// A junction to a directory or a volume mount point.
+    MountPoint { target: String, print_name: String },
+    /// A file whose data is in the deduplication chunk store.
+    Deduplication,
+    /// A file compressed by the Windows Overlay Filter.
+    Wof(FILE_PROVIDER_COMPRESSION),
+    /// A placeholder for a cloud file.
+    Cloud,
+    /// An application execution alias, eg. in `WindowsApps`.
+    AppExecLink { package: String, application: String, target: String },
+    /// A symbolic link created by the Windows Subsystem for Linux.
+    LxSymbolicLink { target: String },
+    /// A Unix domain socket.
+    UnixSocket,
+    /// Any other tag.
+    Unknown(u32),
+}
+
+
+/// A parsed `$REPARSE_POINT` attribute.
+#[derive(Clone, Debug)]
+pub struct ReparsePoint {
+    tag: u32,
+    data: Vec<u8>,
+}
+
+impl ReparsePoint {
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < mem::size_of::<REPARSE_DATA_BUFFER>() {
+            return invalid(&format!("Invalid Reparse Point Length: {}", data.len()));
+        }
+        let header: REPARSE_DATA_BUFFER = unsafe{ *(data.as_ptr() as *const REPARSE_DATA_BUFFER) };
+        debug!("{:#?}", header);
+        let end = mem::size_of::<REPARSE_DATA_BUFFER>() + header.ReparseDataLength as usize;
+        if end > data.len() {
+            return invalid(&format!("Invalid Reparse Data Length: {}", { header.ReparseDataLength }));
+        }
+        Ok(Self {
+            tag: header.ReparseTag,
+            data: data[mem::size_of::<REPARSE_DATA_BUFFER>()..end].to_vec(),
+        })
+    }
+
+    /// Get the `IO_REPARSE_TAG_*`.
+    pub fn tag(&self) -> u32 { self.tag }
+
+    /// Get the tag specific data.
+    pub fn data(&self) -> &[u8] { &self.data }
+
+    /// Decode the tag specific data.
+    pub fn decode(&self) -> io::Result<ReparseData> {
+        let data = &self.data;
+        match self.tag {
+            IO_REPARSE_TAG_SYMLINK => {
+                let size = mem::size_of::<SYMBOLIC_LINK_REPARSE_BUFFER>();
+                if data.len() < size {
+                    return invalid(&format!("Invalid Symbolic Link Length: {}", data.len()));
+                }
+                let link: SYMBOLIC_LINK_REPARSE_BUFFER = unsafe{ *(data.as_ptr() as *const SYMBOLIC_LINK_REPARSE_BUFFER) };
+                debug!("{:#?}", link);
+                Ok(ReparseData::SymbolicLink {
+                    target: utf16(&data[size..], link.SubstituteNameOffset, link.SubstituteNameLength)?,
+                    print_name: utf16(&data[size..], link.PrintNameOffset, link.PrintNameLength)?,
+                    relative: link.Flags & SYMLINK_FLAG_RELATIVE != 0,
+                })
+            }
+            IO_REPARSE_TAG_MOUNT_POINT => {
+                let size = mem::size_of::<MOUNT_POINT_REPARSE_BUFFER>();
+                if data.len() < size {
+                    return invalid(&format!("Invalid Mount Point Length: {}", data.len()));
+                }
+                let mount: MOUNT_POINT_REPARSE_BUFFER = unsafe{ *(data.as_ptr() as *const MOUNT_POINT_REPARSE_BUFFER) };
+                debug!("{:#?}", mount);
+                Ok(ReparseData::MountPoint {
+                    target: utf16(&data[size..], mount.SubstituteNameOffset, mount.SubstituteNameLength)?,
+                    print_name: utf16(&data[size..], mount.PrintNameOffset, mount.PrintNameLength)?,
+                })
+            }
+            IO_REPARSE_TAG_DEDUP => Ok(ReparseData::Deduplication),
+            IO_REPARSE_TAG_WOF => FILE_PROVIDER_COMPRESSION::parse(data).map(ReparseData::Wof),
+            tag if tag & !IO_REPARSE_TAG_CLOUD_MASK == IO_REPARSE_TAG_CLOUD => Ok(ReparseData::Cloud),
+            IO_REPARSE_TAG_APPEXECLINK => {
+                // a version followed by NUL terminated strings
+                if data.len() < 4 || u32::from_le_bytes([data[0], data[1], data[2], data[3]]) != APPEXECLINK_VERSION {
+                    return invalid("Unsupported App Execution Link Version");
+                }
+                let units: Vec<u16> = data[4..].chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+                let mut strings = units.split(|&c| c == 0).map(String::from_utf16_lossy);
+                match (strings.next(), strings.next(), strings.next()) {
+                    (Some(package), Some(application), Some(target)) => {
+                        Ok(ReparseData::AppExecLink { package: package, application: application, target: target })
+                    }
+                    _ => invalid("App Execution Link is truncated"),
+                }
+            }
+            IO_REPARSE_TAG_LX_SYMLINK => {
+                if data.len() < 4 || u32::from_le_bytes([data[0], data[1], data[2], data[3]]) != LX_SYMLINK_VERSION {
+                    return invalid("Unsupported LX Symbolic Link Version");
+                }
+                Ok(ReparseData::LxSymbolicLink { target: String::from_utf8_lossy(&data[4..]).into_owned() })
+            }
+            IO_REPARSE_TAG_AF_UNIX => Ok(ReparseData::UnixSocket),
+            tag => Ok(ReparseData::Unknown(tag)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use super::super::super::super::utils::hex_decode;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect()
+    }
+
+    /// Build the value of a `$REPARSE_POINT`.
+    pub fn reparse_point(tag: u32, data: &[u8]) -> Vec<u8> {
+        let mut buffer = tag.to_le_bytes().to_vec();
+        buffer.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        buffer.extend_from_slice(&[0, 0]);
+        buffer.extend_from_slice(data);
+        buffer
+    }
+
+    /// Build a symbolic link, or a mount point without `flags`, whose print name is its target.
+    pub fn link(tag: u32, target: &str, flags: Option<u32>) -> Vec<u8> {
+        let name = utf16le(target);
+        let length = name.len() as u16;
+        let mut data = Vec::new();
+        for field in [0, length, length, length].iter() {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        if let Some(flags) = flags {
+            data.extend_from_slice(&flags.to_le_bytes());
+        }
+        data.extend_from_slice(&name);
+        data.extend_from_slice(&name);
+        reparse_point(tag, &data)
+    }
+
+    fn decode(data: &[u8]) -> io::Result<ReparseData> {
+        ReparsePoint::parse(data)?.decode()
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(decode(&link(IO_REPARSE_TAG_SYMLINK, "..\\target", Some(SYMLINK_FLAG_RELATIVE))).unwrap(), ReparseData::SymbolicLink {
+            target: String::from("..\\target"),
+            print_name: String::from("..\\target"),
+            relative: true,
+        });
+        assert_eq!(decode(&link(IO_REPARSE_TAG_MOUNT_POINT, "\\??\\C:\\Users", None)).unwrap(), ReparseData::MountPoint {
+            target: String::from("\\??\\C:\\Users"),
+            print_name: String::from("\\??\\C:\\Users"),
+        });
+
+        let mut lx = LX_SYMLINK_VERSION.to_le_bytes().to_vec();
+        lx.extend_from_slice(b"/usr/bin/python3");
+        assert_eq!(decode(&reparse_point(IO_REPARSE_TAG_LX_SYMLINK, &lx)).unwrap(), ReparseData::LxSymbolicLink {
+            target: String::from("/usr/bin/python3"),
+        });
+
+        let mut app = APPEXECLINK_VERSION.to_le_bytes().to_vec();
+        app.extend_from_slice(&utf16le("Microsoft.App_8wekyb3d8bbwe\0Microsoft.App_8wekyb3d8bbwe!App\0C:\\app.exe\0"));
+        assert_eq!(decode(&reparse_point(IO_REPARSE_TAG_APPEXECLINK, &app)).unwrap(), ReparseData::AppExecLink {
+            package: String::from("Microsoft.App_8wekyb3d8bbwe"),
+            application: String::from("Microsoft.App_8wekyb3d8bbwe!App"),
+            target: String::from("C:\\app.exe"),
+        });
+    }
+
+    #[test]
+    fn tags() {
+        let wof = hex_decode("01000000 02000000 01000000 01000000 00000000");
+        assert_eq!(decode(&reparse_point(IO_REPARSE_TAG_WOF, &wof)).unwrap(), ReparseData::Wof(FILE_PROVIDER_COMPRESSION::LZX));
+        assert_eq!(decode(&reparse_point(0x9000301A, &[])).unwrap(), ReparseData::Cloud);
+        assert_eq!(decode(&reparse_point(IO_REPARSE_TAG_DEDUP, &[0; 8])).unwrap(), ReparseData::Deduplication);
+        assert_eq!(decode(&reparse_point(IO_REPARSE_TAG_AF_UNIX, &[])).unwrap(), ReparseData::UnixSocket);
+        assert_eq!(decode(&reparse_point(0x00000042, &[1, 2])).unwrap(), ReparseData::Unknown(0x42));
+    }
+
+    #[test]
+    fn invalid_buffers() {
+        let mut truncated = link(IO_REPARSE_TAG_SYMLINK, "target", Some(0));
+        truncated.pop();
+        assert!(ReparsePoint::parse(&truncated).is_err());
+        assert!(ReparsePoint::parse(&[0; 7]).is_err());
+
+        // names past the path buffer, or of an odd length
+        let mut outside = link(IO_REPARSE_TAG_MOUNT_POINT, "target", None);
+        outside[12] = 0xF0;
+        assert!(decode(&outside).is_err());
+        let mut odd = link(IO_REPARSE_TAG_SYMLINK, "target", Some(0));
+        odd[10] = 11;
+        assert!(decode(&odd).is_err());
+
+        assert!(decode(&reparse_point(IO_REPARSE_TAG_LX_SYMLINK, &[1, 0, 0, 0])).is_err());
+        assert!(decode(&reparse_point(IO_REPARSE_TAG_APPEXECLINK, &[3, 0, 0, 0, b'a', 0])).is_err());
+    }
+}
@@ -0,0 +1,556 @@
+use core::cmp;
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use super::logfile::{LogOperation, LogRecord, LogUpdate};
+use super::record::{apply_fixups, write_fixups, FILE_SIGNATURE, INDEX_SIGNATURE};
+
+
+/// Cluster block offsets are in 512 byte blocks.
+const BLOCK_SIZE: u64 = 512;
+
+// FILE_RECORD_SEGMENT_HEADER and INDEX_ALLOCATION_BUFFER
+const LSN_OFFSET: usize = 0x08;
+const FLAGS_OFFSET: usize = 0x16;
+const REAL_SIZE_OFFSET: usize = 0x18;
+const ALLOCATED_SIZE_OFFSET: usize = 0x1C;
+const INDEX_HEADER_OFFSET: usize = 0x18;
+const FILE_RECORD_SEGMENT_IN_USE: u16 = 0x0001;
+
+// ATTRIBUTE_RECORD_HEADER
+const RECORD_LENGTH_OFFSET: usize = 0x04;
+const VALUE_LENGTH_OFFSET: usize = 0x10;
+const VALUE_OFFSET_OFFSET: usize = 0x14;
+const ALLOCATED_LENGTH_OFFSET: usize = 0x28;
+const MAPPING_PAIR_OFFSET_OFFSET: usize = 0x20;
+const INDEX_ROOT_HEADER_OFFSET: usize = 0x10;
+
+// INDEX_HEADER
+const FIRST_FREE_BYTE_OFFSET: usize = 0x04;
+const BYTES_AVAILABLE_OFFSET: usize = 0x08;
+
+// INDEX_ENTRY
+const ENTRY_LENGTH_OFFSET: usize = 0x08;
+const ENTRY_KEY_OFFSET: usize = 0x10;
+const DUPLICATED_INFORMATION_OFFSET: usize = 0x08;     // after the parent in a FILE_NAME
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+fn get(data: &[u8], offset: usize, length: usize) -> io::Result<&[u8]> {
+    match data.get(offset..offset + length) {
+        Some(slice) => Ok(slice),
+        None => invalid(&format!("Log update out of bounds: {} + {}", offset, length)),
+    }
+}
+
+fn set(data: &mut [u8], offset: usize, value: &[u8]) -> io::Result<()> {
+    match data.get_mut(offset..offset + value.len()) {
+        Some(slice) => {
+            slice.copy_from_slice(value);
+            Ok(())
+        }
+        None => invalid(&format!("Log update out of bounds: {} + {}", offset, value.len())),
+    }
+}
+
+fn get16(data: &[u8], offset: usize) -> io::Result<u16> {
+    get(data, offset, 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn get32(data: &[u8], offset: usize) -> io::Result<u32> {
+    get(data, offset, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn get64(data: &[u8], offset: usize) -> io::Result<u64> {
+    get(data, offset, 8).map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+}
+
+/// Add to a 32 bit length.
+fn adjust32(data: &mut [u8], offset: usize, delta: isize) -> io::Result<()> {
+    let value = get32(data, offset)? as isize + delta;
+    if value < 0 {
+        return invalid(&format!("Log update makes a negative length at {}", offset));
+    }
+    set(data, offset, &(value as u32).to_le_bytes())
+}
+
+/// Insert bytes at `pos`, moving those up to `end` along.
+fn insert(data: &mut [u8], pos: usize, end: usize, value: &[u8]) -> io::Result<()> {
+    if pos > end || end + value.len() > data.len() {
+        return invalid(&format!("Log update does not fit: {} + {} in {}", end, value.len(), data.len()));
+    }
+    data.copy_within(pos..end, pos + value.len());
+    set(data, pos, value)
+}
+
+/// Remove `length` bytes at `pos`, moving those up to `end` back.
+fn remove(data: &mut [u8], pos: usize, end: usize, length: usize) -> io::Result<()> {
+    if pos + length > end || end > data.len() {
+        return invalid(&format!("Log update removes too much: {} + {} in {}", pos, length, end));
+    }
+    data.copy_within(pos + length..end, pos);
+    for b in &mut data[end - length..end] {
+        *b = 0;
+    }
+    Ok(())
+}
+
+/// Change the length of an attribute in a file record, moving the attributes after it.
+fn resize_attribute(record: &mut [u8], attribute: usize, length: usize) -> io::Result<isize> {
+    let old = get32(record, attribute + RECORD_LENGTH_OFFSET)? as usize;
+    let real = get32(record, REAL_SIZE_OFFSET)? as usize;
+    let allocated = cmp::min(get32(record, ALLOCATED_SIZE_OFFSET)? as usize, record.len());
+    let end = attribute + old;
+    if length > old {
+        if real + length - old > allocated {
+            return invalid(&format!("Log update overflows the file record: {}", real + length - old));
+        }
+        insert(record, end, real, &vec![0; length - old])?;
+    } else if length < old {
+        remove(record, attribute + length, real, old - length)?;
+    }
+    let delta = length as isize - old as isize;
+    set(record, attribute + RECORD_LENGTH_OFFSET, &(length as u32).to_le_bytes())?;
+    adjust32(record, REAL_SIZE_OFFSET, delta)?;
+    Ok(delta)
+}
+
+/// Insert or remove an index entry in an `$INDEX_ROOT`, growing or shrinking the attribute.
+fn resize_index_root(record: &mut [u8], attribute: usize, pos: usize, entry: Option<&[u8]>) -> io::Result<()> {
+    let value = attribute + get16(record, attribute + VALUE_OFFSET_OFFSET)? as usize;
+    let header = value + INDEX_ROOT_HEADER_OFFSET;
+    let length = get32(record, attribute + RECORD_LENGTH_OFFSET)? as usize;
+    let end = attribute + length;
+    let delta = match entry {
+        Some(entry) => {
+            resize_attribute(record, attribute, length + entry.len())?;
+            insert(record, pos, end, entry)?;
+            entry.len() as isize
+        }
+        None => {
+            let size = get16(record, pos + ENTRY_LENGTH_OFFSET)? as usize;
+            remove(record, pos, end, size)?;
+            resize_attribute(record, attribute, length - size)?;
+            -(size as isize)
+        }
+    };
+    adjust32(record, attribute + VALUE_LENGTH_OFFSET, delta)?;
+    adjust32(record, header + FIRST_FREE_BYTE_OFFSET, delta)?;
+    adjust32(record, header + BYTES_AVAILABLE_OFFSET, delta)
+}
+
+/// Apply an operation to a file record segment.
+fn apply_file_record(record: &mut [u8], operation: LogOperation, data: &[u8], other: usize, update: &LogUpdate) -> io::Result<()> {
+    let attribute = update.record_offset as usize;
+    let pos = attribute + update.attribute_offset as usize;
+    match operation {
+        LogOperation::InitializeFileRecordSegment => set(record, 0, data),
+        LogOperation::DeallocateFileRecordSegment => {
+            let flags = get16(record, FLAGS_OFFSET)? & !FILE_RECORD_SEGMENT_IN_USE;
+            set(record, FLAGS_OFFSET, &flags.to_le_bytes())
+        }
+        LogOperation::WriteEndOfFileRecordSegment => {
+            resize_attribute(record, attribute, (update.attribute_offset as usize + data.len() + 7) & !7)?;
+            set(record, pos, data)
+        }
+        LogOperation::CreateAttribute => {
+            let real = get32(record, REAL_SIZE_OFFSET)? as usize;
+            insert(record, attribute, real, data)?;
+            adjust32(record, REAL_SIZE_OFFSET, data.len() as isize)
+        }
+        LogOperation::DeleteAttribute => {
+            let real = get32(record, REAL_SIZE_OFFSET)? as usize;
+            let length = get32(record, attribute + RECORD_LENGTH_OFFSET)? as usize;
+            remove(record, attribute, real, length)?;
+            adjust32(record, REAL_SIZE_OFFSET, -(length as isize))
+        }
+        LogOperation::UpdateResidentValue => {
+            // the value changes length when the redo and undo data do
+            if data.len() != other {
+                let value_offset = get16(record, attribute + VALUE_OFFSET_OFFSET)? as usize;
+                let value_length = match (update.attribute_offset as usize + data.len()).checked_sub(value_offset) {
+                    Some(length) => length,
+                    None => return invalid(&format!("Log update before the value: {}", { update.attribute_offset })),
+                };
+                resize_attribute(record, attribute, (value_offset + value_length + 7) & !7)?;
+                set(record, attribute + VALUE_LENGTH_OFFSET, &(value_length as u32).to_le_bytes())?;
+            }
+            set(record, pos, data)
+        }
+        LogOperation::UpdateMappingPairs => {
+            if data.len() != other {
+                resize_attribute(record, attribute, (update.attribute_offset as usize + data.len() + 7) & !7)?;
+            }
+            set(record, pos, data)
+        }
+        LogOperation::SetNewAttributeSizes => {
+            // NEW_ATTRIBUTE_SIZES is allocated, valid, file size and total allocated
+            let allocated = get64(data, 0)?;
+            let valid = get64(data, 8)?;
+            let size = get64(data, 16)?;
+            set(record, attribute + ALLOCATED_LENGTH_OFFSET, &allocated.to_le_bytes())?;
+            set(record, attribute + ALLOCATED_LENGTH_OFFSET + 8, &size.to_le_bytes())?;
+            set(record, attribute + ALLOCATED_LENGTH_OFFSET + 16, &valid.to_le_bytes())?;
+            if let (Ok(total), true) = (get64(data, 24), get16(record, attribute + MAPPING_PAIR_OFFSET_OFFSET)? >= 0x48) {
+                set(record, attribute + ALLOCATED_LENGTH_OFFSET + 24, &total.to_le_bytes())?;
+            }
+            Ok(())
+        }
+        LogOperation::AddIndexEntryRoot => resize_index_root(record, attribute, pos, Some(data)),
+        LogOperation::DeleteIndexEntryRoot => resize_index_root(record, attribute, pos, None),
+        LogOperation::UpdateFileNameRoot => set(record, pos + ENTRY_KEY_OFFSET + DUPLICATED_INFORMATION_OFFSET, data),
+        LogOperation::SetIndexEntryVcnRoot => {
+            let length = get16(record, pos + ENTRY_LENGTH_OFFSET)? as usize;
+            set(record, pos + length - 8, data)
+        }
+        operation => invalid(&format!("{:?} does not apply to a file record", operation)),
+    }
+}
+
+/// Apply an operation to an index allocation buffer.
+fn apply_index_buffer(buffer: &mut [u8], operation: LogOperation, data: &[u8], update: &LogUpdate) -> io::Result<()> {
+    let pos = update.record_offset as usize + update.attribute_offset as usize;
+    let free = INDEX_HEADER_OFFSET + FIRST_FREE_BYTE_OFFSET;
+    let end = INDEX_HEADER_OFFSET + get32(buffer, free)? as usize;
+    match operation {
+        LogOperation::AddIndexEntryAllocation => {
+            insert(buffer, pos, end, data)?;
+            adjust32(buffer, free, data.len() as isize)
+        }
+        LogOperation::DeleteIndexEntryAllocation => {
+            let length = get16(buffer, pos + ENTRY_LENGTH_OFFSET)? as usize;
+            remove(buffer, pos, end, length)?;
+            adjust32(buffer, free, -(length as isize))
+        }
+        LogOperation::WriteEndOfIndexBuffer => {
+            set(buffer, pos, data)?;
+            set(buffer, free, &((pos + data.len() - INDEX_HEADER_OFFSET) as u32).to_le_bytes())
+        }
+        LogOperation::UpdateFileNameAllocation => set(buffer, pos + ENTRY_KEY_OFFSET + DUPLICATED_INFORMATION_OFFSET, data),
+        LogOperation::SetIndexEntryVcnAllocation => {
+            let length = get16(buffer, pos + ENTRY_LENGTH_OFFSET)? as usize;
+            set(buffer, pos + length - 8, data)
+        }
+        operation => invalid(&format!("{:?} does not apply to an index buffer", operation)),
+    }
+}
+
+
+/// The outcome of replaying the log.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Replay {
+    /// Updates of committed transactions applied.
+    pub redone: usize,
+    /// Updates of incomplete transactions rolled back.
+    pub undone: usize,
+    /// Updates already on disk, or which never reached it.
+    pub skipped: usize,
+    /// Updates which could not be applied.
+    pub failed: usize,
+}
+
+/// Replays log records onto a device.
+struct Replayer<'a, D> {
+    device: &'a mut D,
+    cluster_size: u64,
+    record_size: u64,
+    index_buffer_size: u64,
+}
+
+impl<'a, D> Replayer<'a, D>
+where D: Read + Write + Seek {
+
+    /// Get the location on the device of an offset in the page an update targets.
+    fn locate(&self, update: &LogUpdate, offset: u64) -> io::Result<u64> {
+        match update.lcns.get((offset / self.cluster_size) as usize) {
+            Some(lcn) => Ok(lcn * self.cluster_size + offset % self.cluster_size),
+            None => invalid(&format!("Log update at VCN {} has no LCN for offset {}", update.target_vcn, offset)),
+        }
+    }
+
+    /// Read from the page an update targets, a cluster at a time.
+    fn read(&mut self, update: &LogUpdate, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; length];
+        let mut done = 0;
+        while done < length {
+            let location = self.locate(update, offset + done as u64)?;
+            let count = cmp::min(length - done, (self.cluster_size - location % self.cluster_size) as usize);
+            self.device.seek(SeekFrom::Start(location))?;
+            self.device.read_exact(&mut data[done..done + count])?;
+            done += count;
+        }
+        Ok(data)
+    }
+
+    /// Write to the page an update targets, a cluster at a time.
+    fn write(&mut self, update: &LogUpdate, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut done = 0;
+        while done < data.len() {
+            let location = self.locate(update, offset + done as u64)?;
+            let count = cmp::min(data.len() - done, (self.cluster_size - location % self.cluster_size) as usize);
+            self.device.seek(SeekFrom::Start(location))?;
+            self.device.write_all(&data[done..done + count])?;
+            done += count;
+        }
+        Ok(())
+    }
+
+    /// Apply the redo or undo half of a record, returning false if it was not needed.
+    ///
+    /// File records and index buffers hold the LSN of their last update, which shows whether
+    /// an update reached the disk.
+    fn apply(&mut self, record: &LogRecord, undo: bool) -> io::Result<bool> {
+        let update = match &record.update {
+            Some(update) => update,
+            None => return Ok(false),
+        };
+        let (operation, data, other) = if undo {
+            (update.undo_operation, &update.undo_data, update.redo_data.len())
+        } else {
+            (update.redo_operation, &update.redo_data, update.undo_data.len())
+        };
+        let offset = update.cluster_block_offset as u64 * BLOCK_SIZE;
+
+        let (signature, size) = match operation {
+            LogOperation::InitializeFileRecordSegment | LogOperation::DeallocateFileRecordSegment |
+            LogOperation::WriteEndOfFileRecordSegment | LogOperation::CreateAttribute | LogOperation::DeleteAttribute |
+            LogOperation::UpdateResidentValue | LogOperation::UpdateMappingPairs | LogOperation::SetNewAttributeSizes |
+            LogOperation::AddIndexEntryRoot | LogOperation::DeleteIndexEntryRoot | LogOperation::UpdateFileNameRoot |
+            LogOperation::SetIndexEntryVcnRoot => (FILE_SIGNATURE, self.record_size),
+            LogOperation::AddIndexEntryAllocation | LogOperation::DeleteIndexEntryAllocation |
+            LogOperation::WriteEndOfIndexBuffer | LogOperation::UpdateFileNameAllocation |
+            LogOperation::SetIndexEntryVcnAllocation => (INDEX_SIGNATURE, self.index_buffer_size),
+            LogOperation::SetBitsInNonresidentBitMap | LogOperation::ClearBitsInNonresidentBitMap => {
+                // BITMAP_RANGE is the first bit and the number of bits
+                let (first, count) = (get32(data, 0)? as u64, get32(data, 4)? as u64);
+                if count == 0 {
+                    return Ok(false);
+                }
+                let start = offset + first / 8;
+                let mut bitmap = self.read(update, start, (first % 8 + count).div_ceil(8) as usize)?;
+                for bit in first % 8..first % 8 + count {
+                    let mask = 1 << (bit % 8);
+                    if operation == LogOperation::SetBitsInNonresidentBitMap {
+                        bitmap[(bit / 8) as usize] |= mask;
+                    } else {
+                        bitmap[(bit / 8) as usize] &= !mask;
+                    }
+                }
+                self.write(update, start, &bitmap)?;
+                return Ok(true);
+            }
+            LogOperation::UpdateNonresidentValue => {
+                let start = offset + update.record_offset as u64 + update.attribute_offset as u64;
+                self.write(update, start, data)?;
+                return Ok(true);
+            }
+            _ => return Ok(false),
+        };
+
+        let mut page = self.read(update, offset, size as usize)?;
+        let lsn = match apply_fixups(&mut page, signature) {
+            Ok(()) => get64(&page, LSN_OFFSET)?,
+            Err(_) if operation == LogOperation::InitializeFileRecordSegment => 0,
+            Err(err) => {
+                eprintln!("ERROR: Log update at LCN {:?}: {}", update.lcns.first(), err);
+                return Err(err.into());
+            }
+        };
+        if (!undo && lsn >= record.lsn) || (undo && lsn < record.lsn) {
+            return Ok(false);
+        }
+
+        if signature == FILE_SIGNATURE {
+            apply_file_record(&mut page, operation, data, other, update)?;
+        } else {
+            apply_index_buffer(&mut page, operation, data, update)?;
+        }
+        if !undo {
+            set(&mut page, LSN_OFFSET, &record.lsn.to_le_bytes())?;
+        }
+        write_fixups(&mut page)?;
+        self.write(update, offset, &page)?;
+        Ok(true)
+    }
+}
+
+/// Replay log records onto a device, redoing the updates of committed transactions and undoing
+/// those of transactions which never completed.
+pub fn replay<D>(device: &mut D, records: &[LogRecord], cluster_size: u64, record_size: u64, index_buffer_size: u64) -> io::Result<Replay>
+where D: Read + Write + Seek {
+    // a transaction is committed when it is forgotten, its slot may then be reused
+    let mut pending: HashMap<u32, Vec<usize>> = HashMap::new();
+    let mut committed = vec![false; records.len()];
+    for (i, record) in records.iter().enumerate() {
+        let operation = match &record.update {
+            Some(update) => update.redo_operation,
+            None => continue,
+        };
+        match operation {
+            LogOperation::CommitTransaction | LogOperation::ForgetTransaction => {
+                for j in pending.remove(&record.transaction_id).unwrap_or_default() {
+                    committed[j] = true;
+                }
+            }
+            _ => pending.entry(record.transaction_id).or_default().push(i),
+        }
+    }
+    let mut incomplete: Vec<usize> = pending.into_values().flatten().collect();
+    incomplete.sort_unstable_by(|a, b| b.cmp(a));
+    debug!("Replaying {} records, {} incomplete", records.len(), incomplete.len());
+
+    let mut replayer = Replayer {
+        device: device,
+        cluster_size: cluster_size,
+        record_size: record_size,
+        index_buffer_size: index_buffer_size,
+    };
+    let mut result = Replay::default();
+    let redo = records.iter().zip(committed).filter(|(_, c)| *c).map(|(r, _)| (r, false));
+    let undo = incomplete.into_iter().map(|i| (&records[i], true));
+    for (record, undo) in redo.chain(undo) {
+        match replayer.apply(record, undo) {
+            Ok(true) if undo => result.undone += 1,
+            Ok(true) => result.redone += 1,
+            Ok(false) => result.skipped += 1,
+            Err(_) => {
+                eprintln!("WARNING: Failed to {} LSN {:#x}", if undo { "undo" } else { "redo" }, record.lsn);
+                result.failed += 1;
+            }
+        }
+    }
+    Ok(result)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use super::super::record::tests::{file_record, non_resident, resident};
+    use super::super::record::ATTRIBUTE_TYPE_CODE::DATA;
+
+    // the first attribute of a test file record, and the value of a resident one
+    const ATTRIBUTE: usize = 0x38;
+    const VALUE: usize = ATTRIBUTE + 24;
+
+    fn log_update(redo_operation: LogOperation, undo_operation: LogOperation, redo: &[u8], undo: &[u8], attribute_offset: u16) -> LogUpdate {
+        LogUpdate {
+            redo_operation: redo_operation,
+            undo_operation: undo_operation,
+            redo_data: redo.to_vec(),
+            undo_data: undo.to_vec(),
+            target_attribute: 0,
+            record_offset: ATTRIBUTE as u16,
+            attribute_offset: attribute_offset,
+            cluster_block_offset: 0,
+            target_vcn: 0,
+            lcns: vec![1],
+        }
+    }
+
+    fn log_record(lsn: u64, transaction_id: u32, update: LogUpdate) -> LogRecord {
+        LogRecord {
+            lsn: lsn,
+            previous_lsn: 0,
+            undo_next_lsn: 0,
+            client_index: 0,
+            record_type: super::super::logfile::LOG_RECORD_CLIENT,
+            transaction_id: transaction_id,
+            flags: 0,
+            data: Vec::new(),
+            update: Some(update),
+        }
+    }
+
+    #[test]
+    fn resident_values() {
+        // redo grows the value and undo shrinks it back
+        let mut record = file_record(1, 1, 0, &[resident(DATA, "", b"hello")]);
+        let update = log_update(LogOperation::UpdateResidentValue, LogOperation::UpdateResidentValue, b"hello, world", b"hello", 24);
+        assert_eq!(get32(&record, REAL_SIZE_OFFSET).unwrap(), 0x60);
+
+        apply_file_record(&mut record, update.redo_operation, &update.redo_data, update.undo_data.len(), &update).unwrap();
+        assert_eq!(get32(&record, ATTRIBUTE + RECORD_LENGTH_OFFSET).unwrap(), 40);
+        assert_eq!(get32(&record, ATTRIBUTE + VALUE_LENGTH_OFFSET).unwrap(), 12);
+        assert_eq!(&record[VALUE..VALUE + 12], b"hello, world");
+        assert_eq!(get32(&record, ATTRIBUTE + 40).unwrap(), 0xFFFFFFFF);
+        assert_eq!(get32(&record, REAL_SIZE_OFFSET).unwrap(), 0x68);
+
+        apply_file_record(&mut record, update.undo_operation, &update.undo_data, update.redo_data.len(), &update).unwrap();
+        assert_eq!(get32(&record, ATTRIBUTE + RECORD_LENGTH_OFFSET).unwrap(), 32);
+        assert_eq!(get32(&record, ATTRIBUTE + VALUE_LENGTH_OFFSET).unwrap(), 5);
+        assert_eq!(&record[VALUE..VALUE + 5], b"hello");
+        assert_eq!(get32(&record, ATTRIBUTE + 32).unwrap(), 0xFFFFFFFF);
+        assert_eq!(get32(&record, REAL_SIZE_OFFSET).unwrap(), 0x60);
+        assert!(record[0x60..].iter().all(|&b| b == 0));
+
+        // a value may not grow past the end of the record
+        let update = log_update(LogOperation::UpdateResidentValue, LogOperation::Noop, &[0; 1000], &[], 24);
+        assert!(apply_file_record(&mut record, update.redo_operation, &update.redo_data, 0, &update).is_err());
+    }
+
+    #[test]
+    fn create_and_delete_attribute() {
+        let original = file_record(1, 1, 0, &[resident(DATA, "", b"hello")]);
+        let attribute = resident(DATA, "ads", b"stream");
+        let mut update = log_update(LogOperation::CreateAttribute, LogOperation::DeleteAttribute, &attribute, &[], 0);
+        update.record_offset = 0x58;
+
+        let mut record = original.clone();
+        apply_file_record(&mut record, update.redo_operation, &update.redo_data, 0, &update).unwrap();
+        assert_eq!(&record[0x58..0x58 + attribute.len()], &attribute[..]);
+        assert_eq!(get32(&record, 0x58 + attribute.len()).unwrap(), 0xFFFFFFFF);
+        assert_eq!(get32(&record, REAL_SIZE_OFFSET).unwrap() as usize, 0x60 + attribute.len());
+
+        apply_file_record(&mut record, update.undo_operation, &update.undo_data, 0, &update).unwrap();
+        assert_eq!(record, original);
+        assert!(apply_file_record(&mut record, LogOperation::UpdateNonresidentValue, &[], 0, &update).is_err());
+    }
+
+    #[test]
+    fn attribute_sizes() {
+        // NEW_ATTRIBUTE_SIZES is allocated, valid and file size
+        let mut record = file_record(1, 1, 0, &[non_resident(DATA, "", 0, 1, &[0x21, 0x02, 0x04], 8192)]);
+        let sizes: Vec<u8> = [12288u64, 9000, 10000].iter().flat_map(|n| n.to_le_bytes().to_vec()).collect();
+        let update = log_update(LogOperation::SetNewAttributeSizes, LogOperation::SetNewAttributeSizes, &sizes, &[], 0);
+        apply_file_record(&mut record, update.redo_operation, &update.redo_data, 0, &update).unwrap();
+        assert_eq!(get64(&record, ATTRIBUTE + ALLOCATED_LENGTH_OFFSET).unwrap(), 12288);
+        assert_eq!(get64(&record, ATTRIBUTE + ALLOCATED_LENGTH_OFFSET + 8).unwrap(), 10000);
+        assert_eq!(get64(&record, ATTRIBUTE + ALLOCATED_LENGTH_OFFSET + 16).unwrap(), 9000);
+        assert!(apply_file_record(&mut record, update.redo_operation, &sizes[..16], 0, &update).is_err());
+    }
+
+    #[test]
+    fn redo_and_undo() {
+        // the record on disk holds an update of transaction 2, which never committed
+        let mut page = file_record(1, 1, 0, &[resident(DATA, "", b"HELLO world")]);
+        page[LSN_OFFSET..LSN_OFFSET + 8].copy_from_slice(&0x120u64.to_le_bytes());
+        write_fixups(&mut page).unwrap();
+        let mut device = vec![0u8; 8192];
+        device[4096..4096 + 1024].copy_from_slice(&page);
+
+        let mut broken = log_update(LogOperation::UpdateResidentValue, LogOperation::Noop, b"!", b"", 24);
+        broken.lcns.clear();
+        let records = [
+            log_record(0x100, 1, log_update(LogOperation::UpdateResidentValue, LogOperation::Noop, b"x", b"w", 30)),
+            log_record(0x120, 2, log_update(LogOperation::UpdateResidentValue, LogOperation::UpdateResidentValue, b"HELLO", b"hello", 24)),
+            log_record(0x130, 1, log_update(LogOperation::UpdateResidentValue, LogOperation::UpdateResidentValue, b"WORLD", b"xorld", 30)),
+            log_record(0x138, 1, broken),
+            log_record(0x140, 1, log_update(LogOperation::CommitTransaction, LogOperation::Noop, b"", b"", 0)),
+        ];
+        let mut device = Cursor::new(device);
+        let result = replay(&mut device, &records, 4096, 1024, 4096).unwrap();
+        assert_eq!((result.redone, result.undone, result.skipped, result.failed), (1, 1, 1, 1));
+
+        let mut page = device.into_inner()[4096..4096 + 1024].to_vec();
+        apply_fixups(&mut page, FILE_SIGNATURE).unwrap();
+        assert_eq!(get64(&page, LSN_OFFSET).unwrap(), 0x130);
+        assert_eq!(&page[VALUE..VALUE + 11], b"hello WORLD");
+    }
+}
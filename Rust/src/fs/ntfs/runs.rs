@@ -0,0 +1,118 @@
+use std::io;
+
+
+/// A contiguous range of clusters of a non-resident attribute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Run {
+    /// First Virtual Cluster Number, relative to the start of the attribute.
+    pub vcn: u64,
+    /// First Logical Cluster Number on the volume, `None` for a sparse run.
+    pub lcn: Option<u64>,
+    /// Number of clusters.
+    pub length: u64,
+}
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Read a little endian integer of up to eight bytes, sign extending if requested.
+fn read_varint(data: &[u8], signed: bool) -> u64 {
+    let mut value = data.iter().rev().fold(0, |n, &b| (n << 8) | b as u64);
+    if signed && !data.is_empty() && data.len() < 8 && data[data.len() - 1] & 0x80 != 0 {
+        value |= !0 << (data.len() * 8);
+    }
+    value
+}
+
+/// Decode the mapping pairs of a non-resident attribute.
+///
+/// Each pair is a header byte holding the size of the length (low nibble) and the size of the
+/// signed LCN delta (high nibble), followed by the length and delta.  A pair without a delta is
+/// sparse.  The list ends with a zero header byte.  Runs must lie within the `cluster_count`
+/// clusters of the volume.
+pub fn decode(data: &[u8], lowest_vcn: u64, highest_vcn: u64, cluster_count: u64) -> io::Result<Vec<Run>> {
+    let mut runs = Vec::new();
+    let mut vcn = lowest_vcn;
+    let mut lcn: u64 = 0;
+    let mut pos = 0;
+
+    while pos < data.len() && data[pos] != 0 {
+        let length_size = (data[pos] & 0x0F) as usize;
+        let offset_size = (data[pos] >> 4) as usize;
+        pos += 1;
+        if length_size == 0 || length_size > 8 || offset_size > 8 || pos + length_size + offset_size > data.len() {
+            return invalid(&format!("Invalid Mapping Pair at {}", pos - 1));
+        }
+
+        let length = read_varint(&data[pos..pos + length_size], false);
+        pos += length_size;
+        let delta = read_varint(&data[pos..pos + offset_size], true) as i64;
+        pos += offset_size;
+
+        if length == 0 {
+            return invalid("Invalid Run length: 0");
+        }
+        let start = if offset_size == 0 {
+            None
+        } else {
+            let next = if delta >= 0 {
+                lcn.checked_add(delta as u64)
+            } else {
+                lcn.checked_sub(delta.wrapping_neg() as u64)
+            };
+            lcn = match next {
+                Some(lcn) => lcn,
+                None => return invalid(&format!("Invalid LCN delta: {}", delta)),
+            };
+            if lcn.checked_add(length).is_none_or(|end| end > cluster_count) {
+                return invalid(&format!("Run at LCN {} of {} clusters is outside the volume", lcn, length));
+            }
+            Some(lcn)
+        };
+
+        runs.push(Run { vcn: vcn, lcn: start, length: length });
+        vcn = match vcn.checked_add(length) {
+            Some(vcn) => vcn,
+            None => return invalid("Invalid Run length"),
+        };
+    }
+
+    // an empty attribute has a HighestVcn of -1
+    if vcn != highest_vcn.wrapping_add(1) {
+        return invalid(&format!("Mapping Pairs end at VCN {} instead of {}", vcn, highest_vcn.wrapping_add(1)));
+    }
+    Ok(runs)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x18 clusters at 0x5634, 0x30 at 0x5634 - 16, 0x10 sparse, then 8 at 0x5624 + 0x20
+    const PAIRS: &[u8] = &[0x21, 0x18, 0x34, 0x56, 0x11, 0x30, 0xF0, 0x01, 0x10, 0x11, 0x08, 0x20, 0x00];
+
+    #[test]
+    fn signed_deltas_and_sparse_runs() {
+        let runs = decode(PAIRS, 0, 0x5F, 0x10000).unwrap();
+        assert_eq!(runs, vec![
+            Run { vcn: 0x00, lcn: Some(0x5634), length: 0x18 },
+            Run { vcn: 0x18, lcn: Some(0x5624), length: 0x30 },
+            Run { vcn: 0x48, lcn: None, length: 0x10 },
+            Run { vcn: 0x58, lcn: Some(0x5644), length: 0x08 },
+        ]);
+    }
+
+    #[test]
+    fn invalid_pairs() {
+        // truncated pair, delta before the start of the volume, run past its end, short of HighestVcn
+        assert!(decode(&PAIRS[..3], 0, 0x17, 0x10000).is_err());
+        assert!(decode(&[0x11, 0x08, 0xF0, 0x00], 0, 7, 0x10000).is_err());
+        assert!(decode(PAIRS, 0, 0x5F, 0x5640).is_err());
+        assert!(decode(PAIRS, 0, 0x60, 0x10000).is_err());
+        assert_eq!(decode(&[0x00], 0, u64::MAX, 0x10000).unwrap(), Vec::new());
+    }
+}
@@ -0,0 +1,574 @@
+use core::fmt;
+use core::mem;
+use std::io;
+
+
+/// Name of the stream holding the security descriptors in `$Secure`.
+pub const SDS_STREAM: &str = "$SDS";
+/// Name of the index of security descriptors by security id.
+pub const SII_INDEX: &str = "$SII";
+/// Name of the index of security descriptors by hash.
+pub const SDH_INDEX: &str = "$SDH";
+
+const SE_DACL_PRESENT: u16 = 0x0004;
+const SE_SACL_PRESENT: u16 = 0x0010;
+const SE_DACL_AUTO_INHERIT_REQ: u16 = 0x0100;
+const SE_SACL_AUTO_INHERIT_REQ: u16 = 0x0200;
+const SE_DACL_AUTO_INHERITED: u16 = 0x0400;
+const SE_SACL_AUTO_INHERITED: u16 = 0x0800;
+const SE_DACL_PROTECTED: u16 = 0x1000;
+const SE_SACL_PROTECTED: u16 = 0x2000;
+const SE_SELF_RELATIVE: u16 = 0x8000;
+
+const ACCESS_ALLOWED_ACE_TYPE: u8 = 0x00;
+const ACCESS_DENIED_ACE_TYPE: u8 = 0x01;
+const SYSTEM_AUDIT_ACE_TYPE: u8 = 0x02;
+const SYSTEM_ALARM_ACE_TYPE: u8 = 0x03;
+const ACCESS_ALLOWED_OBJECT_ACE_TYPE: u8 = 0x05;
+const ACCESS_DENIED_OBJECT_ACE_TYPE: u8 = 0x06;
+const SYSTEM_AUDIT_OBJECT_ACE_TYPE: u8 = 0x07;
+const SYSTEM_ALARM_OBJECT_ACE_TYPE: u8 = 0x08;
+const ACCESS_ALLOWED_CALLBACK_ACE_TYPE: u8 = 0x09;
+const ACCESS_DENIED_CALLBACK_ACE_TYPE: u8 = 0x0A;
+const ACCESS_ALLOWED_CALLBACK_OBJECT_ACE_TYPE: u8 = 0x0B;
+const SYSTEM_AUDIT_CALLBACK_ACE_TYPE: u8 = 0x0D;
+const SYSTEM_MANDATORY_LABEL_ACE_TYPE: u8 = 0x11;
+const SYSTEM_RESOURCE_ATTRIBUTE_ACE_TYPE: u8 = 0x12;
+const SYSTEM_SCOPED_POLICY_ID_ACE_TYPE: u8 = 0x13;
+
+const ACE_OBJECT_TYPE_PRESENT: u32 = 0x1;
+const ACE_INHERITED_OBJECT_TYPE_PRESENT: u32 = 0x2;
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct SECURITY_DESCRIPTOR_HEADER {
+    pub Hash: u32,
+    pub SecurityId: u32,
+    pub Offset: u64,            // of this header in $SDS
+    pub Length: u32,            // including this header
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct SECURITY_DESCRIPTOR_RELATIVE {
+    Revision: u8,
+    Sbz1: u8,
+    Control: u16,               // SE_*
+    Owner: u32,                 // offsets from the start of the descriptor, 0 if absent
+    Group: u32,
+    Sacl: u32,
+    Dacl: u32,
+}
+
+// named after the Windows structure, like the other on-disk layouts
+#[allow(non_snake_case, clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct ACL {
+    AclRevision: u8,
+    Sbz1: u8,
+    AclSize: u16,
+    AceCount: u16,
+    Sbz2: u16,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct ACE_HEADER {
+    AceType: u8,                // *_ACE_TYPE
+    AceFlags: u8,
+    AceSize: u16,
+    Mask: u32,
+}
+
+/// Well known SIDs with an SDDL alias.
+const SID_ALIASES: &[(&str, &str)] = &[
+    ("S-1-1-0", "WD"),
+    ("S-1-3-0", "CO"),
+    ("S-1-3-1", "CG"),
+    ("S-1-3-4", "OW"),
+    ("S-1-5-2", "NU"),
+    ("S-1-5-4", "IU"),
+    ("S-1-5-6", "SU"),
+    ("S-1-5-7", "AN"),
+    ("S-1-5-9", "ED"),
+    ("S-1-5-10", "PS"),
+    ("S-1-5-11", "AU"),
+    ("S-1-5-12", "RC"),
+    ("S-1-5-18", "SY"),
+    ("S-1-5-19", "LS"),
+    ("S-1-5-20", "NS"),
+    ("S-1-5-32-544", "BA"),
+    ("S-1-5-32-545", "BU"),
+    ("S-1-5-32-546", "BG"),
+    ("S-1-5-32-547", "PU"),
+    ("S-1-5-32-548", "AO"),
+    ("S-1-5-32-549", "SO"),
+    ("S-1-5-32-550", "PO"),
+    ("S-1-5-32-551", "BO"),
+    ("S-1-5-32-552", "RE"),
+    ("S-1-5-32-554", "RU"),
+    ("S-1-5-32-555", "RD"),
+    ("S-1-5-32-556", "NO"),
+    ("S-1-5-33", "WR"),
+    ("S-1-15-2-1", "AC"),
+    ("S-1-16-4096", "LW"),
+    ("S-1-16-8192", "ME"),
+    ("S-1-16-8448", "MP"),
+    ("S-1-16-12288", "HI"),
+    ("S-1-16-16384", "SI"),
+];
+
+/// Access rights with an SDDL alias, whole masks before single bits.
+const RIGHTS_ALIASES: &[(u32, &str)] = &[
+    (0x001F01FF, "FA"),
+    (0x00120089, "FR"),
+    (0x00120116, "FW"),
+    (0x001200A0, "FX"),
+    (0x10000000, "GA"),
+    (0x80000000, "GR"),
+    (0x40000000, "GW"),
+    (0x20000000, "GX"),
+    (0x00020000, "RC"),
+    (0x00010000, "SD"),
+    (0x00040000, "WD"),
+    (0x00080000, "WO"),
+    (0x00000010, "RP"),
+    (0x00000020, "WP"),
+    (0x00000001, "CC"),
+    (0x00000002, "DC"),
+    (0x00000004, "LC"),
+    (0x00000008, "SW"),
+    (0x00000080, "LO"),
+    (0x00000040, "DT"),
+    (0x00000100, "CR"),
+];
+
+const LABEL_ALIASES: &[(u32, &str)] = &[
+    (0x00000001, "NW"),
+    (0x00000002, "NR"),
+    (0x00000004, "NX"),
+];
+
+const ACE_FLAG_ALIASES: &[(u8, &str)] = &[
+    (0x01, "OI"),
+    (0x02, "CI"),
+    (0x04, "NP"),
+    (0x08, "IO"),
+    (0x10, "ID"),
+    (0x40, "SA"),
+    (0x80, "FA"),
+];
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Hash a self-relative security descriptor, as used by the `$SDH` index.
+pub fn security_hash(data: &[u8]) -> u32 {
+    data.chunks_exact(4).fold(0u32, |hash, word| {
+        hash.rotate_left(3).wrapping_add(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+    })
+}
+
+/// Format a GUID as in the registry, eg. `bf967aba-0de6-11d0-a285-00aa003049e2`.
+fn format_guid(guid: &[u8; 16]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+        guid[8], guid[9], guid[10], guid[11], guid[12], guid[13], guid[14], guid[15]
+    )
+}
+
+
+/// A security identifier.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sid {
+    pub revision: u8,
+    pub authority: u64,         // 48 bits
+    pub sub_authorities: Vec<u32>,
+}
+
+impl Sid {
+
+    /// Parse a SID from the start of `data`, returning it and its length.
+    pub fn parse(data: &[u8]) -> io::Result<(Self, usize)> {
+        if data.len() < 8 {
+            return invalid(&format!("Invalid SID Length: {}", data.len()));
+        }
+        let count = data[1] as usize;
+        let length = 8 + count * 4;
+        if data.len() < length {
+            return invalid(&format!("Invalid SID Sub Authority Count: {}", count));
+        }
+        let authority = data[2..8].iter().fold(0, |n, &b| (n << 8) | b as u64);
+        let sub_authorities = data[8..length].chunks(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+        Ok((Self { revision: data[0], authority: authority, sub_authorities: sub_authorities }, length))
+    }
+
+    /// Format as SDDL, using the alias of a well known SID.
+    pub fn to_sddl(&self) -> String {
+        let sid = self.to_string();
+        match SID_ALIASES.iter().find(|(s, _)| *s == sid) {
+            Some((_, alias)) => alias.to_string(),
+            None => sid,
+        }
+    }
+}
+
+impl fmt::Display for Sid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.authority >> 32 == 0 {
+            write!(f, "S-{}-{}", self.revision, self.authority)?;
+        } else {
+            write!(f, "S-{}-{:#014X}", self.revision, self.authority)?;
+        }
+        for sub in &self.sub_authorities {
+            write!(f, "-{}", sub)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// An access control entry.
+#[derive(Clone, Debug)]
+pub struct Ace {
+    pub ace_type: u8,           // *_ACE_TYPE
+    pub flags: u8,
+    pub mask: u32,
+    pub object_type: Option<[u8; 16]>,
+    pub inherited_object_type: Option<[u8; 16]>,
+    pub sid: Sid,
+}
+
+impl Ace {
+
+    /// Parse an ACE from the start of `data`, returning it and its length.
+    fn parse(data: &[u8]) -> io::Result<(Self, usize)> {
+        if data.len() < mem::size_of::<ACE_HEADER>() {
+            return invalid("ACE is truncated");
+        }
+        let header: ACE_HEADER = unsafe{ *(data.as_ptr() as *const ACE_HEADER) };
+        let size = header.AceSize as usize;
+        if size < mem::size_of::<ACE_HEADER>() || size > data.len() {
+            return invalid(&format!("Invalid ACE Size: {}", size));
+        }
+        let data = &data[..size];
+        let mut pos = mem::size_of::<ACE_HEADER>();
+
+        let (mut object_type, mut inherited_object_type) = (None, None);
+        match header.AceType {
+            ACCESS_ALLOWED_OBJECT_ACE_TYPE | ACCESS_DENIED_OBJECT_ACE_TYPE | SYSTEM_AUDIT_OBJECT_ACE_TYPE |
+            SYSTEM_ALARM_OBJECT_ACE_TYPE | ACCESS_ALLOWED_CALLBACK_OBJECT_ACE_TYPE => {
+                if pos + 4 > size {
+                    return invalid("Object ACE is truncated");
+                }
+                let flags = u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+                pos += 4;
+                for (flag, guid) in [(ACE_OBJECT_TYPE_PRESENT, &mut object_type), (ACE_INHERITED_OBJECT_TYPE_PRESENT, &mut inherited_object_type)].iter_mut() {
+                    if flags & *flag != 0 {
+                        if pos + 16 > size {
+                            return invalid("Object ACE is truncated");
+                        }
+                        let mut value = [0; 16];
+                        value.copy_from_slice(&data[pos..pos + 16]);
+                        **guid = Some(value);
+                        pos += 16;
+                    }
+                }
+            }
+            _ => {}
+        }
+        // anything after the SID, eg. the condition of a callback ACE, is not decoded
+        let (sid, _) = Sid::parse(&data[pos..])?;
+
+        Ok((Self {
+            ace_type: header.AceType,
+            flags: header.AceFlags,
+            mask: header.Mask,
+            object_type: object_type,
+            inherited_object_type: inherited_object_type,
+            sid: sid,
+        }, size))
+    }
+
+    /// Format as SDDL, eg. `(A;OICI;FA;;;SY)`.
+    pub fn to_sddl(&self) -> String {
+        let ace_type = match self.ace_type {
+            ACCESS_ALLOWED_ACE_TYPE => "A".to_string(),
+            ACCESS_DENIED_ACE_TYPE => "D".to_string(),
+            SYSTEM_AUDIT_ACE_TYPE => "AU".to_string(),
+            SYSTEM_ALARM_ACE_TYPE => "AL".to_string(),
+            ACCESS_ALLOWED_OBJECT_ACE_TYPE => "OA".to_string(),
+            ACCESS_DENIED_OBJECT_ACE_TYPE => "OD".to_string(),
+            SYSTEM_AUDIT_OBJECT_ACE_TYPE => "OU".to_string(),
+            SYSTEM_ALARM_OBJECT_ACE_TYPE => "OL".to_string(),
+            ACCESS_ALLOWED_CALLBACK_ACE_TYPE => "XA".to_string(),
+            ACCESS_DENIED_CALLBACK_ACE_TYPE => "XD".to_string(),
+            ACCESS_ALLOWED_CALLBACK_OBJECT_ACE_TYPE => "ZA".to_string(),
+            SYSTEM_AUDIT_CALLBACK_ACE_TYPE => "XU".to_string(),
+            SYSTEM_MANDATORY_LABEL_ACE_TYPE => "ML".to_string(),
+            SYSTEM_RESOURCE_ATTRIBUTE_ACE_TYPE => "RA".to_string(),
+            SYSTEM_SCOPED_POLICY_ID_ACE_TYPE => "SP".to_string(),
+            other => format!("{:#x}", other),
+        };
+        let flags: String = ACE_FLAG_ALIASES.iter().filter(|(f, _)| self.flags & f != 0).map(|(_, a)| *a).collect();
+
+        // use aliases only when they describe every bit
+        let aliases = if self.ace_type == SYSTEM_MANDATORY_LABEL_ACE_TYPE { LABEL_ALIASES } else { RIGHTS_ALIASES };
+        let rights = match aliases.iter().find(|(m, _)| *m == self.mask) {
+            Some((_, alias)) => alias.to_string(),
+            None => {
+                let mut remaining = self.mask;
+                let mut rights = String::new();
+                for (mask, alias) in aliases.iter().filter(|(m, _)| m.count_ones() == 1) {
+                    if remaining & mask != 0 {
+                        remaining &= !mask;
+                        rights.push_str(alias);
+                    }
+                }
+                if remaining == 0 { rights } else { format!("{:#x}", self.mask) }
+            }
+        };
+
+        format!(
+            "({};{};{};{};{};{})",
+            ace_type, flags, rights,
+            self.object_type.as_ref().map(format_guid).unwrap_or_default(),
+            self.inherited_object_type.as_ref().map(format_guid).unwrap_or_default(),
+            self.sid.to_sddl()
+        )
+    }
+}
+
+
+/// An access control list.
+#[derive(Clone, Debug)]
+pub struct Acl {
+    pub revision: u8,
+    pub aces: Vec<Ace>,
+}
+
+impl Acl {
+
+    fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < mem::size_of::<ACL>() {
+            return invalid("ACL is truncated");
+        }
+        let header: ACL = unsafe{ *(data.as_ptr() as *const ACL) };
+        if (header.AclSize as usize) < mem::size_of::<ACL>() || header.AclSize as usize > data.len() {
+            return invalid(&format!("Invalid ACL Size: {}", { header.AclSize }));
+        }
+        let data = &data[..header.AclSize as usize];
+        let mut pos = mem::size_of::<ACL>();
+        let mut aces = Vec::new();
+        for _ in 0..header.AceCount {
+            let (ace, size) = Ace::parse(&data[pos..])?;
+            aces.push(ace);
+            pos += size;
+        }
+        Ok(Self { revision: header.AclRevision, aces: aces })
+    }
+}
+
+
+/// A parsed self-relative security descriptor.
+#[derive(Clone, Debug)]
+pub struct SecurityDescriptor {
+    pub control: u16,           // SE_*
+    pub owner: Option<Sid>,
+    pub group: Option<Sid>,
+    /// `None` if absent, `Some(None)` for a NULL ACL.
+    pub sacl: Option<Option<Acl>>,
+    pub dacl: Option<Option<Acl>>,
+}
+
+impl SecurityDescriptor {
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < mem::size_of::<SECURITY_DESCRIPTOR_RELATIVE>() {
+            return invalid(&format!("Invalid Security Descriptor Length: {}", data.len()));
+        }
+        let header: SECURITY_DESCRIPTOR_RELATIVE = unsafe{ *(data.as_ptr() as *const SECURITY_DESCRIPTOR_RELATIVE) };
+        debug!("{:#?}", header);
+        if header.Revision != 1 || header.Control & SE_SELF_RELATIVE == 0 {
+            return invalid(&format!("Unsupported Security Descriptor: revision {} control {:#x}", header.Revision, { header.Control }));
+        }
+
+        let at = |offset: u32| -> io::Result<Option<&[u8]>> {
+            match offset as usize {
+                0 => Ok(None),
+                offset if offset < data.len() => Ok(Some(&data[offset..])),
+                offset => invalid(&format!("Invalid Security Descriptor Offset: {}", offset)),
+            }
+        };
+        let acl = |present: bool, offset: u32| -> io::Result<Option<Option<Acl>>> {
+            if !present {
+                return Ok(None);
+            }
+            Ok(Some(at(offset)?.map(Acl::parse).transpose()?))
+        };
+
+        Ok(Self {
+            control: header.Control,
+            owner: at(header.Owner)?.map(Sid::parse).transpose()?.map(|(sid, _)| sid),
+            group: at(header.Group)?.map(Sid::parse).transpose()?.map(|(sid, _)| sid),
+            sacl: acl(header.Control & SE_SACL_PRESENT != 0, header.Sacl)?,
+            dacl: acl(header.Control & SE_DACL_PRESENT != 0, header.Dacl)?,
+        })
+    }
+
+    /// Format as a Security Descriptor Definition Language string.
+    pub fn to_sddl(&self) -> String {
+        let mut sddl = String::new();
+        if let Some(owner) = &self.owner {
+            sddl.push_str(&format!("O:{}", owner.to_sddl()));
+        }
+        if let Some(group) = &self.group {
+            sddl.push_str(&format!("G:{}", group.to_sddl()));
+        }
+        let flags = [
+            (SE_DACL_PROTECTED, SE_DACL_AUTO_INHERIT_REQ, SE_DACL_AUTO_INHERITED),
+            (SE_SACL_PROTECTED, SE_SACL_AUTO_INHERIT_REQ, SE_SACL_AUTO_INHERITED),
+        ];
+        for ((prefix, acl), (protected, request, inherited)) in [("D", &self.dacl), ("S", &self.sacl)].iter().zip(flags.iter()) {
+            let acl = match acl {
+                Some(acl) => acl,
+                None => continue,
+            };
+            sddl.push_str(prefix);
+            sddl.push(':');
+            if self.control & protected != 0 {
+                sddl.push('P');
+            }
+            if self.control & request != 0 {
+                sddl.push_str("AR");
+            }
+            if self.control & inherited != 0 {
+                sddl.push_str("AI");
+            }
+            match acl {
+                Some(acl) => acl.aces.iter().for_each(|ace| sddl.push_str(&ace.to_sddl())),
+                None => sddl.push_str("NO_ACCESS_CONTROL"),
+            }
+        }
+        sddl
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::super::utils::hex_decode;
+
+    /// A SID from its authority followed by its sub authorities.
+    fn sid(parts: &[u32]) -> Vec<u8> {
+        let mut data = vec![1, parts.len() as u8 - 1, 0, 0, 0, 0, 0, parts[0] as u8];
+        for sub in &parts[1..] {
+            data.extend_from_slice(&sub.to_le_bytes());
+        }
+        data
+    }
+
+    fn ace(ace_type: u8, flags: u8, mask: u32, object: &[u8], sid: &[u8]) -> Vec<u8> {
+        let size = 8 + object.len() + sid.len();
+        let mut data = vec![ace_type, flags];
+        data.extend_from_slice(&(size as u16).to_le_bytes());
+        data.extend_from_slice(&mask.to_le_bytes());
+        data.extend_from_slice(object);
+        data.extend_from_slice(sid);
+        data
+    }
+
+    fn acl(aces: &[Vec<u8>]) -> Vec<u8> {
+        let size = 8 + aces.iter().map(Vec::len).sum::<usize>();
+        let mut data = vec![2, 0];
+        data.extend_from_slice(&(size as u16).to_le_bytes());
+        data.extend_from_slice(&(aces.len() as u16).to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        aces.iter().for_each(|ace| data.extend_from_slice(ace));
+        data
+    }
+
+    /// A self-relative descriptor with its parts in order, any of which may be empty.
+    fn descriptor(control: u16, owner: &[u8], group: &[u8], sacl: &[u8], dacl: &[u8]) -> Vec<u8> {
+        let mut data = vec![1, 0];
+        data.extend_from_slice(&(control | SE_SELF_RELATIVE).to_le_bytes());
+        let mut parts = Vec::new();
+        for part in [owner, group, sacl, dacl].iter() {
+            let offset = if part.is_empty() { 0 } else { 20 + parts.len() as u32 };
+            data.extend_from_slice(&offset.to_le_bytes());
+            parts.extend_from_slice(part);
+        }
+        data.extend_from_slice(&parts);
+        data
+    }
+
+    #[test]
+    fn sids() {
+        let (system, length) = Sid::parse(&sid(&[5, 18])).unwrap();
+        assert_eq!((system.to_string(), system.to_sddl(), length), (String::from("S-1-5-18"), String::from("SY"), 12));
+        let user = Sid::parse(&sid(&[5, 21, 1, 2, 3, 1001])).unwrap().0;
+        assert_eq!(user.to_sddl(), "S-1-5-21-1-2-3-1001");
+
+        let (large, _) = Sid::parse(&hex_decode("0100 010000000000")).unwrap();
+        assert_eq!(large.to_string(), "S-1-0x010000000000");
+
+        assert!(Sid::parse(&sid(&[5, 32])[..7]).is_err());
+        assert!(Sid::parse(&sid(&[5, 32, 544])[..15]).is_err());
+    }
+
+    #[test]
+    fn sddl() {
+        let guid = hex_decode("ba7a96bfe60dd011a28500aa003049e2");
+        let mut object = ACE_OBJECT_TYPE_PRESENT.to_le_bytes().to_vec();
+        object.extend_from_slice(&guid);
+        let dacl = acl(&[
+            ace(ACCESS_ALLOWED_ACE_TYPE, 0x03, 0x001F01FF, &[], &sid(&[5, 18])),
+            ace(ACCESS_ALLOWED_ACE_TYPE, 0x10, 0x001200A9, &[], &sid(&[5, 21, 1, 2, 3, 1001])),
+            ace(ACCESS_DENIED_ACE_TYPE, 0, 0x000C0000, &[], &sid(&[1, 0])),
+            ace(ACCESS_ALLOWED_OBJECT_ACE_TYPE, 0, 0x00000100, &object, &sid(&[5, 11])),
+        ]);
+        let sacl = acl(&[ace(SYSTEM_MANDATORY_LABEL_ACE_TYPE, 0, 0x00000001, &[], &sid(&[16, 12288]))]);
+        let control = SE_DACL_PRESENT | SE_DACL_PROTECTED | SE_DACL_AUTO_INHERITED | SE_SACL_PRESENT;
+        let data = descriptor(control, &sid(&[5, 32, 544]), &sid(&[5, 18]), &sacl, &dacl);
+
+        let descriptor = SecurityDescriptor::parse(&data).unwrap();
+        assert_eq!(descriptor.to_sddl(), concat!(
+            "O:BAG:SYD:PAI(A;OICI;FA;;;SY)(A;ID;0x1200a9;;;S-1-5-21-1-2-3-1001)(D;;WDWO;;;WD)",
+            "(OA;;CR;bf967aba-0de6-11d0-a285-00aa003049e2;;AU)S:(ML;;NW;;;HI)",
+        ));
+    }
+
+    #[test]
+    fn null_and_invalid() {
+        let null = descriptor(SE_DACL_PRESENT, &sid(&[5, 18]), &[], &[], &[]);
+        assert_eq!(SecurityDescriptor::parse(&null).unwrap().to_sddl(), "O:SYD:NO_ACCESS_CONTROL");
+
+        let mut absolute = null.clone();
+        absolute[3] = 0;
+        assert!(SecurityDescriptor::parse(&absolute).is_err());
+        let mut outside = null.clone();
+        outside[4] = 32;
+        assert!(SecurityDescriptor::parse(&outside).is_err());
+
+        // an ACL larger than the descriptor, and an ACE larger than its ACL
+        let mut dacl = acl(&[ace(ACCESS_ALLOWED_ACE_TYPE, 0, 1, &[], &sid(&[5, 18]))]);
+        dacl[2] += 1;
+        assert!(SecurityDescriptor::parse(&descriptor(SE_DACL_PRESENT, &[], &[], &[], &dacl)).is_err());
+        dacl[2] -= 1;
+        dacl[10] += 4;
+        assert!(SecurityDescriptor::parse(&descriptor(SE_DACL_PRESENT, &[], &[], &[], &dacl)).is_err());
+
+        assert_eq!(security_hash(&hex_decode("01000000 02000000 ff")), 10);
+    }
+}
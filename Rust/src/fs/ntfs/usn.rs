@@ -0,0 +1,464 @@
+use core::cmp;
+use core::mem;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::reader::AttributeReader;
+use super::record::{Attribute, AttributeValue, FileReference};
+
+
+/// Name of the stream holding the change journal records in `$UsnJrnl`.
+pub const USN_JOURNAL_STREAM: &str = "$J";
+/// Name of the stream holding the change journal parameters in `$UsnJrnl`.
+pub const USN_JOURNAL_MAX_STREAM: &str = "$Max";
+
+/// Records are padded so none crosses a page.
+const USN_PAGE_SIZE: u64 = 0x1000;
+
+pub const USN_REASON_DATA_OVERWRITE: u32 = 0x00000001;
+pub const USN_REASON_DATA_EXTEND: u32 = 0x00000002;
+pub const USN_REASON_DATA_TRUNCATION: u32 = 0x00000004;
+pub const USN_REASON_NAMED_DATA_OVERWRITE: u32 = 0x00000010;
+pub const USN_REASON_NAMED_DATA_EXTEND: u32 = 0x00000020;
+pub const USN_REASON_NAMED_DATA_TRUNCATION: u32 = 0x00000040;
+pub const USN_REASON_FILE_CREATE: u32 = 0x00000100;
+pub const USN_REASON_FILE_DELETE: u32 = 0x00000200;
+pub const USN_REASON_EA_CHANGE: u32 = 0x00000400;
+pub const USN_REASON_SECURITY_CHANGE: u32 = 0x00000800;
+pub const USN_REASON_RENAME_OLD_NAME: u32 = 0x00001000;
+pub const USN_REASON_RENAME_NEW_NAME: u32 = 0x00002000;
+pub const USN_REASON_INDEXABLE_CHANGE: u32 = 0x00004000;
+pub const USN_REASON_BASIC_INFO_CHANGE: u32 = 0x00008000;
+pub const USN_REASON_HARD_LINK_CHANGE: u32 = 0x00010000;
+pub const USN_REASON_COMPRESSION_CHANGE: u32 = 0x00020000;
+pub const USN_REASON_ENCRYPTION_CHANGE: u32 = 0x00040000;
+pub const USN_REASON_OBJECT_ID_CHANGE: u32 = 0x00080000;
+pub const USN_REASON_REPARSE_POINT_CHANGE: u32 = 0x00100000;
+pub const USN_REASON_STREAM_CHANGE: u32 = 0x00200000;
+pub const USN_REASON_TRANSACTED_CHANGE: u32 = 0x00400000;
+pub const USN_REASON_INTEGRITY_CHANGE: u32 = 0x00800000;
+pub const USN_REASON_DESIRED_STORAGE_CLASS_CHANGE: u32 = 0x01000000;
+pub const USN_REASON_CLOSE: u32 = 0x80000000;
+
+pub const USN_SOURCE_DATA_MANAGEMENT: u32 = 0x00000001;
+pub const USN_SOURCE_AUXILIARY_DATA: u32 = 0x00000002;
+pub const USN_SOURCE_REPLICATION_MANAGEMENT: u32 = 0x00000004;
+pub const USN_SOURCE_CLIENT_REPLICATION_MANAGEMENT: u32 = 0x00000008;
+
+const REASONS: [(u32, &str); 24] = [
+    (USN_REASON_DATA_OVERWRITE, "DATA_OVERWRITE"),
+    (USN_REASON_DATA_EXTEND, "DATA_EXTEND"),
+    (USN_REASON_DATA_TRUNCATION, "DATA_TRUNCATION"),
+    (USN_REASON_NAMED_DATA_OVERWRITE, "NAMED_DATA_OVERWRITE"),
+    (USN_REASON_NAMED_DATA_EXTEND, "NAMED_DATA_EXTEND"),
+    (USN_REASON_NAMED_DATA_TRUNCATION, "NAMED_DATA_TRUNCATION"),
+    (USN_REASON_FILE_CREATE, "FILE_CREATE"),
+    (USN_REASON_FILE_DELETE, "FILE_DELETE"),
+    (USN_REASON_EA_CHANGE, "EA_CHANGE"),
+    (USN_REASON_SECURITY_CHANGE, "SECURITY_CHANGE"),
+    (USN_REASON_RENAME_OLD_NAME, "RENAME_OLD_NAME"),
+    (USN_REASON_RENAME_NEW_NAME, "RENAME_NEW_NAME"),
+    (USN_REASON_INDEXABLE_CHANGE, "INDEXABLE_CHANGE"),
+    (USN_REASON_BASIC_INFO_CHANGE, "BASIC_INFO_CHANGE"),
+    (USN_REASON_HARD_LINK_CHANGE, "HARD_LINK_CHANGE"),
+    (USN_REASON_COMPRESSION_CHANGE, "COMPRESSION_CHANGE"),
+    (USN_REASON_ENCRYPTION_CHANGE, "ENCRYPTION_CHANGE"),
+    (USN_REASON_OBJECT_ID_CHANGE, "OBJECT_ID_CHANGE"),
+    (USN_REASON_REPARSE_POINT_CHANGE, "REPARSE_POINT_CHANGE"),
+    (USN_REASON_STREAM_CHANGE, "STREAM_CHANGE"),
+    (USN_REASON_TRANSACTED_CHANGE, "TRANSACTED_CHANGE"),
+    (USN_REASON_INTEGRITY_CHANGE, "INTEGRITY_CHANGE"),
+    (USN_REASON_DESIRED_STORAGE_CLASS_CHANGE, "DESIRED_STORAGE_CLASS_CHANGE"),
+    (USN_REASON_CLOSE, "CLOSE"),
+];
+
+const SOURCES: [(u32, &str); 4] = [
+    (USN_SOURCE_DATA_MANAGEMENT, "DATA_MANAGEMENT"),
+    (USN_SOURCE_AUXILIARY_DATA, "AUXILIARY_DATA"),
+    (USN_SOURCE_REPLICATION_MANAGEMENT, "REPLICATION_MANAGEMENT"),
+    (USN_SOURCE_CLIENT_REPLICATION_MANAGEMENT, "CLIENT_REPLICATION_MANAGEMENT"),
+];
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct USN_JOURNAL_DATA {
+    MaximumSize: u64,
+    AllocationDelta: u64,
+    UsnJournalID: u64,
+    LowestValidUsn: u64,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct USN_RECORD_COMMON_HEADER {
+    RecordLength: u32,          // including the name or extents, 8 byte aligned
+    MajorVersion: u16,
+    MinorVersion: u16,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct USN_RECORD_V2 {
+    Header: USN_RECORD_COMMON_HEADER,
+    FileReferenceNumber: u64,
+    ParentFileReferenceNumber: u64,
+    Usn: u64,
+    TimeStamp: u64,
+    Reason: u32,                // USN_REASON_*
+    SourceInfo: u32,            // USN_SOURCE_*
+    SecurityId: u32,
+    FileAttributes: u32,
+    FileNameLength: u16,        // in bytes
+    FileNameOffset: u16,
+    // FileName: [u16],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct USN_RECORD_V3 {
+    Header: USN_RECORD_COMMON_HEADER,
+    FileReferenceNumber: [u8; 16],  // FILE_ID_128, the MFT reference in the low half on NTFS
+    ParentFileReferenceNumber: [u8; 16],
+    Usn: u64,
+    TimeStamp: u64,
+    Reason: u32,
+    SourceInfo: u32,
+    SecurityId: u32,
+    FileAttributes: u32,
+    FileNameLength: u16,
+    FileNameOffset: u16,
+    // FileName: [u16],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct USN_RECORD_V4 {
+    Header: USN_RECORD_COMMON_HEADER,
+    FileReferenceNumber: [u8; 16],
+    ParentFileReferenceNumber: [u8; 16],
+    Usn: u64,
+    Reason: u32,
+    SourceInfo: u32,
+    RemainingExtents: u32,
+    NumberOfExtents: u16,
+    ExtentSize: u16,
+    // Extents: [USN_RECORD_EXTENT; NumberOfExtents],
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct USN_RECORD_EXTENT {
+    Offset: u64,
+    Length: u64,
+}
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Get the MFT reference from the low half of a 128 bit file id.
+fn file_id_128(id: [u8; 16]) -> FileReference {
+    let mut low = [0; 8];
+    low.copy_from_slice(&id[..8]);
+    FileReference::from_u64(u64::from_le_bytes(low))
+}
+
+
+/// The parameters of the change journal, from its `$Max` stream.
+#[derive(Clone, Copy, Debug)]
+pub struct UsnJournal {
+    pub maximum_size: u64,
+    pub allocation_delta: u64,
+    /// Identifies this instance of the journal, it changes each time the journal is recreated.
+    pub journal_id: u64,
+    /// Records before this have been discarded.
+    pub lowest_valid_usn: u64,
+}
+
+impl UsnJournal {
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < mem::size_of::<USN_JOURNAL_DATA>() {
+            return invalid(&format!("Invalid USN Journal Data Length: {}", data.len()));
+        }
+        let info: USN_JOURNAL_DATA = unsafe{ *(data.as_ptr() as *const USN_JOURNAL_DATA) };
+        debug!("{:#?}", info);
+        Ok(Self {
+            maximum_size: info.MaximumSize,
+            allocation_delta: info.AllocationDelta,
+            journal_id: info.UsnJournalID,
+            lowest_valid_usn: info.LowestValidUsn,
+        })
+    }
+}
+
+
+/// A range of a file modified, from a version 4 record.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UsnExtent {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// A record from the change journal.
+///
+/// Version 2 and 3 records name the file, version 4 records list the ranges that were modified
+/// instead.
+#[derive(Clone, Debug)]
+pub struct UsnRecord {
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub file_reference: FileReference,
+    pub parent_reference: FileReference,
+    /// The offset of this record in the journal.
+    pub usn: u64,
+    pub timestamp: Option<u64>,
+    pub reason: u32,            // USN_REASON_*
+    pub source_info: u32,       // USN_SOURCE_*
+    pub security_id: Option<u32>,
+    pub file_attributes: Option<u32>,   // PERMISSION_FLAG_*
+    pub name: Option<String>,
+    pub extents: Vec<UsnExtent>,
+    /// The number of extents in the records that follow for the same change.
+    pub remaining_extents: u32,
+}
+
+impl UsnRecord {
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < mem::size_of::<USN_RECORD_COMMON_HEADER>() {
+            return invalid(&format!("Invalid USN Record Length: {}", data.len()));
+        }
+        let header: USN_RECORD_COMMON_HEADER = unsafe{ *(data.as_ptr() as *const USN_RECORD_COMMON_HEADER) };
+        let length = header.RecordLength as usize;
+        if length > data.len() {
+            return invalid(&format!("Invalid USN Record Length: {}", length));
+        }
+        let data = &data[..length];
+
+        match header.MajorVersion {
+            2 => {
+                if length < mem::size_of::<USN_RECORD_V2>() {
+                    return invalid(&format!("Invalid USN Record Length: {}", length));
+                }
+                let record: USN_RECORD_V2 = unsafe{ *(data.as_ptr() as *const USN_RECORD_V2) };
+                Ok(Self {
+                    major_version: 2,
+                    minor_version: header.MinorVersion,
+                    file_reference: FileReference::from_u64(record.FileReferenceNumber),
+                    parent_reference: FileReference::from_u64(record.ParentFileReferenceNumber),
+                    usn: record.Usn,
+                    timestamp: Some(record.TimeStamp),
+                    reason: record.Reason,
+                    source_info: record.SourceInfo,
+                    security_id: Some(record.SecurityId),
+                    file_attributes: Some(record.FileAttributes),
+                    name: Some(utf16(data, record.FileNameOffset, record.FileNameLength)?),
+                    extents: Vec::new(),
+                    remaining_extents: 0,
+                })
+            }
+            3 => {
+                if length < mem::size_of::<USN_RECORD_V3>() {
+                    return invalid(&format!("Invalid USN Record Length: {}", length));
+                }
+                let record: USN_RECORD_V3 = unsafe{ *(data.as_ptr() as *const USN_RECORD_V3) };
+                Ok(Self {
+                    major_version: 3,
+                    minor_version: header.MinorVersion,
+                    file_reference: file_id_128(record.FileReferenceNumber),
+                    parent_reference: file_id_128(record.ParentFileReferenceNumber),
+                    usn: record.Usn,
+                    timestamp: Some(record.TimeStamp),
+                    reason: record.Reason,
+                    source_info: record.SourceInfo,
+                    security_id: Some(record.SecurityId),
+                    file_attributes: Some(record.FileAttributes),
+                    name: Some(utf16(data, record.FileNameOffset, record.FileNameLength)?),
+                    extents: Vec::new(),
+                    remaining_extents: 0,
+                })
+            }
+            4 => {
+                let size = mem::size_of::<USN_RECORD_V4>();
+                if length < size {
+                    return invalid(&format!("Invalid USN Record Length: {}", length));
+                }
+                let record: USN_RECORD_V4 = unsafe{ *(data.as_ptr() as *const USN_RECORD_V4) };
+                let extent_size = record.ExtentSize as usize;
+                let count = record.NumberOfExtents as usize;
+                if extent_size < mem::size_of::<USN_RECORD_EXTENT>() || size + count * extent_size > length {
+                    return invalid(&format!("Invalid USN Record Extents: {} x {}", count, extent_size));
+                }
+                let extents = data[size..size + count * extent_size].chunks(extent_size).map(|c| {
+                    let extent: USN_RECORD_EXTENT = unsafe{ *(c.as_ptr() as *const USN_RECORD_EXTENT) };
+                    UsnExtent { offset: extent.Offset, length: extent.Length }
+                }).collect();
+                Ok(Self {
+                    major_version: 4,
+                    minor_version: header.MinorVersion,
+                    file_reference: file_id_128(record.FileReferenceNumber),
+                    parent_reference: file_id_128(record.ParentFileReferenceNumber),
+                    usn: record.Usn,
+                    timestamp: None,
+                    reason: record.Reason,
+                    source_info: record.SourceInfo,
+                    security_id: None,
+                    file_attributes: None,
+                    name: None,
+                    extents: extents,
+                    remaining_extents: record.RemainingExtents,
+                })
+            }
+            version => invalid(&format!("Unsupported USN Record Version: {}.{}", version, { header.MinorVersion })),
+        }
+    }
+
+    /// Get the names of the reason flags, eg. `FILE_CREATE`.
+    pub fn reasons(&self) -> Vec<&'static str> {
+        REASONS.iter().filter(|(flag, _)| self.reason & flag != 0).map(|(_, name)| *name).collect()
+    }
+
+    /// Get the names of the source flags, eg. `DATA_MANAGEMENT`.
+    pub fn sources(&self) -> Vec<&'static str> {
+        SOURCES.iter().filter(|(flag, _)| self.source_info & flag != 0).map(|(_, name)| *name).collect()
+    }
+}
+
+/// Get a UTF-16 string at a byte offset and length within a record.
+fn utf16(data: &[u8], offset: u16, length: u16) -> io::Result<String> {
+    let start = offset as usize;
+    let end = start + length as usize;
+    if end > data.len() || !length.is_multiple_of(2) {
+        return invalid(&format!("Invalid USN Record Name: {} + {}", offset, length));
+    }
+    let units: Vec<u16> = data[start..end].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    Ok(String::from_utf16_lossy(&units))
+}
+
+
+/// An iterator over the records of the change journal.
+///
+/// Only the allocated parts of the sparse `$J` stream are read.  A page that can not be parsed
+/// is returned as an error and the rest of it skipped.
+pub struct UsnRecords<'a, R> {
+    reader: AttributeReader<'a, R>,
+    ranges: Vec<(u64, u64)>,    // allocated byte ranges of the stream
+    range: usize,
+    page: Vec<u8>,
+    page_offset: u64,
+    pos: usize,
+}
+
+impl<'a, R> UsnRecords<'a, R> {
+
+    /// Creates a new `UsnRecords` for the `$J` attribute opened by `reader`.
+    pub fn new(reader: AttributeReader<'a, R>, attribute: &Attribute, cluster_size: u64) -> Self {
+        let size = reader.size();
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        match attribute.value() {
+            AttributeValue::Resident(_) => ranges.push((0, size)),
+            AttributeValue::NonResident { runs, .. } => {
+                for run in runs.iter().filter(|r| r.lcn.is_some()) {
+                    let start = cmp::min(run.vcn * cluster_size, size) / USN_PAGE_SIZE * USN_PAGE_SIZE;
+                    let end = cmp::min((run.vcn + run.length) * cluster_size, size);
+                    match ranges.last_mut() {
+                        Some(last) if last.1 >= start => last.1 = cmp::max(last.1, end),
+                        _ if start < end => ranges.push((start, end)),
+                        _ => (),
+                    }
+                }
+            }
+        }
+        debug!("USN Journal allocated ranges: {:x?}", ranges);
+        let page_offset = ranges.first().map_or(0, |r| r.0);
+        Self {
+            reader: reader,
+            ranges: ranges,
+            range: 0,
+            page: Vec::new(),
+            page_offset: page_offset,
+            pos: 0,
+        }
+    }
+}
+
+impl<'a, R> UsnRecords<'a, R>
+where R: Read + Seek {
+
+    /// Read the next page, returning false at the end of the journal.
+    fn next_page(&mut self) -> io::Result<bool> {
+        if !self.page.is_empty() {
+            self.page_offset += self.page.len() as u64;
+        }
+        while let Some(&(start, end)) = self.ranges.get(self.range) {
+            if self.page_offset < start {
+                self.page_offset = start;
+            }
+            if self.page_offset < end {
+                let length = cmp::min(USN_PAGE_SIZE, end - self.page_offset) as usize;
+                self.page.resize(length, 0);
+                self.reader.seek(SeekFrom::Start(self.page_offset))?;
+                self.reader.read_exact(&mut self.page)?;
+                self.pos = 0;
+                return Ok(true);
+            }
+            self.range += 1;
+        }
+        self.page.clear();
+        Ok(false)
+    }
+}
+
+impl<'a, R> Iterator for UsnRecords<'a, R>
+where R: Read + Seek {
+    type Item = io::Result<UsnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.pos + mem::size_of::<USN_RECORD_COMMON_HEADER>() > self.page.len() {
+                match self.next_page() {
+                    Ok(true) => (),
+                    Ok(false) => return None,
+                    Err(err) => {
+                        eprintln!("ERROR: Failed to read USN Journal at {}: {}", self.page_offset, err);
+                        self.range = self.ranges.len();
+                        return Some(Err(err));
+                    }
+                }
+                continue;
+            }
+
+            // the rest of the page is padding
+            let start = self.pos;
+            let data = &self.page[start..];
+            let length = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+            if length == 0 {
+                self.pos = self.page.len();
+                continue;
+            }
+
+            let usn = self.page_offset + start as u64;
+            if !length.is_multiple_of(8) || length > self.page.len() - start {
+                self.pos = self.page.len();
+                return Some(invalid(&format!("Invalid USN Record Length at {}: {}", usn, length)));
+            }
+            self.pos += length;
+            let result = UsnRecord::parse(&self.page[start..start + length]);
+            if let Ok(record) = &result {
+                if record.usn != usn {
+                    debug!("USN Record at {} has USN {}", usn, record.usn);
+                }
+            }
+            return Some(result);
+        }
+    }
+}
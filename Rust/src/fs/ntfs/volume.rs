@@ -0,0 +1,150 @@
+use core::mem;
+use std::io;
+
+
+pub const VOLUME_IS_DIRTY: u16 = 0x0001;
+pub const VOLUME_RESIZE_LOG_FILE: u16 = 0x0002;
+pub const VOLUME_UPGRADE_ON_MOUNT: u16 = 0x0004;
+pub const VOLUME_MOUNTED_ON_NT4: u16 = 0x0008;
+pub const VOLUME_DELETE_USN_UNDERWAY: u16 = 0x0010;
+pub const VOLUME_REPAIR_OBJECT_ID: u16 = 0x0020;
+pub const VOLUME_CHKDSK_UNDERWAY: u16 = 0x4000;
+pub const VOLUME_MODIFIED_BY_CHKDSK: u16 = 0x8000;
+
+const FLAGS: [(u16, &str); 8] = [
+    (VOLUME_IS_DIRTY, "IS_DIRTY"),
+    (VOLUME_RESIZE_LOG_FILE, "RESIZE_LOG_FILE"),
+    (VOLUME_UPGRADE_ON_MOUNT, "UPGRADE_ON_MOUNT"),
+    (VOLUME_MOUNTED_ON_NT4, "MOUNTED_ON_NT4"),
+    (VOLUME_DELETE_USN_UNDERWAY, "DELETE_USN_UNDERWAY"),
+    (VOLUME_REPAIR_OBJECT_ID, "REPAIR_OBJECT_ID"),
+    (VOLUME_CHKDSK_UNDERWAY, "CHKDSK_UNDERWAY"),
+    (VOLUME_MODIFIED_BY_CHKDSK, "MODIFIED_BY_CHKDSK"),
+];
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct VOLUME_INFORMATION {
+    Reserved: u64,
+    MajorVersion: u8,
+    MinorVersion: u8,
+    Flags: u16,                 // VOLUME_*
+}
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+
+/// Facts about a volume from `$Volume` and the boot sector.
+#[derive(Clone, Debug)]
+pub struct VolumeInfo {
+    /// The label, empty if there is none.
+    pub label: String,
+    /// The NTFS version, eg. 3.1 since Windows XP.
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub flags: u16,             // VOLUME_*
+    /// The serial number, of which Windows shows the low 32 bits.
+    pub serial_number: u64,
+    /// The number of sectors, not counting the backup boot sector at the end.
+    pub total_sectors: u64,
+    pub sector_size: u64,
+    pub cluster_size: u64,
+}
+
+impl VolumeInfo {
+
+    /// Parse the `$VOLUME_NAME` and `$VOLUME_INFORMATION` of `$Volume`.
+    ///
+    /// The facts from the boot sector are left zero.
+    pub fn parse(name: Option<&[u8]>, information: &[u8]) -> io::Result<Self> {
+        if information.len() < mem::size_of::<VOLUME_INFORMATION>() {
+            return invalid(&format!("Invalid Volume Information Length: {}", information.len()));
+        }
+        let info: VOLUME_INFORMATION = unsafe{ *(information.as_ptr() as *const VOLUME_INFORMATION) };
+        debug!("{:#?}", info);
+
+        let label = match name {
+            Some(name) if name.len() % 2 == 0 => {
+                let units: Vec<u16> = name.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+                String::from_utf16_lossy(&units)
+            }
+            Some(name) => return invalid(&format!("Invalid Volume Name Length: {}", name.len())),
+            None => String::new(),
+        };
+
+        Ok(Self {
+            label: label,
+            major_version: info.MajorVersion,
+            minor_version: info.MinorVersion,
+            flags: info.Flags,
+            serial_number: 0,
+            total_sectors: 0,
+            sector_size: 0,
+            cluster_size: 0,
+        })
+    }
+
+    /// Was the volume not unmounted cleanly, so should be checked?
+    pub fn is_dirty(&self) -> bool {
+        self.flags & VOLUME_IS_DIRTY != 0
+    }
+
+    /// Will the volume be upgraded to the current NTFS version when next mounted?
+    pub fn upgrade_on_mount(&self) -> bool {
+        self.flags & VOLUME_UPGRADE_ON_MOUNT != 0
+    }
+
+    /// Will `$LogFile` be resized when next mounted?
+    pub fn resize_log_file(&self) -> bool {
+        self.flags & VOLUME_RESIZE_LOG_FILE != 0
+    }
+
+    /// Get the names of the flags, eg. `IS_DIRTY`.
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        FLAGS.iter().filter(|(flag, _)| self.flags & flag != 0).map(|(_, name)| *name).collect()
+    }
+
+    /// Get the serial number as Windows shows it, eg. `1234-ABCD`.
+    pub fn short_serial_number(&self) -> String {
+        format!("{:04X}-{:04X}", (self.serial_number >> 16) & 0xFFFF, self.serial_number & 0xFFFF)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn information(major_version: u8, minor_version: u8, flags: u16) -> Vec<u8> {
+        let mut data = vec![0u8; 12];
+        data[8] = major_version;
+        data[9] = minor_version;
+        data[10..12].copy_from_slice(&flags.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn parse() {
+        let name: Vec<u8> = "Données".encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+        let mut info = VolumeInfo::parse(Some(&name), &information(3, 1, 0x8003)).unwrap();
+        assert_eq!(info.label, "Données");
+        assert_eq!((info.major_version, info.minor_version), (3, 1));
+        assert!(info.is_dirty() && info.resize_log_file() && !info.upgrade_on_mount());
+        assert_eq!(info.flag_names(), vec!["IS_DIRTY", "RESIZE_LOG_FILE", "MODIFIED_BY_CHKDSK"]);
+        info.serial_number = 0x0123_4567_89AB_CDEF;
+        assert_eq!(info.short_serial_number(), "89AB-CDEF");
+
+        let info = VolumeInfo::parse(None, &information(1, 2, 0)).unwrap();
+        assert_eq!(info.label, "");
+        assert!(info.flag_names().is_empty() && !info.is_dirty());
+        assert_eq!(info.short_serial_number(), "0000-0000");
+
+        assert!(VolumeInfo::parse(Some(&name[..3]), &information(3, 1, 0)).is_err());
+        assert!(VolumeInfo::parse(None, &information(3, 1, 0)[..11]).is_err());
+    }
+}
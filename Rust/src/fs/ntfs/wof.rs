@@ -0,0 +1,242 @@
+use core::cmp;
+use core::mem;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::super::super::compress::{lzx_decompress, xpress_decompress};
+use super::super::super::utils::iadd;
+
+
+/// Name of the stream holding the compressed data of a file.
+pub const WOF_STREAM: &str = "WofCompressedData";
+
+const WOF_CURRENT_VERSION: u32 = 1;
+const WOF_PROVIDER_FILE: u32 = 2;
+const FILE_PROVIDER_CURRENT_VERSION: u32 = 1;
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct WOF_EXTERNAL_INFO {
+    Version: u32,
+    Provider: u32,              // WOF_PROVIDER_*
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+struct FILE_PROVIDER_EXTERNAL_INFO_V1 {
+    Version: u32,
+    Algorithm: u32,             // FILE_PROVIDER_COMPRESSION_*
+    Flags: u32,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FILE_PROVIDER_COMPRESSION {
+    XPRESS4K = 0,
+    LZX = 1,
+    XPRESS8K = 2,
+    XPRESS16K = 3,
+}
+
+impl FILE_PROVIDER_COMPRESSION {
+
+    /// Parse the data of an `IO_REPARSE_TAG_WOF` reparse point.
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < mem::size_of::<WOF_EXTERNAL_INFO>() + mem::size_of::<FILE_PROVIDER_EXTERNAL_INFO_V1>() {
+            return invalid(&format!("Invalid WOF Reparse Data Length: {}", data.len()));
+        }
+        let wof: WOF_EXTERNAL_INFO = unsafe{ *(data.as_ptr() as *const WOF_EXTERNAL_INFO) };
+        let info: FILE_PROVIDER_EXTERNAL_INFO_V1 = unsafe{
+            *(data[mem::size_of::<WOF_EXTERNAL_INFO>()..].as_ptr() as *const FILE_PROVIDER_EXTERNAL_INFO_V1)
+        };
+        debug!("{:#?}\n{:#?}", wof, info);
+        if wof.Version != WOF_CURRENT_VERSION || wof.Provider != WOF_PROVIDER_FILE || info.Version != FILE_PROVIDER_CURRENT_VERSION {
+            return invalid(&format!("Unsupported WOF Provider: {} version {}", { wof.Provider }, { wof.Version }));
+        }
+        match info.Algorithm {
+            0 => Ok(FILE_PROVIDER_COMPRESSION::XPRESS4K),
+            1 => Ok(FILE_PROVIDER_COMPRESSION::LZX),
+            2 => Ok(FILE_PROVIDER_COMPRESSION::XPRESS8K),
+            3 => Ok(FILE_PROVIDER_COMPRESSION::XPRESS16K),
+            _ => invalid(&format!("Unsupported WOF Compression: {}", { info.Algorithm })),
+        }
+    }
+
+    /// Get the uncompressed size of a chunk.
+    pub fn chunk_size(&self) -> u64 {
+        match self {
+            FILE_PROVIDER_COMPRESSION::XPRESS4K => 4096,
+            FILE_PROVIDER_COMPRESSION::LZX => 32768,
+            FILE_PROVIDER_COMPRESSION::XPRESS8K => 8192,
+            FILE_PROVIDER_COMPRESSION::XPRESS16K => 16384,
+        }
+    }
+}
+
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    eprintln!("ERROR: {}", message);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+
+/// A reader for a file compressed by the Windows Overlay Filter.
+///
+/// The compressed stream starts with the offsets of the end of every chunk but the last,
+/// relative to the end of the table.  Chunks which did not shrink are stored as is.
+pub struct WofReader<S> {
+    inner: S,
+    algorithm: FILE_PROVIDER_COMPRESSION,
+    chunks: Vec<u64>,           // offsets of the start of each chunk, and the end
+    size: u64,
+    pos: u64,
+    chunk: Option<(u64, Vec<u8>)>,  // the last chunk read
+}
+
+impl<S> WofReader<S>
+where S: Read + Seek {
+
+    /// Creates a new `WofReader` for the compressed stream of a file of `size` bytes.
+    pub fn new(mut inner: S, algorithm: FILE_PROVIDER_COMPRESSION, size: u64) -> io::Result<Self> {
+        let count = size.div_ceil(algorithm.chunk_size());
+        let entry_size = if size > u32::MAX as u64 { 8 } else { 4 };
+        let table_size = count.saturating_sub(1) * entry_size;
+        let stream_size = inner.seek(SeekFrom::End(0))?;
+        // every chunk holds at least a byte after the table
+        if table_size.checked_add(count).is_none_or(|n| n > stream_size) {
+            return invalid(&format!("WOF stream is too short for {} chunks: {} bytes", count, stream_size));
+        }
+
+        let mut table = vec![0; table_size as usize];
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(&mut table)?;
+        let mut chunks = vec![table_size];
+        for entry in table.chunks(entry_size as usize) {
+            let mut offset = [0; 8];
+            offset[..entry.len()].copy_from_slice(entry);
+            chunks.push(table_size + u64::from_le_bytes(offset));
+        }
+        chunks.push(stream_size);
+        if chunks.windows(2).any(|w| w[0] > w[1]) {
+            return invalid("WOF chunk table is not in order");
+        }
+
+        Ok(Self {
+            inner: inner,
+            algorithm: algorithm,
+            chunks: chunks,
+            size: size,
+            pos: 0,
+            chunk: None,
+        })
+    }
+
+    /// Read and decompress a chunk.
+    fn read_chunk(&mut self, index: u64) -> io::Result<Vec<u8>> {
+        let chunk_size = self.algorithm.chunk_size();
+        let size = cmp::min(chunk_size, self.size - index * chunk_size) as usize;
+        let start = self.chunks[index as usize];
+        let end = self.chunks[index as usize + 1];
+
+        let mut data = vec![0; (end - start) as usize];
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.read_exact(&mut data)?;
+        if data.len() == size {
+            return Ok(data);
+        }
+
+        let mut chunk = vec![0; size];
+        let count = match self.algorithm {
+            FILE_PROVIDER_COMPRESSION::LZX => lzx_decompress(&data, &mut chunk)?,
+            _ => xpress_decompress(&data, &mut chunk)?,
+        };
+        if count != size {
+            return invalid(&format!("WOF chunk {} decompressed to {} of {} bytes", index, count, size));
+        }
+        Ok(chunk)
+    }
+}
+
+impl<S> WofReader<S> {
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &S { &self.inner }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut S { &mut self.inner }
+
+    /// Unwraps this `WofReader`, returning the underlying reader.
+    pub fn into_inner(self) -> S { self.inner }
+
+    /// Get the uncompressed size in bytes.
+    pub fn size(&self) -> u64 { self.size }
+}
+
+impl<S> Read for WofReader<S>
+where S: Read + Seek {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk_size = self.algorithm.chunk_size();
+        let index = self.pos / chunk_size;
+        if self.chunk.as_ref().is_none_or(|(i, _)| *i != index) {
+            self.chunk = Some((index, self.read_chunk(index)?));
+        }
+        let chunk = &self.chunk.as_ref().unwrap().1;
+        let offset = (self.pos - index * chunk_size) as usize;
+        let count = cmp::min(buf.len(), chunk.len() - offset);
+        buf[..count].copy_from_slice(&chunk[offset..offset + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl<S> Seek for WofReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(n) => n,
+            SeekFrom::Current(n) => iadd(self.pos, n)?,
+            SeekFrom::End(n) => iadd(self.size, n)?,
+        };
+        Ok(self.pos)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn stored_chunks() {
+        // chunks which did not compress are stored as is
+        let data: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let mut stream = 4096u32.to_le_bytes().to_vec();
+        stream.extend_from_slice(&data);
+
+        let mut reader = WofReader::new(Cursor::new(stream), FILE_PROVIDER_COMPRESSION::XPRESS4K, 5000).unwrap();
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+
+        reader.seek(SeekFrom::Start(4090)).unwrap();
+        let mut buf = [0; 12];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[4090..4102]);
+    }
+
+    #[test]
+    fn invalid_chunk_table() {
+        // too short for its chunks, and offsets out of order
+        let stream = Cursor::new(vec![0; 16]);
+        assert!(WofReader::new(stream, FILE_PROVIDER_COMPRESSION::XPRESS4K, u64::MAX).is_err());
+        let mut stream = 100u32.to_le_bytes().to_vec();
+        stream.extend_from_slice(&50u32.to_le_bytes());
+        stream.resize(90, 0);
+        assert!(WofReader::new(Cursor::new(stream), FILE_PROVIDER_COMPRESSION::XPRESS4K, 12000).is_err());
+    }
+}
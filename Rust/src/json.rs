@@ -0,0 +1,220 @@
+use std::io;
+
+
+/// A parsed JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+
+    /// Parse a JSON document.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut parser = Parser { text: text.as_bytes(), pos: 0 };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.pos < parser.text.len() {
+            return parser.error();
+        }
+        Ok(value)
+    }
+
+    /// Look up a member of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Get an unsigned integer, also accepting decimal strings.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+
+    fn error<T>(&self) -> io::Result<T> {
+        eprintln!("ERROR: Invalid JSON at offset {}", self.pos);
+        Err(io::Error::from(io::ErrorKind::InvalidData))
+    }
+
+    fn whitespace(&mut self) {
+        while self.pos < self.text.len() && b" \t\r\n".contains(&self.text[self.pos]) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.whitespace();
+        self.text.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, token: &[u8]) -> io::Result<()> {
+        if self.text[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            Ok(())
+        } else {
+            self.error()
+        }
+    }
+
+    fn value(&mut self) -> io::Result<Value> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.expect(b"true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect(b"false").map(|_| Value::Bool(false)),
+            Some(b'n') => self.expect(b"null").map(|_| Value::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ => self.error(),
+        }
+    }
+
+    fn object(&mut self) -> io::Result<Value> {
+        let mut members = Vec::new();
+        self.pos += 1;
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return self.error();
+            }
+            let key = self.string()?;
+            if self.peek() != Some(b':') {
+                return self.error();
+            }
+            self.pos += 1;
+            members.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => { self.pos += 1; }
+                Some(b'}') => { self.pos += 1; return Ok(Value::Object(members)); }
+                _ => { return self.error(); }
+            }
+        }
+    }
+
+    fn array(&mut self) -> io::Result<Value> {
+        let mut values = Vec::new();
+        self.pos += 1;
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            match self.peek() {
+                Some(b',') => { self.pos += 1; }
+                Some(b']') => { self.pos += 1; return Ok(Value::Array(values)); }
+                _ => { return self.error(); }
+            }
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let mut units: Vec<u16> = Vec::new();
+        self.pos += 1;
+        loop {
+            let c = match self.text.get(self.pos) {
+                Some(c) => *c,
+                None => return self.error(),
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = match self.text.get(self.pos) {
+                        Some(c) => *c,
+                        None => return self.error(),
+                    };
+                    self.pos += 1;
+                    match escape {
+                        b'"' | b'\\' | b'/' => units.push(escape as u16),
+                        b'b' => units.push(0x08),
+                        b'f' => units.push(0x0c),
+                        b'n' => units.push(0x0a),
+                        b'r' => units.push(0x0d),
+                        b't' => units.push(0x09),
+                        b'u' => {
+                            let hex = self.text.get(self.pos..self.pos + 4)
+                                .and_then(|hex| core::str::from_utf8(hex).ok())
+                                .and_then(|hex| u16::from_str_radix(hex, 16).ok());
+                            match hex {
+                                Some(unit) => units.push(unit),
+                                None => return self.error(),
+                            }
+                            self.pos += 4;
+                        }
+                        _ => return self.error(),
+                    }
+                }
+                _ => {
+                    // re-encode raw utf-8 sequences as utf-16
+                    let start = self.pos - 1;
+                    let mut end = self.pos;
+                    while end < self.text.len() && self.text[end] & 0xc0 == 0x80 {
+                        end += 1;
+                    }
+                    match core::str::from_utf8(&self.text[start..end]) {
+                        Ok(s) => units.extend(s.encode_utf16()),
+                        Err(_) => return self.error(),
+                    }
+                    self.pos = end;
+                }
+            }
+        }
+        String::from_utf16(&units).or_else(|_| self.error())
+    }
+
+    fn number(&mut self) -> io::Result<Value> {
+        let start = self.pos;
+        while self.pos < self.text.len() && b"+-.eE0123456789".contains(&self.text[self.pos]) {
+            self.pos += 1;
+        }
+        core::str::from_utf8(&self.text[start..self.pos]).ok()
+            .and_then(|number| number.parse().ok())
+            .map(Value::Number)
+            .map_or_else(|| self.error(), Ok)
+    }
+}
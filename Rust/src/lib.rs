@@ -1,6 +1,11 @@
 #[macro_use]
 mod utils;
 
+mod json;
+pub mod plist;
+
 #[macro_use]
 pub mod device;
+pub mod compress;
+pub mod crypto;
 pub mod fs;
\ No newline at end of file
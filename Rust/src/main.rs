@@ -1,15 +1,18 @@
 use std::env;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
 use std::process;
 
-use warped_drive::device::Device;
-use warped_drive::fs::parse;
+use warped_drive::device::{Block, Device, Volume};
+use warped_drive::fs::{parse, BitLocker, BitLockerKey, CoreStorage, Luks};
 
 
 fn print_usage(program: &str, err: bool) {
     if err {
-        eprintln!("usage: {} [-h] device", program);
+        eprintln!("usage: {} [-h] [-p PASSWORD | -k KEY_FILE] device", program);
     } else {
-        println!("usage: {} [-h] device", program);
+        println!("usage: {} [-h] [-p PASSWORD | -k KEY_FILE] device", program);
     }
 }
 
@@ -19,44 +22,193 @@ fn print_help(program: &str) {
 filesystem reader
 
 positional arguments:
-  device      device or file to open
+  device                device or file to open
 
 optional arguments:
-  -h, --help  show this help message and exit");
+  -h, --help            show this help message and exit
+  -p PASSWORD, --password PASSWORD
+                        passphrase or recovery password for an encrypted volume
+  -k KEY_FILE, --key-file KEY_FILE
+                        key file or startup key for an encrypted volume");
+}
+
+#[cfg(unix)]
+fn set_echo(enable: bool) {
+    use nix::sys::termios;
+
+    if let Ok(mut attributes) = termios::tcgetattr(0) {
+        if enable {
+            attributes.local_flags.insert(termios::ECHO);
+        } else {
+            attributes.local_flags.remove(termios::ECHO);
+        }
+        let _ = termios::tcsetattr(0, termios::SetArg::TCSANOW, &attributes);
+    }
+}
+
+#[cfg(windows)]
+fn set_echo(enable: bool) {
+    use winapi::um::{consoleapi, processenv, winbase, wincon};
+
+    unsafe {
+        let handle = processenv::GetStdHandle(winbase::STD_INPUT_HANDLE);
+        let mut mode = 0;
+        if consoleapi::GetConsoleMode(handle, &mut mode) != 0 {
+            if enable {
+                mode |= wincon::ENABLE_ECHO_INPUT;
+            } else {
+                mode &= !wincon::ENABLE_ECHO_INPUT;
+            }
+            consoleapi::SetConsoleMode(handle, mode);
+        }
+    }
+}
+
+/// Get the key from the arguments, or prompt for a passphrase.
+fn read_key(password: &Option<String>, key_file: &Option<String>) -> io::Result<Vec<u8>> {
+    if let Some(password) = password {
+        return Ok(password.as_bytes().to_vec());
+    }
+    if let Some(key_file) = key_file {
+        return fs::read(key_file);
+    }
+
+    print!("Enter passphrase: ");
+    io::stdout().flush()?;
+
+    set_echo(false);
+    let mut line = String::new();
+    let result = io::stdin().lock().read_line(&mut line);
+    set_echo(true);
+    println!();
+
+    result?;
+    while line.ends_with('\n') || line.ends_with('\r') {
+        line.pop();
+    }
+    Ok(line.into_bytes())
+}
+
+/// Parse the file system of an unlocked volume.
+fn parse_unlocked<R>(prog: &str, path: &str, result: io::Result<Device<R>>)
+where R: Block + Read + Seek {
+    match result {
+        Ok(decrypted) => {
+            if parse(decrypted).is_err() {
+                process::exit(3);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}: error: failed to unlock {}: {}", prog, path, err);
+            process::exit(3);
+        }
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let prog = &args[0];
 
-    for arg in args.iter() {
-        if arg == "-h" || arg == "--help" {
-            print_help(prog);
-            process::exit(0);
+    let mut password = None;
+    let mut key_file = None;
+    let mut positional = Vec::new();
+    let mut unrecognized = Vec::new();
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print_help(prog);
+                process::exit(0);
+            }
+            "-p" | "--password" | "-k" | "--key-file" => {
+                let value = match iter.next() {
+                    Some(value) => value.clone(),
+                    None => {
+                        print_usage(prog, true);
+                        eprintln!("{}: error: argument {}: expected one argument", prog, arg);
+                        process::exit(1);
+                    }
+                };
+                if arg == "-p" || arg == "--password" {
+                    password = Some(value);
+                } else {
+                    key_file = Some(value);
+                }
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => { unrecognized.push(arg.clone()); }
+            _ if positional.is_empty() => { positional.push(arg.clone()); }
+            _ => { unrecognized.push(arg.clone()); }
         }
     }
-    if args.len() < 2 {
+
+    if password.is_some() && key_file.is_some() {
+        print_usage(prog, true);
+        eprintln!("{}: error: argument -k/--key-file: not allowed with argument -p/--password",
+                  prog);
+        process::exit(1);
+    }
+    if positional.is_empty() {
         print_usage(prog, true);
         eprintln!("{}: error: the following arguments are required: device",
                   prog);
         process::exit(1);
     }
-    if args.len() > 2 {
+    if !unrecognized.is_empty() {
         print_usage(prog, true);
         eprintln!("{}: error: unrecognized arguments: {}", prog,
-                  args[2..].join(" "));
+                  unrecognized.join(" "));
         process::exit(1);
     }
 
-    let path = &args[1];
+    let path = &positional[0];
     let result = Device::open(path);
     if let Err(err) = result {
         eprintln!("{}: error: failed to open {}: {}", prog, path, err);
         process::exit(2);
     }
-    let device = result.unwrap();
+    let mut device = result.unwrap();
+
+    let mut header: [u8; 512] = [0; 512];
+    if device.read_exact(&mut header).and_then(|_| device.seek(SeekFrom::Start(0))).is_err() {
+        process::exit(3);
+    }
+
+    if Luks::<Device<fs::File>>::is_supported(&header) {
+        let result = Luks::with_header(device, &header).and_then(|luks| {
+            let key = read_key(&password, &key_file)?;
+            luks.unlock(&key)
+        }).and_then(Device::new);
+        parse_unlocked(prog, path, result);
+        return;
+    }
+
+    if CoreStorage::<Device<fs::File>>::is_supported(&header) {
+        let result = CoreStorage::with_header(device, &header).and_then(|corestorage| {
+            let key = read_key(&password, &key_file)?;
+            corestorage.unlock(&key)
+        }).and_then(Device::new);
+        parse_unlocked(prog, path, result);
+        return;
+    }
+
+    if BitLocker::<Device<fs::File>>::is_supported(&header) {
+        let result = BitLocker::with_header(device, &header).and_then(|bitlocker| {
+            if password.is_none() && key_file.is_none() && bitlocker.has_clear_key() {
+                return bitlocker.unlock(&BitLockerKey::ClearKey);
+            }
+            let key = read_key(&password, &key_file)?;
+            if key_file.is_some() {
+                bitlocker.unlock(&BitLockerKey::StartupKey(&key))
+            } else {
+                bitlocker.unlock(&BitLockerKey::Password(&String::from_utf8_lossy(&key)))
+            }
+        }).and_then(Device::new);
+        parse_unlocked(prog, path, result);
+        return;
+    }
 
-    if let Err(_) = parse(device) {
+    if parse(device).is_err() {
         process::exit(3);
     }
 }
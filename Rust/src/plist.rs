@@ -0,0 +1,651 @@
+use std::io;
+
+use super::utils::base64_decode;
+
+
+/// Seconds between the Unix epoch and the Core Foundation epoch (2001-01-01).
+const EPOCH_OFFSET: f64 = 978_307_200.0;
+
+
+/// A parsed property list value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Array(Vec<Value>),
+    Dict(Vec<(String, Value)>),
+    Boolean(bool),
+    Data(Vec<u8>),
+    Date(f64),      // seconds since 2001-01-01 00:00:00 UTC
+    Integer(i64),
+    Real(f64),
+    String(String),
+    Uid(u64),
+}
+
+impl Value {
+
+    /// Parse an XML or binary property list.
+    pub fn parse(text: &[u8]) -> io::Result<Self> {
+        if text.starts_with(b"bplist00") {
+            return Binary::parse(text);
+        }
+
+        // property lists embedded in fixed size buffers are NUL terminated
+        let end = text.iter().position(|&c| c == 0).unwrap_or(text.len());
+        let mut parser = Parser { text: &text[..end], pos: 0, ids: Vec::new() };
+        let value = parser.value()?;
+        parser.skip();
+        if parser.pos < parser.text.len() {
+            return parser.error();
+        }
+        Ok(value)
+    }
+
+    /// Look up a member of a dictionary.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Dict(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Look up a nested value by a `/` separated path of keys and array indices.
+    pub fn lookup(&self, path: &str) -> Option<&Value> {
+        path.split('/').filter(|c| !c.is_empty()).try_fold(self, |value, component| match value {
+            Value::Array(values) => component.parse::<usize>().ok().and_then(|i| values.get(i)),
+            _ => value.get(component),
+        })
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Get an unsigned integer, reinterpreting negative values.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Integer(n) => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    /// Get a real number, converting integers.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Real(n) => Some(*n),
+            Value::Integer(n) => Some(*n as f64),
+            _ => None,
+        }
+    }
+
+    /// Get a date as seconds since the Unix epoch.
+    pub fn as_unix_time(&self) -> Option<f64> {
+        match self {
+            Value::Date(t) => Some(t + EPOCH_OFFSET),
+            _ => None,
+        }
+    }
+
+    pub fn as_uid(&self) -> Option<u64> {
+        match self {
+            Value::Uid(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_data(&self) -> Option<&[u8]> {
+        match self {
+            Value::Data(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Dict(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+
+/// The name, attributes and emptiness of an opening XML tag.
+type Tag = (String, Vec<(String, String)>, bool);
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    ids: Vec<(String, Value)>,      // values tagged with ID="" for IDREF=""
+}
+
+impl<'a> Parser<'a> {
+
+    fn error<T>(&self) -> io::Result<T> {
+        error(self.pos)
+    }
+
+    fn whitespace(&mut self) {
+        while self.pos < self.text.len() && b" \t\r\n".contains(&self.text[self.pos]) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip whitespace, comments and declarations.
+    fn skip(&mut self) {
+        loop {
+            self.whitespace();
+            let rest = &self.text[self.pos..];
+            let end: &[u8] = if rest.starts_with(b"<?") {
+                b"?>"
+            } else if rest.starts_with(b"<!--") {
+                b"-->"
+            } else if rest.starts_with(b"<!") {
+                b">"
+            } else {
+                return;
+            };
+            match rest.windows(end.len()).position(|w| w == end) {
+                Some(i) => self.pos += i + end.len(),
+                None => self.pos = self.text.len(),
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let start = self.pos;
+        while self.pos < self.text.len() && !b" \t\r\n/>=".contains(&self.text[self.pos]) {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.text[start..self.pos]).into_owned()
+    }
+
+    /// Parse an opening tag, returning its name, attributes and whether it is empty.
+    fn open_tag(&mut self) -> io::Result<Tag> {
+        self.skip();
+        if self.text.get(self.pos) != Some(&b'<') {
+            return self.error();
+        }
+        self.pos += 1;
+        let tag = self.name();
+
+        let mut attributes = Vec::new();
+        loop {
+            self.whitespace();
+            let rest = &self.text[self.pos..];
+            if rest.starts_with(b"/>") {
+                self.pos += 2;
+                return Ok((tag, attributes, true));
+            }
+            if rest.starts_with(b">") {
+                self.pos += 1;
+                return Ok((tag, attributes, false));
+            }
+
+            let name = self.name();
+            if name.is_empty() || self.text.get(self.pos) != Some(&b'=') {
+                return self.error();
+            }
+            self.pos += 1;
+            let quote = match self.text.get(self.pos) {
+                Some(&c) if c == b'"' || c == b'\'' => c,
+                _ => return self.error(),
+            };
+            let start = self.pos + 1;
+            let end = match self.text[start..].iter().position(|&c| c == quote) {
+                Some(i) => start + i,
+                None => return self.error(),
+            };
+            attributes.push((name, unescape(&self.text[start..end])));
+            self.pos = end + 1;
+        }
+    }
+
+    fn close_tag(&mut self, tag: &str) -> io::Result<()> {
+        self.skip();
+        if !self.text[self.pos..].starts_with(b"</") {
+            return self.error();
+        }
+        self.pos += 2;
+        if self.name() != tag {
+            return self.error();
+        }
+        self.whitespace();
+        if self.text.get(self.pos) != Some(&b'>') {
+            return self.error();
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn at_close_tag(&mut self) -> bool {
+        self.skip();
+        self.text[self.pos..].starts_with(b"</")
+    }
+
+    /// Get the character data of an element.
+    fn text(&mut self, tag: &str, empty: bool) -> io::Result<String> {
+        if empty {
+            return Ok(String::new());
+        }
+        let start = self.pos;
+        while self.pos < self.text.len() && self.text[self.pos] != b'<' {
+            self.pos += 1;
+        }
+        let text = unescape(&self.text[start..self.pos]);
+        self.close_tag(tag)?;
+        Ok(text)
+    }
+
+    fn value(&mut self) -> io::Result<Value> {
+        let (tag, attributes, empty) = self.open_tag()?;
+        let attribute = |name: &str| attributes.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone());
+
+        if let Some(id) = attribute("IDREF") {
+            if !empty {
+                self.close_tag(&tag)?;
+            }
+            return match self.ids.iter().find(|(k, _)| *k == id) {
+                Some((_, value)) => Ok(value.clone()),
+                None => self.error(),
+            };
+        }
+
+        let value = match tag.as_str() {
+            "plist" if !empty => {
+                let value = self.value()?;
+                self.close_tag(&tag)?;
+                value
+            }
+            "dict" => {
+                let mut members = Vec::new();
+                while !empty && !self.at_close_tag() {
+                    let (key_tag, _, key_empty) = self.open_tag()?;
+                    if key_tag != "key" {
+                        return self.error();
+                    }
+                    let key = self.text(&key_tag, key_empty)?;
+                    members.push((key, self.value()?));
+                }
+                if !empty {
+                    self.close_tag(&tag)?;
+                }
+                // keyed archives written as XML encode UIDs as single member dictionaries
+                match members.as_slice() {
+                    [(key, Value::Integer(n))] if key == "CF$UID" => Value::Uid(*n as u64),
+                    _ => Value::Dict(members),
+                }
+            }
+            "array" => {
+                let mut values = Vec::new();
+                while !empty && !self.at_close_tag() {
+                    values.push(self.value()?);
+                }
+                if !empty {
+                    self.close_tag(&tag)?;
+                }
+                Value::Array(values)
+            }
+            "true" | "false" => {
+                if !empty {
+                    self.close_tag(&tag)?;
+                }
+                Value::Boolean(tag == "true")
+            }
+            "string" => Value::String(self.text(&tag, empty)?),
+            "data" => match base64_decode(&self.text(&tag, empty)?) {
+                Ok(data) => Value::Data(data),
+                Err(_) => return self.error(),
+            },
+            "integer" => match parse_integer(self.text(&tag, empty)?.trim()) {
+                Some(n) => Value::Integer(n),
+                None => return self.error(),
+            },
+            "real" => match self.text(&tag, empty)?.trim().parse() {
+                Ok(n) => Value::Real(n),
+                Err(_) => return self.error(),
+            },
+            "date" => match parse_date(self.text(&tag, empty)?.trim()) {
+                Some(t) => Value::Date(t),
+                None => return self.error(),
+            },
+            _ => return self.error(),
+        };
+
+        if let Some(id) = attribute("ID") {
+            self.ids.push((id, value.clone()));
+        }
+        Ok(value)
+    }
+}
+
+
+/// Reader for `bplist00` binary property lists.
+struct Binary<'a> {
+    data: &'a [u8],
+    offsets: Vec<usize>,
+    ref_size: usize,
+    parents: Vec<u64>,      // objects being parsed, to reject reference cycles
+}
+
+impl<'a> Binary<'a> {
+
+    fn parse(data: &'a [u8]) -> io::Result<Value> {
+        if data.len() < 8 + 32 {
+            return error(data.len());
+        }
+
+        // trailer: unused[5], sort version, offset size, ref size, count, top, table offset
+        let trailer = &data[data.len() - 32..];
+        let offset_size = trailer[6] as usize;
+        let ref_size = trailer[7] as usize;
+        let count = read_uint(&trailer[8..16]);
+        let top = read_uint(&trailer[16..24]);
+        let table = read_uint(&trailer[24..32]);
+        debug!("bplist: {} objects, top {}, table at {:#x}", count, top, table);
+
+        let end = (data.len() - 32) as u64;
+        if offset_size == 0 || offset_size > 8 || ref_size == 0 || ref_size > 8 ||
+           table < 8 || count.checked_mul(offset_size as u64).and_then(|n| n.checked_add(table)).is_none_or(|n| n > end) {
+            return error(data.len() - 32);
+        }
+
+        let table = &data[table as usize..(table + count * offset_size as u64) as usize];
+        let offsets = table.chunks(offset_size).map(|c| read_uint(c) as usize).collect();
+        let mut reader = Binary { data: &data[..end as usize], offsets: offsets, ref_size: ref_size, parents: Vec::new() };
+        reader.object(top)
+    }
+
+    fn bytes(&self, offset: usize, size: usize) -> io::Result<&'a [u8]> {
+        match offset.checked_add(size) {
+            Some(end) if end <= self.data.len() => Ok(&self.data[offset..end]),
+            _ => error(offset),
+        }
+    }
+
+    /// Get the element count following a marker, which may be a separate integer object.
+    fn length(&self, offset: usize, info: u8) -> io::Result<(usize, usize)> {
+        if info != 0xF {
+            return Ok((info as usize, offset + 1));
+        }
+        let marker = self.bytes(offset + 1, 1)?[0];
+        if marker >> 4 != 0x1 || marker & 0xF > 3 {
+            return error(offset + 1);
+        }
+        let size = 1 << (marker & 0xF);
+        let length = read_uint(self.bytes(offset + 2, size)?);
+        Ok((length as usize, offset + 2 + size))
+    }
+
+    fn refs(&self, offset: usize, count: usize) -> io::Result<Vec<u64>> {
+        let size = match count.checked_mul(self.ref_size) {
+            Some(size) => size,
+            None => return error(offset),
+        };
+        Ok(self.bytes(offset, size)?.chunks(self.ref_size).map(read_uint).collect())
+    }
+
+    fn object(&mut self, index: u64) -> io::Result<Value> {
+        let offset = match self.offsets.get(index as usize) {
+            Some(&offset) if !self.parents.contains(&index) => offset,
+            _ => return error(self.data.len()),
+        };
+        self.parents.push(index);
+        let value = self.value(offset);
+        self.parents.pop();
+        value
+    }
+
+    fn value(&mut self, offset: usize) -> io::Result<Value> {
+        let marker = self.bytes(offset, 1)?[0];
+        let info = marker & 0xF;
+        let value = match marker >> 4 {
+            0x0 if info == 0x8 => Value::Boolean(false),
+            0x0 if info == 0x9 => Value::Boolean(true),
+            0x1 if info <= 4 => {
+                // 128-bit integers only occur for values that do not fit in 64 bits
+                let bytes = self.bytes(offset + 1, 1 << info)?;
+                Value::Integer(read_uint(&bytes[bytes.len().saturating_sub(8)..]) as i64)
+            }
+            0x2 if info == 2 => Value::Real(f32::from_bits(read_uint(self.bytes(offset + 1, 4)?) as u32) as f64),
+            0x2 if info == 3 => Value::Real(f64::from_bits(read_uint(self.bytes(offset + 1, 8)?))),
+            0x3 if info == 3 => Value::Date(f64::from_bits(read_uint(self.bytes(offset + 1, 8)?))),
+            0x4 => {
+                let (length, start) = self.length(offset, info)?;
+                Value::Data(self.bytes(start, length)?.to_vec())
+            }
+            0x5 | 0x7 => {
+                let (length, start) = self.length(offset, info)?;
+                Value::String(String::from_utf8_lossy(self.bytes(start, length)?).into_owned())
+            }
+            0x6 => {
+                let (length, start) = self.length(offset, info)?;
+                let size = match length.checked_mul(2) {
+                    Some(size) => size,
+                    None => return error(offset),
+                };
+                let units: Vec<u16> = self.bytes(start, size)?.chunks(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+                Value::String(String::from_utf16_lossy(&units))
+            }
+            0x8 => Value::Uid(read_uint(self.bytes(offset + 1, info as usize + 1)?)),
+            0xA..=0xC => {
+                // ordered sets and sets are read as arrays
+                let (length, start) = self.length(offset, info)?;
+                let mut values = Vec::with_capacity(length.min(self.offsets.len()));
+                for index in self.refs(start, length)? {
+                    values.push(self.object(index)?);
+                }
+                Value::Array(values)
+            }
+            0xD => {
+                let (length, start) = self.length(offset, info)?;
+                let keys = self.refs(start, length)?;
+                let objects = self.refs(start + length * self.ref_size, length)?;
+                let mut members = Vec::with_capacity(length.min(self.offsets.len()));
+                for (key, object) in keys.into_iter().zip(objects) {
+                    let key = match self.object(key)? {
+                        Value::String(key) => key,
+                        _ => return error(offset),
+                    };
+                    members.push((key, self.object(object)?));
+                }
+                Value::Dict(members)
+            }
+            _ => return error(offset),
+        };
+        Ok(value)
+    }
+}
+
+
+fn error<T>(offset: usize) -> io::Result<T> {
+    eprintln!("ERROR: Invalid plist at offset {}", offset);
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Read a big endian unsigned integer of up to eight bytes.
+fn read_uint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &b| (n << 8) | b as u64)
+}
+
+fn parse_integer(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let magnitude = if digits.starts_with("0x") || digits.starts_with("0X") {
+        u64::from_str_radix(&digits[2..], 16).ok()?
+    } else {
+        digits.parse::<u64>().ok()?
+    };
+    Some(if negative { (magnitude as i64).wrapping_neg() } else { magnitude as i64 })
+}
+
+/// Parse an ISO 8601 `YYYY-MM-DDTHH:MM:SSZ` date into seconds since 2001.
+fn parse_date(text: &str) -> Option<f64> {
+    let text = text.strip_suffix('Z')?;
+    let (date, time) = text.split_at(text.find('T')?);
+    let date: Vec<i64> = date.split('-').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+    let time: Vec<i64> = time[1..].split(':').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+    let (y, m, d) = match date.as_slice() {
+        [y, m, d] if (1..=12).contains(m) && (1..=31).contains(d) => (*y, *m, *d),
+        _ => return None,
+    };
+    let (h, min, s) = match time.as_slice() {
+        [h, min, s] if (0..24).contains(h) && (0..60).contains(min) && (0..=60).contains(s) => (*h, *min, *s),
+        _ => return None,
+    };
+
+    // days from the civil calendar to 1970-01-01
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let days = era * 146_097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719_468;
+    Some((days * 86400 + h * 3600 + min * 60 + s) as f64 - EPOCH_OFFSET)
+}
+
+/// Replace the predefined and numeric character references.
+fn unescape(raw: &[u8]) -> String {
+    let text = String::from_utf8_lossy(raw);
+    let mut result = String::with_capacity(text.len());
+    let mut rest: &str = &text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(core::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(core::char::from_u32),
+            _ => None,
+        };
+        match c {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::utils::hex_decode;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Name</key>
+    <string>disk &amp; &#x263A;</string>
+    <key>Size</key>
+    <integer>0x400</integer>
+    <key>Ratio</key>
+    <real>0.5</real>
+    <key>Key</key>
+    <data>
+        aGVs
+        bG8=
+    </data>
+    <key>Created</key>
+    <date>2020-01-01T00:00:00Z</date>
+    <key>Items</key>
+    <array>
+        <integer>-3</integer>
+        <string ID="1">two</string>
+        <dict><key>CF$UID</key><integer>7</integer></dict>
+        <string IDREF="1"/>
+    </array>
+    <key>Sealed</key>
+    <true/>
+</dict>
+</plist>
+"#;
+
+    // written by Python's plistlib with the same members as `XML`, less the IDREF
+    const BINARY: &str = "62706c6973743030d70102030405060708090a0b0c0d11544e616d655453697a6555526174696f534b65795743726561746564
+                          554974656d73565365616c6564546469736b110400233fe00000000000004568656c6c6f3341c1de0c40000000a30e0f1013
+                          fffffffffffffffd5374776f80070908171c21272b3339404548515760646d717300000000000001010000000000000012
+                          00000000000000000000000000000074";
+
+    #[test]
+    fn xml() {
+        let value = Value::parse(XML.as_bytes()).unwrap();
+        assert_eq!(value.lookup("Name").and_then(Value::as_str), Some("disk & \u{263a}"));
+        assert_eq!(value.lookup("Size").and_then(Value::as_i64), Some(1024));
+        assert_eq!(value.lookup("Ratio").and_then(Value::as_f64), Some(0.5));
+        assert_eq!(value.lookup("Key").and_then(Value::as_data), Some(&b"hello"[..]));
+        assert_eq!(value.lookup("Created").and_then(Value::as_unix_time), Some(1_577_836_800.0));
+        assert_eq!(value.lookup("Items/0").and_then(Value::as_i64), Some(-3));
+        assert_eq!(value.lookup("Items/2").and_then(Value::as_uid), Some(7));
+        assert_eq!(value.lookup("Items/3").and_then(Value::as_str), Some("two"));
+        assert_eq!(value.lookup("/Sealed/").and_then(Value::as_bool), Some(true));
+        assert_eq!(value.lookup("Items/4"), None);
+        assert_eq!(value.lookup("Missing/0"), None);
+    }
+
+    #[test]
+    fn binary() {
+        let value = Value::parse(&hex_decode(BINARY)).unwrap();
+        assert_eq!(value, Value::Dict(vec![
+            (String::from("Name"), Value::String(String::from("disk"))),
+            (String::from("Size"), Value::Integer(1024)),
+            (String::from("Ratio"), Value::Real(0.5)),
+            (String::from("Key"), Value::Data(b"hello".to_vec())),
+            (String::from("Created"), Value::Date(599_529_600.0)),
+            (String::from("Items"), Value::Array(vec![
+                Value::Integer(-3),
+                Value::String(String::from("two")),
+                Value::Uid(7),
+            ])),
+            (String::from("Sealed"), Value::Boolean(true)),
+        ]));
+        assert_eq!(value.lookup("Items/2").and_then(Value::as_uid), Some(7));
+    }
+
+    #[test]
+    fn truncated() {
+        let binary = hex_decode(BINARY);
+        assert!(Value::parse(&binary[..binary.len() - 8]).is_err());
+        assert!(Value::parse(&XML.as_bytes()[..XML.len() / 2]).is_err());
+    }
+}
\ No newline at end of file
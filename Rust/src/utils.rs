@@ -15,6 +15,39 @@ pub fn iadd(lvalue: u64, rvalue: i64) -> io::Result<u64> {
 }
 
 
+pub fn base64_decode(text: &str) -> io::Result<Vec<u8>> {
+    let mut result = Vec::with_capacity(text.len() * 3 / 4);
+    let mut value: u32 = 0;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let digit = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => return Err(io::Error::from(io::ErrorKind::InvalidData)),
+        };
+        value = (value << 6) | digit as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((value >> bits) as u8);
+        }
+    }
+    Ok(result)
+}
+
+
+#[cfg(test)]
+pub fn hex_decode(text: &str) -> Vec<u8> {
+    let digits: Vec<u8> = text.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    digits.chunks(2).map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap()).collect()
+}
+
+
 pub fn xxd(buffer: &[u8], address: u64) {
     let size = buffer.len();
     for i in (0..size).step_by(16) {
@@ -51,9 +84,9 @@ pub fn xxd(buffer: &[u8], address: u64) {
             j += 1;
         }
 
-        eprintln!("");
+        eprintln!();
     }
-    eprintln!("");
+    eprintln!();
 }
 
 